@@ -0,0 +1,538 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::backend::{ensure_writable, open_backend};
+use super::manager::suppress_next_archive_event;
+use super::order::sorted_image_files;
+use super::reader::read_archive;
+use super::types::{ErrorResponse, ReadArchiveError, ToErrorResponse};
+use super::writer::{
+    PageSettings, described_page_settings, populate_filenames_from_archive,
+    save_page_settings_impl, update_zip_with_comicinfo,
+};
+use crate::comicinfo::{ComicPageInfo, ComicPageType, Pages};
+
+/// Bytes read from the start of each image. Enough for the headers of every
+/// supported format; images whose metadata pushes the dimensions further back
+/// are retried with the whole entry.
+const IMAGE_HEADER_LIMIT: usize = 64 * 1024;
+
+/// Dimensions and byte size of an image, as stored on a `<Page>`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ImageInfo {
+    pub width: i32,
+    pub height: i32,
+    pub size: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UnreadableImage {
+    pub file_name: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PageDimensionsReport {
+    pub updated_pages: usize,
+    pub added_pages: usize,
+    pub unreadable: Vec<UnreadableImage>,
+}
+
+/// Thresholds that decide when a page is a double-page spread.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct SpreadDetectionOptions {
+    /// Minimum width to height ratio of a spread.
+    pub min_aspect_ratio: f64,
+    /// Minimum width of a spread relative to the median page width of the
+    /// archive, so that comics drawn in landscape are not flagged throughout.
+    pub min_relative_width: f64,
+}
+
+impl Default for SpreadDetectionOptions {
+    fn default() -> Self {
+        Self {
+            min_aspect_ratio: 1.2,
+            min_relative_width: 1.5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpreadSuggestion {
+    pub file_name: String,
+    pub image: i32,
+    pub width: i32,
+    pub height: i32,
+    pub aspect_ratio: f64,
+    /// Whether the page is already marked `DoublePage="true"`.
+    pub already_marked: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpreadDetectionReport {
+    pub spreads: Vec<SpreadSuggestion>,
+    pub unreadable: Vec<UnreadableImage>,
+    pub saved: bool,
+}
+
+/// Reads the dimensions of each image from its header, without decoding it.
+///
+/// Entries are read in parallel where the archive format allows it. Images
+/// that cannot be read or identified are returned separately.
+pub fn read_image_info(
+    path: &str,
+    file_names: &[String],
+) -> Result<(HashMap<String, ImageInfo>, Vec<UnreadableImage>), ReadArchiveError> {
+    let backend = open_backend(path)?;
+    let images = Mutex::new(HashMap::new());
+    let unreadable = Mutex::new(Vec::new());
+
+    let on_error = |file_name: String, message: String| {
+        unreadable
+            .lock()
+            .unwrap()
+            .push(UnreadableImage { file_name, message });
+    };
+
+    let on_data = |file_name: String, header: Vec<u8>, size: u64| {
+        let dimensions = match imagesize::blob_size(&header) {
+            Err(_) if (header.len() as u64) < size => backend
+                .read_file(path, &file_name)
+                .map_err(|e| e.to_string())
+                .and_then(|data| imagesize::blob_size(&data).map_err(|e| e.to_string())),
+            result => result.map_err(|e| e.to_string()),
+        };
+
+        match dimensions {
+            Ok(dimensions) => {
+                let info = ImageInfo {
+                    width: dimensions.width as i32,
+                    height: dimensions.height as i32,
+                    size: size as i64,
+                };
+                images.lock().unwrap().insert(file_name, info);
+            }
+            Err(message) => on_error(file_name, message),
+        }
+    };
+
+    backend.read_file_headers(path, file_names, IMAGE_HEADER_LIMIT, &on_data, &on_error);
+
+    let mut unreadable = unreadable.into_inner().unwrap();
+    unreadable.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+    Ok((images.into_inner().unwrap(), unreadable))
+}
+
+/// Fills in `ImageWidth`, `ImageHeight` and `ImageSize` on every `<Page>` from
+/// the archive's images.
+///
+/// With `include_all_images`, images without a `<Page>` get one so that every
+/// page carries its dimensions.
+pub fn populate_page_dimensions_impl(
+    path: String,
+    include_all_images: bool,
+) -> Result<PageDimensionsReport, ErrorResponse> {
+    ensure_writable(&path).map_err(|e| e.to_error_response())?;
+    let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
+
+    let sorted = sorted_image_files(&archive);
+    let (images, unreadable) =
+        read_image_info(&path, &sorted).map_err(|e| e.to_error_response())?;
+
+    let mut comic_info = archive.comic_info.clone().unwrap_or_default();
+    let pages = comic_info
+        .pages
+        .get_or_insert_with(|| Pages { page: Vec::new() });
+
+    let mut added_pages = 0;
+
+    if include_all_images {
+        let described: HashSet<i32> = pages.page.iter().map(|p| p.image).collect();
+
+        for image in (0..sorted.len() as i32).filter(|i| !described.contains(i)) {
+            pages.page.push(ComicPageInfo::from_page_settings(
+                ComicPageType::Other,
+                false,
+                String::new(),
+                image,
+            ));
+            added_pages += 1;
+        }
+
+        pages.page.sort_by_key(|p| p.image);
+    }
+
+    let mut updated_pages = 0;
+
+    for page in pages.page.iter_mut() {
+        let info = usize::try_from(page.image)
+            .ok()
+            .and_then(|index| sorted.get(index))
+            .and_then(|file_name| images.get(file_name));
+
+        let Some(info) = info else {
+            continue;
+        };
+
+        page.image_width = info.width;
+        page.image_height = info.height;
+        page.image_size = info.size;
+        updated_pages += 1;
+    }
+
+    let report = PageDimensionsReport {
+        updated_pages,
+        added_pages,
+        unreadable,
+    };
+
+    if updated_pages == 0 && added_pages == 0 {
+        return Ok(report);
+    }
+
+    populate_filenames_from_archive(&mut comic_info, &archive);
+
+    let xml_content = comic_info.to_xml().map_err(|e| e.to_error_response())?;
+    suppress_next_archive_event(&path);
+    update_zip_with_comicinfo(&path, &xml_content).map_err(|e| e.to_error_response())?;
+
+    Ok(report)
+}
+
+fn median_width(images: &HashMap<String, ImageInfo>) -> Option<f64> {
+    let mut widths: Vec<i32> = images.values().map(|i| i.width).collect();
+
+    if widths.is_empty() {
+        return None;
+    }
+
+    widths.sort_unstable();
+    let middle = widths.len() / 2;
+
+    if widths.len() % 2 == 0 {
+        Some((widths[middle - 1] as f64 + widths[middle] as f64) / 2.0)
+    } else {
+        Some(widths[middle] as f64)
+    }
+}
+
+/// Flags the images that are double-page spreads.
+pub(crate) fn find_spreads(
+    sorted: &[String],
+    images: &HashMap<String, ImageInfo>,
+    options: &SpreadDetectionOptions,
+) -> Vec<(usize, ImageInfo)> {
+    let median = median_width(images).unwrap_or(0.0);
+
+    sorted
+        .iter()
+        .enumerate()
+        .filter_map(|(index, file_name)| images.get(file_name).map(|info| (index, *info)))
+        .filter(|(_, info)| info.width > 0 && info.height > 0)
+        .filter(|(_, info)| {
+            info.width as f64 / info.height as f64 >= options.min_aspect_ratio
+                && info.width as f64 >= median * options.min_relative_width
+        })
+        .collect()
+}
+
+/// Keeps the settings of every described page and marks the spreads as
+/// `DoublePage`, so saving them changes nothing else.
+fn spread_page_settings(
+    sorted: &[String],
+    pages: &[ComicPageInfo],
+    spreads: &[SpreadSuggestion],
+) -> HashMap<String, PageSettings> {
+    let mut settings = described_page_settings(sorted, pages);
+
+    for spread in spreads {
+        settings
+            .entry(spread.file_name.clone())
+            .or_insert_with(|| PageSettings {
+                page_type: ComicPageType::Other,
+                double_page: false,
+                bookmark: String::new(),
+                image: spread.image,
+            })
+            .double_page = true;
+    }
+
+    settings
+}
+
+/// Finds pages that are double-page spreads from the aspect ratio of their
+/// images.
+///
+/// Unless `preview` is set, the spreads are marked `DoublePage="true"`.
+/// Pages are never unmarked.
+pub fn detect_double_page_spreads_impl(
+    path: String,
+    options: SpreadDetectionOptions,
+    preview: bool,
+) -> Result<SpreadDetectionReport, ErrorResponse> {
+    let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
+
+    let sorted = sorted_image_files(&archive);
+    let (images, unreadable) =
+        read_image_info(&path, &sorted).map_err(|e| e.to_error_response())?;
+
+    let pages = archive
+        .comic_info
+        .as_ref()
+        .and_then(|ci| ci.pages.as_ref())
+        .map(|p| p.page.clone())
+        .unwrap_or_default();
+    let marked: HashSet<i32> = pages
+        .iter()
+        .filter(|p| p.double_page)
+        .map(|p| p.image)
+        .collect();
+
+    let spreads: Vec<SpreadSuggestion> = find_spreads(&sorted, &images, &options)
+        .into_iter()
+        .map(|(index, info)| SpreadSuggestion {
+            file_name: sorted[index].clone(),
+            image: index as i32,
+            width: info.width,
+            height: info.height,
+            aspect_ratio: info.width as f64 / info.height as f64,
+            already_marked: marked.contains(&(index as i32)),
+        })
+        .collect();
+
+    let saved = !preview && spreads.iter().any(|s| !s.already_marked);
+
+    if saved {
+        save_page_settings_impl(path, spread_page_settings(&sorted, &pages, &spreads))?;
+    }
+
+    Ok(SpreadDetectionReport {
+        spreads,
+        unreadable,
+        saved,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::tests::write_cbz;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[8, 6, 0, 0, 0, 0, 0, 0, 0]);
+        data
+    }
+
+    /// A JPEG whose frame header sits behind more metadata than
+    /// `IMAGE_HEADER_LIMIT`.
+    fn jpeg_with_large_metadata(width: u16, height: u16) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+
+        for _ in 0..2 {
+            data.extend_from_slice(&[0xFF, 0xE1, 0xFF, 0xFF]);
+            data.extend(std::iter::repeat_n(0, 0xFFFF - 2));
+        }
+
+        data.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x11, 0x08]);
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&[0x03, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+        data.extend_from_slice(&[0xFF, 0xD9]);
+        data
+    }
+
+    #[test]
+    fn test_read_image_info_from_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        let jpeg = jpeg_with_large_metadata(800, 600);
+        write_cbz(
+            &path,
+            &[
+                ("001.png", &png(100, 150)),
+                ("002.jpg", &jpeg),
+                ("003.jpg", b"not an image"),
+            ],
+        );
+        let names = ["001.png", "002.jpg", "003.jpg", "004.jpg"].map(String::from);
+
+        let (images, unreadable) = read_image_info(path.to_str().unwrap(), &names).unwrap();
+
+        assert_eq!(
+            images["001.png"],
+            ImageInfo {
+                width: 100,
+                height: 150,
+                size: png(100, 150).len() as i64
+            }
+        );
+        assert_eq!(
+            images["002.jpg"],
+            ImageInfo {
+                width: 800,
+                height: 600,
+                size: jpeg.len() as i64
+            }
+        );
+        let unreadable_names: Vec<&str> = unreadable.iter().map(|u| u.file_name.as_str()).collect();
+        assert_eq!(unreadable_names, vec!["003.jpg", "004.jpg"]);
+    }
+
+    #[test]
+    fn test_read_image_info_from_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("001.png"), png(640, 480)).unwrap();
+
+        let (images, unreadable) = read_image_info(
+            dir.path().to_str().unwrap(),
+            &["001.png".to_string(), "../001.png".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(images["001.png"].width, 640);
+        assert_eq!(images["001.png"].height, 480);
+        assert_eq!(unreadable.len(), 1);
+        assert_eq!(unreadable[0].file_name, "../001.png");
+    }
+
+    #[test]
+    fn test_populate_page_dimensions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        write_cbz(
+            &path,
+            &[
+                ("001.png", &png(100, 150)),
+                ("002.png", &png(300, 150)),
+                (
+                    "ComicInfo.xml",
+                    br#"<ComicInfo><Pages><Page Image="0" Type="FrontCover" /></Pages></ComicInfo>"#,
+                ),
+            ],
+        );
+        let path = path.to_str().unwrap().to_string();
+
+        let report = populate_page_dimensions_impl(path.clone(), false).unwrap();
+        assert_eq!(report.updated_pages, 1);
+        assert_eq!(report.added_pages, 0);
+
+        let pages = read_archive(&path)
+            .unwrap()
+            .comic_info
+            .unwrap()
+            .pages
+            .unwrap();
+        assert_eq!(pages.page.len(), 1);
+        assert_eq!(pages.page[0].image_width, 100);
+        assert_eq!(pages.page[0].image_height, 150);
+        assert_eq!(pages.page[0].image_size, png(100, 150).len() as i64);
+        assert_eq!(pages.page[0].type_, Some(ComicPageType::FrontCover));
+
+        let report = populate_page_dimensions_impl(path.clone(), true).unwrap();
+        assert_eq!(report.updated_pages, 2);
+        assert_eq!(report.added_pages, 1);
+
+        let pages = read_archive(&path)
+            .unwrap()
+            .comic_info
+            .unwrap()
+            .pages
+            .unwrap();
+        assert_eq!(pages.page.len(), 2);
+        assert_eq!(pages.page[1].image, 1);
+        assert_eq!(pages.page[1].image_width, 300);
+        assert_eq!(pages.page[1].type_, None);
+    }
+
+    #[test]
+    fn test_find_spreads_relative_to_median_width() {
+        let sorted = ["001.png", "002.png", "003.png", "004.png"].map(String::from);
+        let info = |width, height| ImageInfo {
+            width,
+            height,
+            size: 0,
+        };
+        let images = HashMap::from([
+            (sorted[0].clone(), info(100, 150)),
+            (sorted[1].clone(), info(200, 150)),
+            (sorted[2].clone(), info(100, 150)),
+            (sorted[3].clone(), info(120, 90)),
+        ]);
+
+        let spreads = find_spreads(&sorted, &images, &SpreadDetectionOptions::default());
+        let indices: Vec<usize> = spreads.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![1]);
+
+        let options = SpreadDetectionOptions {
+            min_aspect_ratio: 1.2,
+            min_relative_width: 1.0,
+        };
+        let spreads = find_spreads(&sorted, &images, &options);
+        let indices: Vec<usize> = spreads.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_detect_double_page_spreads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        write_cbz(
+            &path,
+            &[
+                ("001.png", &png(100, 150)),
+                ("002.png", &png(200, 150)),
+                ("003.png", &png(100, 150)),
+                (
+                    "ComicInfo.xml",
+                    br#"<ComicInfo><Pages><Page Image="0" Type="FrontCover" /><Page Image="2" Bookmark="End" /></Pages></ComicInfo>"#,
+                ),
+            ],
+        );
+        let path = path.to_str().unwrap().to_string();
+
+        let report =
+            detect_double_page_spreads_impl(path.clone(), SpreadDetectionOptions::default(), true)
+                .unwrap();
+        assert!(!report.saved);
+        assert_eq!(report.spreads.len(), 1);
+        assert_eq!(report.spreads[0].file_name, "002.png");
+        assert!(!report.spreads[0].already_marked);
+
+        let pages = read_archive(&path)
+            .unwrap()
+            .comic_info
+            .unwrap()
+            .pages
+            .unwrap();
+        assert!(pages.page.iter().all(|p| !p.double_page));
+
+        let report =
+            detect_double_page_spreads_impl(path.clone(), SpreadDetectionOptions::default(), false)
+                .unwrap();
+        assert!(report.saved);
+
+        let pages = read_archive(&path)
+            .unwrap()
+            .comic_info
+            .unwrap()
+            .pages
+            .unwrap();
+        assert_eq!(pages.page.len(), 3);
+        assert_eq!(pages.page[0].type_, Some(ComicPageType::FrontCover));
+        assert!(pages.page[1].double_page);
+        assert_eq!(pages.page[2].bookmark, "End");
+
+        let report =
+            detect_double_page_spreads_impl(path, SpreadDetectionOptions::default(), false)
+                .unwrap();
+        assert!(!report.saved);
+        assert!(report.spreads[0].already_marked);
+    }
+}
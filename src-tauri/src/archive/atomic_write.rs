@@ -0,0 +1,338 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+#[cfg(target_os = "macos")]
+use std::os::macos::fs::FileTimesExt;
+#[cfg(windows)]
+use std::os::windows::fs::FileTimesExt;
+
+use log::debug;
+use tempfile::NamedTempFile;
+
+/// Suffix of the temp files that writes go to before they replace their
+/// target.
+const TEMP_SUFFIX: &str = ".kikou-tmp";
+
+/// Temp files left alone for this long are no longer being written, so they
+/// were left behind by a save that never finished.
+const STALE_AFTER: Duration = Duration::from_secs(60);
+
+static PENDING_WRITES_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// A file that replaces `target` only once it is completely written and on
+/// disk.
+///
+/// The data goes to a uniquely named temp file next to the target, so
+/// concurrent saves never share a temp file and the final rename stays on one
+/// filesystem. Dropping it without committing removes the temp file.
+pub struct AtomicFile {
+    temp: NamedTempFile,
+    target: PathBuf,
+    _pending: Option<PendingWrite>,
+}
+
+impl AtomicFile {
+    pub fn create(target: impl AsRef<Path>) -> io::Result<Self> {
+        let target = target.as_ref().to_path_buf();
+        let file_name = target
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let temp = tempfile::Builder::new()
+            .prefix(&format!(".{}.", file_name))
+            .suffix(TEMP_SUFFIX)
+            .tempfile_in(parent_dir(&target))?;
+
+        let pending = PENDING_WRITES_DIR.get().and_then(|dir| {
+            PendingWrite::record(dir, temp.path())
+                .inspect_err(|e| debug!("Failed to record pending write: {}", e))
+                .ok()
+        });
+
+        Ok(Self {
+            temp,
+            target,
+            _pending: pending,
+        })
+    }
+
+    pub fn as_file_mut(&mut self) -> &mut fs::File {
+        self.temp.as_file_mut()
+    }
+
+    /// Syncs the written data and moves it over the target.
+    ///
+    /// The new file keeps the permissions, access time and, where the platform
+    /// records one, creation time of the file it replaces. Its modification
+    /// time is left to the write, so the watcher, caches and fingerprints all
+    /// see the change.
+    pub fn commit(self) -> io::Result<()> {
+        let AtomicFile {
+            temp,
+            target,
+            _pending,
+        } = self;
+
+        let file = temp.as_file();
+
+        if let Ok(original) = fs::metadata(&target) {
+            file.set_permissions(original.permissions())?;
+            file.set_times(preserved_times(&original))?;
+        }
+
+        file.sync_all()?;
+        temp.persist(&target).map_err(|e| e.error)?;
+
+        sync_dir(parent_dir(&target))
+    }
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+fn preserved_times(original: &fs::Metadata) -> fs::FileTimes {
+    let mut times = fs::FileTimes::new();
+
+    if let Ok(accessed) = original.accessed() {
+        times = times.set_accessed(accessed);
+    }
+
+    #[cfg(any(windows, target_os = "macos"))]
+    if let Ok(created) = original.created() {
+        times = times.set_created(created);
+    }
+
+    times
+}
+
+/// Makes the rename itself durable. Windows offers no way to sync a
+/// directory, and commits renames to the journal on its own.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// An entry in the pending writes directory naming the temp file of a save
+/// that is running. A crash leaves the entry behind, so the temp file can be
+/// found again on the next start.
+struct PendingWrite {
+    entry: PathBuf,
+}
+
+impl PendingWrite {
+    fn record(dir: &Path, temp_path: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let mut entry = NamedTempFile::new_in(dir)?;
+        entry.write_all(temp_path.to_string_lossy().as_bytes())?;
+        let (_, entry) = entry.keep().map_err(|e| e.error)?;
+
+        Ok(Self { entry })
+    }
+}
+
+impl Drop for PendingWrite {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.entry) {
+            debug!("Failed to remove pending write {:?}: {}", self.entry, e);
+        }
+    }
+}
+
+/// Removes the temp files of saves that were interrupted, returning how many
+/// were removed. Temp files still being written are left for a later start.
+pub fn clean_up_interrupted_writes(dir: &Path) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+
+    let mut removed = 0;
+
+    for entry in entries.flatten() {
+        let Ok(temp_path) = fs::read_to_string(entry.path()).map(PathBuf::from) else {
+            continue;
+        };
+
+        // Never trust the entry to name anything but one of our temp files.
+        if !temp_path.to_string_lossy().ends_with(TEMP_SUFFIX) {
+            let _ = fs::remove_file(entry.path());
+            continue;
+        }
+
+        match fs::metadata(&temp_path) {
+            Ok(metadata) if !is_stale(&metadata) => continue,
+            Ok(_) => match fs::remove_file(&temp_path) {
+                Ok(()) => {
+                    debug!("Removed temp file of interrupted save {:?}", temp_path);
+                    removed += 1;
+                }
+                Err(e) => {
+                    debug!("Failed to remove {:?}: {}", temp_path, e);
+                    continue;
+                }
+            },
+            Err(_) => {}
+        }
+
+        let _ = fs::remove_file(entry.path());
+    }
+
+    removed
+}
+
+fn is_stale(metadata: &fs::Metadata) -> bool {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age >= STALE_AFTER)
+}
+
+/// Sets up the directory that pending writes are recorded in and cleans up
+/// after saves interrupted in an earlier run. Called once at startup.
+pub fn init_pending_writes(dir: PathBuf) {
+    let removed = clean_up_interrupted_writes(&dir);
+
+    if removed > 0 {
+        debug!("Cleaned up {} interrupted saves", removed);
+    }
+
+    if PENDING_WRITES_DIR.set(dir).is_err() {
+        debug!("Pending writes directory was already initialised");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_files(dir: &Path) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .unwrap()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.to_string_lossy().ends_with(TEMP_SUFFIX))
+            .collect()
+    }
+
+    fn age(path: &Path, by: Duration) {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - by)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_commit_replaces_the_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("comic.cbz");
+        fs::write(&target, b"old").unwrap();
+
+        let accessed = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&target)
+            .unwrap()
+            .set_times(fs::FileTimes::new().set_accessed(accessed))
+            .unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&target, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        let mut file = AtomicFile::create(&target).unwrap();
+        file.as_file_mut().write_all(b"new").unwrap();
+        assert_eq!(temp_files(dir.path()).len(), 1);
+
+        file.commit().unwrap();
+
+        // Checked before reading the file, which may bump its access time.
+        let metadata = fs::metadata(&target).unwrap();
+        assert_eq!(metadata.accessed().unwrap(), accessed);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        }
+
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert!(temp_files(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_writes_use_their_own_temp_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("comic.cbz");
+
+        let mut first = AtomicFile::create(&target).unwrap();
+        let mut second = AtomicFile::create(&target).unwrap();
+        first.as_file_mut().write_all(b"first").unwrap();
+        second.as_file_mut().write_all(b"second").unwrap();
+        assert_eq!(temp_files(dir.path()).len(), 2);
+
+        first.commit().unwrap();
+        second.commit().unwrap();
+
+        assert_eq!(fs::read(&target).unwrap(), b"second");
+        assert!(temp_files(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_dropping_an_uncommitted_write_keeps_the_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("comic.cbz");
+        fs::write(&target, b"old").unwrap();
+
+        let mut file = AtomicFile::create(&target).unwrap();
+        file.as_file_mut().write_all(b"partial").unwrap();
+        drop(file);
+
+        assert_eq!(fs::read(&target).unwrap(), b"old");
+        assert!(temp_files(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_clean_up_interrupted_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let pending = dir.path().join("pending-writes");
+
+        let interrupted = dir.path().join(format!(".a.cbz.1{}", TEMP_SUFFIX));
+        let running = dir.path().join(format!(".b.cbz.2{}", TEMP_SUFFIX));
+        let unrelated = dir.path().join("c.cbz");
+
+        for path in [&interrupted, &running, &unrelated] {
+            fs::write(path, b"data").unwrap();
+            std::mem::forget(PendingWrite::record(&pending, path).unwrap());
+        }
+        age(&interrupted, STALE_AFTER * 2);
+
+        assert_eq!(clean_up_interrupted_writes(&pending), 1);
+        assert!(!interrupted.exists());
+        assert!(running.exists());
+        assert!(unrelated.exists());
+        assert_eq!(fs::read_dir(&pending).unwrap().count(), 1);
+
+        age(&running, STALE_AFTER * 2);
+        assert_eq!(clean_up_interrupted_writes(&pending), 1);
+        assert!(!running.exists());
+        assert_eq!(fs::read_dir(&pending).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,189 @@
+use super::{ArchiveBackend, read_files_in_one_pass};
+use crate::archive::types::{ArchiveFile, ArchiveFormat, ReadArchiveError};
+use sevenz_rust::{Password, SevenZReader};
+use std::collections::HashSet;
+
+/// Read-only access to 7z archives.
+///
+/// Solid 7z archives compress many entries as one stream, so an entry can only
+/// be reached by decoding everything in front of it. `read_files` overrides
+/// the default to extract all requested entries in a single pass.
+pub struct Cb7Backend;
+
+fn seven_zip_error(err: impl std::fmt::Display) -> ReadArchiveError {
+    ReadArchiveError::SevenZip(err.to_string())
+}
+
+fn open_reader(path: &str) -> Result<SevenZReader<std::fs::File>, ReadArchiveError> {
+    std::fs::metadata(path).map_err(ReadArchiveError::Io)?;
+
+    SevenZReader::open(path, Password::empty()).map_err(seven_zip_error)
+}
+
+fn entry_name(entry: &sevenz_rust::SevenZArchiveEntry) -> String {
+    entry.name().replace('\\', "/")
+}
+
+impl ArchiveBackend for Cb7Backend {
+    fn format(&self) -> ArchiveFormat {
+        ArchiveFormat::SevenZip
+    }
+
+    fn list_files(&self, path: &str) -> Result<Vec<ArchiveFile>, ReadArchiveError> {
+        let reader = open_reader(path)?;
+
+        let files = reader
+            .archive()
+            .files
+            .iter()
+            .filter(|entry| !entry.is_directory())
+            .map(|entry| ArchiveFile {
+                name: entry_name(entry),
+            })
+            .collect();
+
+        Ok(files)
+    }
+
+    fn read_file(&self, path: &str, file_name: &str) -> Result<Vec<u8>, ReadArchiveError> {
+        let mut data = None;
+        let mut pending = HashSet::from([file_name]);
+
+        extract_matching(
+            path,
+            &mut pending,
+            &mut |_, content| data = Some(content),
+            &|| false,
+        )?;
+
+        data.ok_or_else(|| ReadArchiveError::EntryNotFound(file_name.to_string()))
+    }
+
+    fn read_files(
+        &self,
+        path: &str,
+        file_names: &[String],
+        on_data: &(dyn Fn(String, Vec<u8>) + Send + Sync),
+        on_error: &(dyn Fn(String, String) + Send + Sync),
+    ) {
+        self.read_files_cancellable(path, file_names, on_data, on_error, &|| false);
+    }
+
+    fn read_files_cancellable(
+        &self,
+        path: &str,
+        file_names: &[String],
+        on_data: &(dyn Fn(String, Vec<u8>) + Send + Sync),
+        on_error: &(dyn Fn(String, String) + Send + Sync),
+        is_cancelled: &(dyn Fn() -> bool + Send + Sync),
+    ) {
+        read_files_in_one_pass(file_names, on_error, is_cancelled, |pending| {
+            extract_matching(
+                path,
+                pending,
+                &mut |name, data| on_data(name, data),
+                is_cancelled,
+            )
+        });
+    }
+}
+
+/// Decodes the archive once, handing every entry listed in `pending` to
+/// `on_data` and removing it from the set. Entries that are not requested
+/// still have to be drained because later entries in a solid block depend on
+/// them. An entry that fails to decode stays in `pending`. The walk stops
+/// early once `is_cancelled` returns true.
+fn extract_matching(
+    path: &str,
+    pending: &mut HashSet<&str>,
+    on_data: &mut dyn FnMut(String, Vec<u8>),
+    is_cancelled: &dyn Fn() -> bool,
+) -> Result<(), ReadArchiveError> {
+    let mut reader = open_reader(path)?;
+
+    reader
+        .for_each_entries(|entry, content| {
+            if pending.is_empty() || is_cancelled() {
+                return Ok(false);
+            }
+
+            let name = entry_name(entry);
+            if entry.is_directory() || !pending.contains(name.as_str()) {
+                std::io::copy(content, &mut std::io::sink())?;
+                return Ok(true);
+            }
+
+            let mut data = Vec::new();
+            content.read_to_end(&mut data)?;
+            pending.remove(name.as_str());
+            on_data(name, data);
+
+            Ok(true)
+        })
+        .map_err(seven_zip_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sevenz_rust::{SevenZArchiveEntry, SevenZWriter};
+    use std::sync::Mutex;
+
+    fn create_7z(path: &std::path::Path, files: &[(&str, &[u8])]) {
+        let mut writer = SevenZWriter::create(path).unwrap();
+        for (name, content) in files {
+            let mut entry = SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            writer.push_archive_entry(entry, Some(*content)).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_list_and_read_7z_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cb7");
+        create_7z(&path, &[("page1.jpg", b"one"), ("page2.jpg", b"two")]);
+        let path = path.to_str().unwrap();
+
+        let names: Vec<String> = Cb7Backend
+            .list_files(path)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+
+        assert_eq!(names, vec!["page1.jpg", "page2.jpg"]);
+        assert_eq!(Cb7Backend.read_file(path, "page2.jpg").unwrap(), b"two");
+        assert!(matches!(
+            Cb7Backend.read_file(path, "missing.jpg"),
+            Err(ReadArchiveError::EntryNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_files_reports_missing_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cb7");
+        create_7z(&path, &[("page1.jpg", b"one"), ("page2.jpg", b"two")]);
+
+        let data = Mutex::new(Vec::new());
+        let errors = Mutex::new(Vec::new());
+
+        Cb7Backend.read_files(
+            path.to_str().unwrap(),
+            &["page2.jpg".to_string(), "missing.jpg".to_string()],
+            &|name, content| data.lock().unwrap().push((name, content)),
+            &|name, _| errors.lock().unwrap().push(name),
+        );
+
+        assert_eq!(
+            data.into_inner().unwrap(),
+            vec![("page2.jpg".to_string(), b"two".to_vec())]
+        );
+        assert_eq!(
+            errors.into_inner().unwrap(),
+            vec!["missing.jpg".to_string()]
+        );
+    }
+}
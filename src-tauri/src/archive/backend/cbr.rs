@@ -0,0 +1,216 @@
+use super::{ArchiveBackend, read_files_in_one_pass};
+use crate::archive::types::{ArchiveFile, ArchiveFormat, ReadArchiveError};
+use std::collections::HashSet;
+use unrar::Archive;
+
+/// Read-only access to RAR archives through libunrar.
+///
+/// RAR has no central directory, so every read walks the headers from the
+/// start of the archive. `read_files` overrides the default to extract all
+/// requested entries in a single pass.
+pub struct CbrBackend;
+
+fn rar_error(err: impl std::fmt::Display) -> ReadArchiveError {
+    ReadArchiveError::Rar(err.to_string())
+}
+
+fn entry_name(header: &unrar::FileHeader) -> String {
+    header.filename.to_string_lossy().replace('\\', "/")
+}
+
+impl ArchiveBackend for CbrBackend {
+    fn format(&self) -> ArchiveFormat {
+        ArchiveFormat::Rar
+    }
+
+    fn list_files(&self, path: &str) -> Result<Vec<ArchiveFile>, ReadArchiveError> {
+        std::fs::metadata(path).map_err(ReadArchiveError::Io)?;
+
+        let archive = Archive::new(path).open_for_listing().map_err(rar_error)?;
+
+        let mut files = Vec::new();
+        for header in archive {
+            let header = header.map_err(rar_error)?;
+            if header.is_directory() {
+                continue;
+            }
+
+            files.push(ArchiveFile {
+                name: entry_name(&header),
+            });
+        }
+
+        Ok(files)
+    }
+
+    fn read_file(&self, path: &str, file_name: &str) -> Result<Vec<u8>, ReadArchiveError> {
+        let mut archive = Archive::new(path)
+            .open_for_processing()
+            .map_err(rar_error)?;
+
+        while let Some(header) = archive.read_header().map_err(rar_error)? {
+            if entry_name(header.entry()) != file_name {
+                archive = header.skip().map_err(rar_error)?;
+                continue;
+            }
+
+            let (data, _) = header.read().map_err(rar_error)?;
+            return Ok(data);
+        }
+
+        Err(ReadArchiveError::EntryNotFound(file_name.to_string()))
+    }
+
+    fn read_files(
+        &self,
+        path: &str,
+        file_names: &[String],
+        on_data: &(dyn Fn(String, Vec<u8>) + Send + Sync),
+        on_error: &(dyn Fn(String, String) + Send + Sync),
+    ) {
+        self.read_files_cancellable(path, file_names, on_data, on_error, &|| false);
+    }
+
+    fn read_files_cancellable(
+        &self,
+        path: &str,
+        file_names: &[String],
+        on_data: &(dyn Fn(String, Vec<u8>) + Send + Sync),
+        on_error: &(dyn Fn(String, String) + Send + Sync),
+        is_cancelled: &(dyn Fn() -> bool + Send + Sync),
+    ) {
+        read_files_in_one_pass(file_names, on_error, is_cancelled, |pending| {
+            extract_matching(path, pending, on_data, on_error, is_cancelled)
+        });
+    }
+}
+
+/// Walks the archive once, handing every entry listed in `pending` to
+/// `on_data` and removing it from the set. The walk stops early once
+/// `is_cancelled` returns true.
+fn extract_matching(
+    path: &str,
+    pending: &mut HashSet<&str>,
+    on_data: &(dyn Fn(String, Vec<u8>) + Send + Sync),
+    on_error: &(dyn Fn(String, String) + Send + Sync),
+    is_cancelled: &dyn Fn() -> bool,
+) -> Result<(), ReadArchiveError> {
+    let mut archive = Archive::new(path)
+        .open_for_processing()
+        .map_err(rar_error)?;
+
+    while !pending.is_empty() && !is_cancelled() {
+        let Some(header) = archive.read_header().map_err(rar_error)? else {
+            break;
+        };
+
+        let name = entry_name(header.entry());
+        if !pending.remove(name.as_str()) {
+            archive = header.skip().map_err(rar_error)?;
+            continue;
+        }
+
+        match header.read() {
+            Ok((data, rest)) => {
+                on_data(name, data);
+                archive = rest;
+            }
+            Err(e) => {
+                let message = e.to_string();
+                on_error(name, message.clone());
+                return Err(ReadArchiveError::Rar(message));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::reader::read_archive;
+    use std::sync::Mutex;
+
+    /// A stored RAR 4 archive holding, in this order, `page2.jpg`,
+    /// `ComicInfo.xml`, an `extras` folder with `page3.jpg`, `page1.jpg` and
+    /// `notes.txt`.
+    const COMIC_CBR: &[u8] = include_bytes!("../../../test-fixtures/comic.cbr");
+
+    fn copy_fixture(dir: &tempfile::TempDir) -> String {
+        let path = dir.path().join("comic.cbr");
+        std::fs::write(&path, COMIC_CBR).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_list_and_read_rar_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = copy_fixture(&dir);
+
+        let names: Vec<String> = CbrBackend
+            .list_files(&path)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "page2.jpg",
+                "ComicInfo.xml",
+                "extras/page3.jpg",
+                "page1.jpg",
+                "notes.txt"
+            ]
+        );
+        assert_eq!(
+            CbrBackend.read_file(&path, "extras/page3.jpg").unwrap(),
+            b"\xFF\xD8\xFFthree"
+        );
+        assert!(matches!(
+            CbrBackend.read_file(&path, "missing.jpg"),
+            Err(ReadArchiveError::EntryNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_files_reports_missing_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = copy_fixture(&dir);
+
+        let data = Mutex::new(Vec::new());
+        let errors = Mutex::new(Vec::new());
+
+        CbrBackend.read_files(
+            &path,
+            &["page1.jpg".to_string(), "missing.jpg".to_string()],
+            &|name, content| data.lock().unwrap().push((name, content)),
+            &|name, _| errors.lock().unwrap().push(name),
+        );
+
+        assert_eq!(
+            data.into_inner().unwrap(),
+            vec![("page1.jpg".to_string(), b"\xFF\xD8\xFFone".to_vec())]
+        );
+        assert_eq!(
+            errors.into_inner().unwrap(),
+            vec!["missing.jpg".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_read_rar_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = copy_fixture(&dir);
+
+        let archive = read_archive(&path).unwrap();
+
+        assert_eq!(
+            archive.image_files,
+            vec!["page1.jpg", "page2.jpg", "extras/page3.jpg"]
+        );
+        assert_eq!(archive.comic_info.unwrap().title, Some("RAR".to_string()));
+    }
+}
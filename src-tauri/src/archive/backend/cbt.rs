@@ -0,0 +1,257 @@
+use super::{ArchiveBackend, read_files_in_one_pass};
+use crate::archive::atomic_write::AtomicFile;
+use crate::archive::types::{ArchiveFile, ArchiveFormat, ReadArchiveError, WriteArchiveError};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+/// Access to uncompressed tar archives.
+///
+/// Tar has no index, so reads walk the entries from the start and
+/// `read_files` extracts all requested entries in a single pass. Writes
+/// rebuild the archive, copying every entry except ComicInfo.xml.
+pub struct CbtBackend;
+
+fn open_tar_archive(path: &str) -> Result<tar::Archive<BufReader<fs::File>>, ReadArchiveError> {
+    let file = fs::File::open(path).map_err(ReadArchiveError::Io)?;
+    Ok(tar::Archive::new(BufReader::new(file)))
+}
+
+/// Entry path with the `./` prefix that `tar -cf x.cbt .` adds stripped, so
+/// names match the other formats.
+fn entry_name<R: Read>(entry: &tar::Entry<R>) -> std::io::Result<String> {
+    let path = entry.path()?;
+    let name = path.to_string_lossy();
+    Ok(name.trim_start_matches("./").to_string())
+}
+
+impl ArchiveBackend for CbtBackend {
+    fn format(&self) -> ArchiveFormat {
+        ArchiveFormat::Tar
+    }
+
+    fn list_files(&self, path: &str) -> Result<Vec<ArchiveFile>, ReadArchiveError> {
+        let mut archive = open_tar_archive(path)?;
+
+        let mut files = Vec::new();
+        for entry in archive.entries().map_err(ReadArchiveError::Io)? {
+            let entry = entry.map_err(ReadArchiveError::Io)?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let name = entry_name(&entry).map_err(ReadArchiveError::Io)?;
+            files.push(ArchiveFile { name });
+        }
+
+        Ok(files)
+    }
+
+    fn read_file(&self, path: &str, file_name: &str) -> Result<Vec<u8>, ReadArchiveError> {
+        let mut data = None;
+        let mut pending = HashSet::from([file_name]);
+
+        extract_matching(
+            path,
+            &mut pending,
+            &mut |_, content| data = Some(content),
+            &|| false,
+        )?;
+
+        data.ok_or_else(|| ReadArchiveError::EntryNotFound(file_name.to_string()))
+    }
+
+    fn read_files(
+        &self,
+        path: &str,
+        file_names: &[String],
+        on_data: &(dyn Fn(String, Vec<u8>) + Send + Sync),
+        on_error: &(dyn Fn(String, String) + Send + Sync),
+    ) {
+        self.read_files_cancellable(path, file_names, on_data, on_error, &|| false);
+    }
+
+    fn read_files_cancellable(
+        &self,
+        path: &str,
+        file_names: &[String],
+        on_data: &(dyn Fn(String, Vec<u8>) + Send + Sync),
+        on_error: &(dyn Fn(String, String) + Send + Sync),
+        is_cancelled: &(dyn Fn() -> bool + Send + Sync),
+    ) {
+        read_files_in_one_pass(file_names, on_error, is_cancelled, |pending| {
+            extract_matching(
+                path,
+                pending,
+                &mut |name, data| on_data(name, data),
+                is_cancelled,
+            )
+        });
+    }
+
+    fn is_writable(&self) -> bool {
+        true
+    }
+
+    fn write_comic_info(
+        &self,
+        path: &str,
+        xml_content: Option<&str>,
+    ) -> Result<(), WriteArchiveError> {
+        let mut output = AtomicFile::create(path).map_err(WriteArchiveError::Io)?;
+
+        {
+            let mut original_archive = open_tar_archive(path)?;
+
+            let mut new_archive = tar::Builder::new(BufWriter::new(output.as_file_mut()));
+
+            for entry in original_archive.entries().map_err(WriteArchiveError::Io)? {
+                let mut entry = entry.map_err(WriteArchiveError::Io)?;
+                if entry_name(&entry).map_err(WriteArchiveError::Io)? == "ComicInfo.xml" {
+                    continue;
+                }
+
+                let mut header = entry.header().clone();
+                let entry_path = entry.path().map_err(WriteArchiveError::Io)?.into_owned();
+                new_archive
+                    .append_data(&mut header, entry_path, &mut entry)
+                    .map_err(WriteArchiveError::Io)?;
+            }
+
+            if let Some(xml_content) = xml_content {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(xml_content.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(
+                    std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or_default(),
+                );
+
+                new_archive
+                    .append_data(&mut header, "ComicInfo.xml", xml_content.as_bytes())
+                    .map_err(WriteArchiveError::Io)?;
+            }
+
+            new_archive
+                .into_inner()
+                .and_then(|mut writer| writer.flush())
+                .map_err(WriteArchiveError::Io)?;
+        }
+
+        output.commit().map_err(WriteArchiveError::Io)
+    }
+}
+
+/// Walks the archive once, handing every regular file listed in `pending` to
+/// `on_data` and removing it from the set. An entry that fails to read stays
+/// in `pending`. The walk stops early once `is_cancelled` returns true.
+fn extract_matching(
+    path: &str,
+    pending: &mut HashSet<&str>,
+    on_data: &mut dyn FnMut(String, Vec<u8>),
+    is_cancelled: &dyn Fn() -> bool,
+) -> Result<(), ReadArchiveError> {
+    let mut archive = open_tar_archive(path)?;
+
+    for entry in archive.entries().map_err(ReadArchiveError::Io)? {
+        if pending.is_empty() || is_cancelled() {
+            break;
+        }
+
+        let mut entry = entry.map_err(ReadArchiveError::Io)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let name = entry_name(&entry).map_err(ReadArchiveError::Io)?;
+        if !pending.contains(name.as_str()) {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).map_err(ReadArchiveError::Io)?;
+        pending.remove(name.as_str());
+        on_data(name, data);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_tar(path: &std::path::Path, files: &[(&str, &[u8])]) {
+        let mut builder = tar::Builder::new(fs::File::create(path).unwrap());
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_list_and_read_tar_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbt");
+        create_tar(&path, &[("./page1.jpg", b"one"), ("page2.jpg", b"two")]);
+        let path = path.to_str().unwrap();
+
+        let names: Vec<String> = CbtBackend
+            .list_files(path)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+
+        assert_eq!(names, vec!["page1.jpg", "page2.jpg"]);
+        assert_eq!(CbtBackend.read_file(path, "page1.jpg").unwrap(), b"one");
+        assert!(matches!(
+            CbtBackend.read_file(path, "missing.jpg"),
+            Err(ReadArchiveError::EntryNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_write_comic_info_replaces_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbt");
+        create_tar(
+            &path,
+            &[("page1.jpg", b"one"), ("ComicInfo.xml", b"<ComicInfo />")],
+        );
+        let path = path.to_str().unwrap();
+
+        CbtBackend
+            .write_comic_info(path, Some("<ComicInfo><Title>New</Title></ComicInfo>"))
+            .unwrap();
+
+        let names: Vec<String> = CbtBackend
+            .list_files(path)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(names, vec!["page1.jpg", "ComicInfo.xml"]);
+        assert_eq!(
+            CbtBackend.read_file(path, "ComicInfo.xml").unwrap(),
+            b"<ComicInfo><Title>New</Title></ComicInfo>"
+        );
+
+        CbtBackend.write_comic_info(path, None).unwrap();
+
+        let names: Vec<String> = CbtBackend
+            .list_files(path)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(names, vec!["page1.jpg"]);
+        assert_eq!(CbtBackend.read_file(path, "page1.jpg").unwrap(), b"one");
+    }
+}
@@ -0,0 +1,138 @@
+use super::ArchiveBackend;
+use super::zip_cache::{CachedZipArchive, cached_zip_archive};
+use crate::archive::atomic_write::AtomicFile;
+use crate::archive::types::{ArchiveFile, ArchiveFormat, ReadArchiveError, WriteArchiveError};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use zip::CompressionMethod;
+use zip::write::FileOptions;
+
+pub struct CbzBackend;
+
+fn open_zip_archive(path: &str) -> Result<CachedZipArchive, ReadArchiveError> {
+    cached_zip_archive(path)
+}
+
+impl ArchiveBackend for CbzBackend {
+    fn format(&self) -> ArchiveFormat {
+        ArchiveFormat::Zip
+    }
+
+    fn list_files(&self, path: &str) -> Result<Vec<ArchiveFile>, ReadArchiveError> {
+        let mut archive = open_zip_archive(path)?;
+
+        let mut files = Vec::new();
+        for i in 0..archive.len() {
+            let zip_file = archive.by_index(i).map_err(ReadArchiveError::Zip)?;
+            let name = zip_file.name().to_string();
+            files.push(ArchiveFile { name });
+        }
+
+        Ok(files)
+    }
+
+    fn read_file(&self, path: &str, file_name: &str) -> Result<Vec<u8>, ReadArchiveError> {
+        let mut archive = open_zip_archive(path)?;
+
+        let mut zip_file = archive.by_name(file_name).map_err(ReadArchiveError::Zip)?;
+        let mut data = Vec::new();
+        zip_file
+            .read_to_end(&mut data)
+            .map_err(ReadArchiveError::Io)?;
+        Ok(data)
+    }
+
+    fn read_file_headers(
+        &self,
+        path: &str,
+        file_names: &[String],
+        limit: usize,
+        on_data: &(dyn Fn(String, Vec<u8>, u64) + Send + Sync),
+        on_error: &(dyn Fn(String, String) + Send + Sync),
+    ) {
+        file_names.par_iter().for_each(|file_name| {
+            let header = open_zip_archive(path).and_then(|mut archive| {
+                let zip_file = archive.by_name(file_name).map_err(ReadArchiveError::Zip)?;
+                let size = zip_file.size();
+
+                let mut data = Vec::new();
+                zip_file
+                    .take(limit as u64)
+                    .read_to_end(&mut data)
+                    .map_err(ReadArchiveError::Io)?;
+
+                Ok((data, size))
+            });
+
+            match header {
+                Ok((data, size)) => on_data(file_name.clone(), data, size),
+                Err(e) => on_error(file_name.clone(), e.to_string()),
+            }
+        });
+    }
+
+    fn entry_checksums(&self, path: &str) -> Result<HashMap<String, u32>, ReadArchiveError> {
+        let mut archive = open_zip_archive(path)?;
+
+        let mut checksums = HashMap::new();
+        for i in 0..archive.len() {
+            let zip_file = archive.by_index_raw(i).map_err(ReadArchiveError::Zip)?;
+            checksums.insert(zip_file.name().to_string(), zip_file.crc32());
+        }
+
+        Ok(checksums)
+    }
+
+    fn is_writable(&self) -> bool {
+        true
+    }
+
+    fn write_comic_info(
+        &self,
+        path: &str,
+        xml_content: Option<&str>,
+    ) -> Result<(), WriteArchiveError> {
+        let mut output = AtomicFile::create(path).map_err(WriteArchiveError::Io)?;
+
+        {
+            let original_file = fs::File::open(path).map_err(WriteArchiveError::Io)?;
+            let mut original_archive = zip::ZipArchive::new(BufReader::new(original_file))
+                .map_err(WriteArchiveError::Zip)?;
+
+            let mut new_archive = zip::ZipWriter::new(BufWriter::new(output.as_file_mut()));
+
+            for i in 0..original_archive.len() {
+                let file = original_archive
+                    .by_index_raw(i)
+                    .map_err(WriteArchiveError::Zip)?;
+                if file.name() != "ComicInfo.xml" {
+                    new_archive
+                        .raw_copy_file(file)
+                        .map_err(WriteArchiveError::Zip)?;
+                }
+            }
+
+            if let Some(xml_content) = xml_content {
+                let xml_options =
+                    FileOptions::<()>::default().compression_method(CompressionMethod::Stored);
+
+                new_archive
+                    .start_file("ComicInfo.xml", xml_options)
+                    .map_err(WriteArchiveError::Zip)?;
+                new_archive
+                    .write_all(xml_content.as_bytes())
+                    .map_err(WriteArchiveError::Io)?;
+            }
+
+            new_archive
+                .finish()
+                .map_err(WriteArchiveError::Zip)?
+                .flush()
+                .map_err(WriteArchiveError::Io)?;
+        }
+
+        output.commit().map_err(WriteArchiveError::Io)
+    }
+}
@@ -0,0 +1,210 @@
+use super::ArchiveBackend;
+use crate::archive::atomic_write::AtomicFile;
+use crate::archive::types::{ArchiveFile, ArchiveFormat, ReadArchiveError, WriteArchiveError};
+use rayon::prelude::*;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// A plain folder of images treated as a comic.
+///
+/// Entry names are paths relative to the folder, using `/` as separator.
+/// ComicInfo.xml is stored as a sidecar file in the folder root.
+pub struct DirectoryBackend;
+
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<ArchiveFile>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+            continue;
+        }
+
+        if !path.is_file() {
+            continue;
+        }
+
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.push(ArchiveFile { name });
+    }
+
+    Ok(())
+}
+
+/// Resolves an entry name to a path inside `root`, refusing names that would
+/// escape the folder.
+fn entry_path(root: &str, file_name: &str) -> Option<PathBuf> {
+    let relative = Path::new(file_name);
+    let stays_inside = relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+
+    if !stays_inside {
+        return None;
+    }
+
+    Some(Path::new(root).join(relative))
+}
+
+impl ArchiveBackend for DirectoryBackend {
+    fn format(&self) -> ArchiveFormat {
+        ArchiveFormat::Directory
+    }
+
+    fn list_files(&self, path: &str) -> Result<Vec<ArchiveFile>, ReadArchiveError> {
+        let root = Path::new(path);
+
+        let mut files = Vec::new();
+        collect_files(root, root, &mut files).map_err(ReadArchiveError::Io)?;
+
+        Ok(files)
+    }
+
+    fn read_file(&self, path: &str, file_name: &str) -> Result<Vec<u8>, ReadArchiveError> {
+        let file_path = entry_path(path, file_name)
+            .ok_or_else(|| ReadArchiveError::EntryNotFound(file_name.to_string()))?;
+
+        fs::read(file_path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ReadArchiveError::EntryNotFound(file_name.to_string()),
+            _ => ReadArchiveError::Io(e),
+        })
+    }
+
+    fn read_file_headers(
+        &self,
+        path: &str,
+        file_names: &[String],
+        limit: usize,
+        on_data: &(dyn Fn(String, Vec<u8>, u64) + Send + Sync),
+        on_error: &(dyn Fn(String, String) + Send + Sync),
+    ) {
+        file_names.par_iter().for_each(|file_name| {
+            let header = entry_path(path, file_name)
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, file_name.clone()))
+                .and_then(|file_path| {
+                    let file = fs::File::open(file_path)?;
+                    let size = file.metadata()?.len();
+
+                    let mut data = Vec::new();
+                    file.take(limit as u64).read_to_end(&mut data)?;
+
+                    Ok((data, size))
+                });
+
+            match header {
+                Ok((data, size)) => on_data(file_name.clone(), data, size),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => on_error(
+                    file_name.clone(),
+                    ReadArchiveError::EntryNotFound(file_name.clone()).to_string(),
+                ),
+                Err(e) => on_error(file_name.clone(), ReadArchiveError::Io(e).to_string()),
+            }
+        });
+    }
+
+    fn is_writable(&self) -> bool {
+        true
+    }
+
+    fn write_comic_info(
+        &self,
+        path: &str,
+        xml_content: Option<&str>,
+    ) -> Result<(), WriteArchiveError> {
+        let sidecar_path = Path::new(path).join("ComicInfo.xml");
+
+        let Some(xml_content) = xml_content else {
+            return match fs::remove_file(&sidecar_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(WriteArchiveError::Io(e)),
+                _ => Ok(()),
+            };
+        };
+
+        let mut output = AtomicFile::create(&sidecar_path).map_err(WriteArchiveError::Io)?;
+        output
+            .as_file_mut()
+            .write_all(xml_content.as_bytes())
+            .map_err(WriteArchiveError::Io)?;
+
+        output.commit().map_err(WriteArchiveError::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_names(path: &str) -> Vec<String> {
+        let mut names: Vec<String> = DirectoryBackend
+            .list_files(path)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_lists_and_reads_nested_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("001.jpg"), b"one").unwrap();
+        fs::create_dir(dir.path().join("extra")).unwrap();
+        fs::write(dir.path().join("extra").join("002.jpg"), b"two").unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        assert_eq!(sorted_names(path), vec!["001.jpg", "extra/002.jpg"]);
+        assert_eq!(
+            DirectoryBackend.read_file(path, "extra/002.jpg").unwrap(),
+            b"two"
+        );
+        assert!(matches!(
+            DirectoryBackend.read_file(path, "missing.jpg"),
+            Err(ReadArchiveError::EntryNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_refuses_entries_outside_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let comic = dir.path().join("comic");
+        fs::create_dir(&comic).unwrap();
+        fs::write(dir.path().join("secret.txt"), b"secret").unwrap();
+
+        let result = DirectoryBackend.read_file(comic.to_str().unwrap(), "../secret.txt");
+
+        assert!(matches!(result, Err(ReadArchiveError::EntryNotFound(_))));
+    }
+
+    #[test]
+    fn test_writes_and_removes_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("001.jpg"), b"one").unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        DirectoryBackend
+            .write_comic_info(path, Some("<ComicInfo />"))
+            .unwrap();
+
+        assert_eq!(sorted_names(path), vec!["001.jpg", "ComicInfo.xml"]);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("ComicInfo.xml")).unwrap(),
+            "<ComicInfo />"
+        );
+
+        DirectoryBackend.write_comic_info(path, None).unwrap();
+        DirectoryBackend.write_comic_info(path, None).unwrap();
+
+        assert_eq!(sorted_names(path), vec!["001.jpg"]);
+    }
+}
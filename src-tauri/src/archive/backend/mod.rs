@@ -0,0 +1,343 @@
+mod cb7;
+mod cbr;
+mod cbt;
+mod cbz;
+mod directory;
+mod zip_cache;
+
+pub use cb7::Cb7Backend;
+pub use cbr::CbrBackend;
+pub use cbt::CbtBackend;
+pub use cbz::CbzBackend;
+pub use directory::DirectoryBackend;
+pub use zip_cache::{FileStamp, cached_zip_archive, invalidate_zip_archive};
+
+use super::types::{ArchiveFile, ArchiveFormat, ReadArchiveError, WriteArchiveError};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+const ZIP_MAGIC: &[u8] = b"PK";
+const RAR_MAGIC: &[u8] = b"Rar!\x1a\x07";
+const SEVEN_ZIP_MAGIC: &[u8] = b"7z\xbc\xaf\x27\x1c";
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// A container format that comic pages can be read from.
+///
+/// Every command that touches archive contents goes through this trait, so
+/// supporting a new format only requires a new implementation and an entry in
+/// [`backend_for`].
+pub trait ArchiveBackend: Send + Sync {
+    fn format(&self) -> ArchiveFormat;
+
+    /// Lists every entry in the archive.
+    fn list_files(&self, path: &str) -> Result<Vec<ArchiveFile>, ReadArchiveError>;
+
+    /// Reads the full contents of a single entry.
+    fn read_file(&self, path: &str, file_name: &str) -> Result<Vec<u8>, ReadArchiveError>;
+
+    /// Reads several entries, reporting each one through `on_data` or `on_error`.
+    ///
+    /// The default implementation reads entries in parallel, one `read_file`
+    /// call per entry. Formats without random access should override it.
+    fn read_files(
+        &self,
+        path: &str,
+        file_names: &[String],
+        on_data: &(dyn Fn(String, Vec<u8>) + Send + Sync),
+        on_error: &(dyn Fn(String, String) + Send + Sync),
+    ) {
+        file_names
+            .par_iter()
+            .for_each(|file_name| match self.read_file(path, file_name) {
+                Ok(data) => on_data(file_name.clone(), data),
+                Err(e) => on_error(file_name.clone(), e.to_string()),
+            });
+    }
+
+    /// Like `read_files`, but stops once `is_cancelled` returns true. Entries
+    /// that were not read by then are not reported.
+    ///
+    /// Entries are read in the order given as far as the format allows, so
+    /// callers can put the most urgent ones first. The default implementation
+    /// reads one batch of entries per thread at a time.
+    fn read_files_cancellable(
+        &self,
+        path: &str,
+        file_names: &[String],
+        on_data: &(dyn Fn(String, Vec<u8>) + Send + Sync),
+        on_error: &(dyn Fn(String, String) + Send + Sync),
+        is_cancelled: &(dyn Fn() -> bool + Send + Sync),
+    ) {
+        for batch in file_names.chunks(rayon::current_num_threads().max(1)) {
+            if is_cancelled() {
+                return;
+            }
+
+            self.read_files(path, batch, on_data, on_error);
+        }
+    }
+
+    /// Reads at most `limit` bytes from the start of each entry and reports
+    /// them through `on_data` together with the entry's full size.
+    ///
+    /// Used to inspect file headers. The default implementation extracts
+    /// whole entries through `read_files`; formats with random access should
+    /// override it to stop reading early.
+    fn read_file_headers(
+        &self,
+        path: &str,
+        file_names: &[String],
+        limit: usize,
+        on_data: &(dyn Fn(String, Vec<u8>, u64) + Send + Sync),
+        on_error: &(dyn Fn(String, String) + Send + Sync),
+    ) {
+        let on_full_data = |file_name: String, mut data: Vec<u8>| {
+            let size = data.len() as u64;
+            data.truncate(limit);
+            on_data(file_name, data, size);
+        };
+
+        self.read_files(path, file_names, &on_full_data, on_error);
+    }
+
+    /// Returns the CRC32 of each entry when the format records them, so
+    /// entries can be identified without extracting them. Formats without
+    /// recorded checksums return an empty map.
+    fn entry_checksums(&self, _path: &str) -> Result<HashMap<String, u32>, ReadArchiveError> {
+        Ok(HashMap::new())
+    }
+
+    fn is_writable(&self) -> bool {
+        false
+    }
+
+    /// Replaces the ComicInfo.xml entry, or removes it when `xml_content` is `None`.
+    fn write_comic_info(
+        &self,
+        _path: &str,
+        _xml_content: Option<&str>,
+    ) -> Result<(), WriteArchiveError> {
+        Err(read_only_error(self.format()))
+    }
+}
+
+pub fn read_only_error(format: ArchiveFormat) -> WriteArchiveError {
+    WriteArchiveError::ReadOnlyFormat {
+        format,
+        convert_to: ArchiveFormat::Zip,
+    }
+}
+
+/// Fails with [`WriteArchiveError::ReadOnlyFormat`] when the archive at `path`
+/// cannot be rewritten in place.
+pub fn ensure_writable(path: &str) -> Result<(), WriteArchiveError> {
+    let backend = open_backend(path)?;
+    if backend.is_writable() {
+        return Ok(());
+    }
+
+    Err(read_only_error(backend.format()))
+}
+
+pub fn backend_for(format: ArchiveFormat) -> &'static dyn ArchiveBackend {
+    match format {
+        ArchiveFormat::Zip => &CbzBackend,
+        ArchiveFormat::Rar => &CbrBackend,
+        ArchiveFormat::SevenZip => &Cb7Backend,
+        ArchiveFormat::Tar => &CbtBackend,
+        ArchiveFormat::Directory => &DirectoryBackend,
+    }
+}
+
+/// `read_files` for formats that can only be walked front to back.
+///
+/// `extract` receives the set of requested entries and removes each one it
+/// hands to `on_data`. Whatever is left afterwards is reported through
+/// `on_error`, either as missing or with the error that stopped the walk,
+/// unless the read was cancelled.
+fn read_files_in_one_pass(
+    file_names: &[String],
+    on_error: &(dyn Fn(String, String) + Send + Sync),
+    is_cancelled: &(dyn Fn() -> bool + Send + Sync),
+    extract: impl FnOnce(&mut HashSet<&str>) -> Result<(), ReadArchiveError>,
+) {
+    let mut pending: HashSet<&str> = file_names.iter().map(String::as_str).collect();
+    let result = extract(&mut pending);
+
+    if is_cancelled() {
+        return;
+    }
+
+    if let Err(e) = result {
+        for file_name in pending.drain() {
+            on_error(file_name.to_string(), e.to_string());
+        }
+        return;
+    }
+
+    for file_name in pending {
+        on_error(
+            file_name.to_string(),
+            ReadArchiveError::EntryNotFound(file_name.to_string()).to_string(),
+        );
+    }
+}
+
+/// Detects the container format by its magic bytes, falling back to the file
+/// extension when the header is not recognised. Directories are opened as a
+/// loose folder of images.
+pub fn detect_format(path: &str) -> Result<ArchiveFormat, ReadArchiveError> {
+    if std::fs::metadata(path)
+        .map_err(ReadArchiveError::Io)?
+        .is_dir()
+    {
+        return Ok(ArchiveFormat::Directory);
+    }
+
+    let mut header = Vec::with_capacity(TAR_MAGIC_OFFSET + TAR_MAGIC.len());
+    std::fs::File::open(path)
+        .and_then(|file| {
+            file.take((TAR_MAGIC_OFFSET + TAR_MAGIC.len()) as u64)
+                .read_to_end(&mut header)
+        })
+        .map_err(ReadArchiveError::Io)?;
+
+    if header.starts_with(ZIP_MAGIC) {
+        return Ok(ArchiveFormat::Zip);
+    }
+
+    if header.starts_with(RAR_MAGIC) {
+        return Ok(ArchiveFormat::Rar);
+    }
+
+    if header.starts_with(SEVEN_ZIP_MAGIC) {
+        return Ok(ArchiveFormat::SevenZip);
+    }
+
+    if header
+        .get(TAR_MAGIC_OFFSET..)
+        .is_some_and(|magic| magic.starts_with(TAR_MAGIC))
+    {
+        return Ok(ArchiveFormat::Tar);
+    }
+
+    Ok(format_from_extension(path).unwrap_or(ArchiveFormat::Zip))
+}
+
+fn format_from_extension(path: &str) -> Option<ArchiveFormat> {
+    let extension = std::path::Path::new(path)
+        .extension()?
+        .to_string_lossy()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "cbz" | "zip" => Some(ArchiveFormat::Zip),
+        "cbr" | "rar" => Some(ArchiveFormat::Rar),
+        "cb7" | "7z" => Some(ArchiveFormat::SevenZip),
+        "cbt" | "tar" => Some(ArchiveFormat::Tar),
+        _ => None,
+    }
+}
+
+pub fn open_backend(path: &str) -> Result<&'static dyn ArchiveBackend, ReadArchiveError> {
+    detect_format(path).map(backend_for)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_temp(suffix: &str, content: &[u8]) -> tempfile::NamedTempFile {
+        let mut file = tempfile::Builder::new().suffix(suffix).tempfile().unwrap();
+        file.write_all(content).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_detect_format_by_magic_bytes() {
+        let zip = write_temp(".cbr", b"PK\x03\x04rest");
+        let rar4 = write_temp(".cbz", b"Rar!\x1a\x07\x00rest");
+        let rar5 = write_temp(".bin", b"Rar!\x1a\x07\x01\x00rest");
+        let seven_zip = write_temp(".cbz", b"7z\xbc\xaf\x27\x1c\x00\x04rest");
+        let mut tar_header = vec![0u8; 512];
+        tar_header[257..262].copy_from_slice(b"ustar");
+        let tar = write_temp(".cbz", &tar_header);
+
+        let path = |f: &tempfile::NamedTempFile| f.path().to_str().unwrap().to_string();
+
+        assert_eq!(detect_format(&path(&zip)).unwrap(), ArchiveFormat::Zip);
+        assert_eq!(detect_format(&path(&rar4)).unwrap(), ArchiveFormat::Rar);
+        assert_eq!(detect_format(&path(&rar5)).unwrap(), ArchiveFormat::Rar);
+        assert_eq!(
+            detect_format(&path(&seven_zip)).unwrap(),
+            ArchiveFormat::SevenZip
+        );
+        assert_eq!(detect_format(&path(&tar)).unwrap(), ArchiveFormat::Tar);
+    }
+
+    #[test]
+    fn test_detect_format_falls_back_to_extension() {
+        let rar = write_temp(".CBR", b"");
+        let seven_zip = write_temp(".cb7", b"");
+        let tar = write_temp(".cbt", b"page.jpg");
+        let unknown = write_temp(".bin", b"garbage");
+
+        assert_eq!(
+            detect_format(rar.path().to_str().unwrap()).unwrap(),
+            ArchiveFormat::Rar
+        );
+        assert_eq!(
+            detect_format(seven_zip.path().to_str().unwrap()).unwrap(),
+            ArchiveFormat::SevenZip
+        );
+        assert_eq!(
+            detect_format(tar.path().to_str().unwrap()).unwrap(),
+            ArchiveFormat::Tar
+        );
+        assert_eq!(
+            detect_format(unknown.path().to_str().unwrap()).unwrap(),
+            ArchiveFormat::Zip
+        );
+    }
+
+    #[test]
+    fn test_detect_format_directory() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(
+            detect_format(dir.path().to_str().unwrap()).unwrap(),
+            ArchiveFormat::Directory
+        );
+    }
+
+    #[test]
+    fn test_detect_format_missing_file() {
+        let result = detect_format("does/not/exist.cbr");
+        assert!(matches!(result, Err(ReadArchiveError::Io(_))));
+    }
+
+    #[test]
+    fn test_cbr_refuses_comic_info_writes() {
+        let result = CbrBackend.write_comic_info("comic.cbr", Some("<ComicInfo />"));
+
+        match result {
+            Err(WriteArchiveError::ReadOnlyFormat { format, convert_to }) => {
+                assert_eq!(format, ArchiveFormat::Rar);
+                assert_eq!(convert_to, ArchiveFormat::Zip);
+            }
+            other => panic!("expected ReadOnlyFormat, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_writable_backends() {
+        assert!(CbzBackend.is_writable());
+        assert!(CbtBackend.is_writable());
+        assert!(DirectoryBackend.is_writable());
+        assert!(!CbrBackend.is_writable());
+        assert!(!Cb7Backend.is_writable());
+    }
+}
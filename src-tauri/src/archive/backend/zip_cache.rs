@@ -0,0 +1,264 @@
+use crate::archive::types::ReadArchiveError;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+
+/// Number of archives whose parsed central directory is kept around.
+const MAX_CACHED_ARCHIVES: usize = 8;
+
+/// A file handle that can be cloned and read from several threads at once.
+///
+/// Every clone keeps its own position and reads with positional reads, so
+/// clones never move each other's cursor.
+#[derive(Clone)]
+pub struct SharedFile {
+    file: Arc<fs::File>,
+    len: u64,
+    position: u64,
+}
+
+impl SharedFile {
+    fn new(file: fs::File) -> io::Result<Self> {
+        let len = file.metadata()?.len();
+
+        Ok(Self {
+            file: Arc::new(file),
+            len,
+            position: 0,
+        })
+    }
+
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self.file.as_ref(), buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self.file.as_ref(), buf, offset)
+    }
+}
+
+impl Read for SharedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.read_at(buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for SharedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        Ok(self.position)
+    }
+}
+
+pub type CachedZipArchive = zip::ZipArchive<SharedFile>;
+
+/// Size and modification time of an archive when it was opened. A cached
+/// handle is only reused while the file on disk still matches.
+///
+/// Modification times can be too coarse to tell two quick writes of the same
+/// size apart, so every writer also drops the handle with
+/// [`invalidate_zip_archive`] once its new file is in place.
+/// The size and modification time of a file, to tell whether what was cached
+/// about it is still current.
+#[derive(Clone, Copy, PartialEq)]
+pub struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    pub fn of(metadata: &fs::Metadata) -> Self {
+        Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
+
+struct CachedArchive {
+    archive: CachedZipArchive,
+    stamp: FileStamp,
+    last_used: Instant,
+}
+
+static ZIP_ARCHIVES: Lazy<Mutex<HashMap<String, CachedArchive>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Returns a handle to the zip archive at `path`, parsing its central
+/// directory only the first time or when the file changed since.
+///
+/// Handles are cheap clones of a cached one, so every thread can take its own.
+pub fn cached_zip_archive(path: &str) -> Result<CachedZipArchive, ReadArchiveError> {
+    let stamp = FileStamp::of(&fs::metadata(path).map_err(ReadArchiveError::Io)?);
+
+    if let Some(cached) = ZIP_ARCHIVES.lock().unwrap().get_mut(path) {
+        if cached.stamp == stamp {
+            cached.last_used = Instant::now();
+            return Ok(cached.archive.clone());
+        }
+    }
+
+    let file = fs::File::open(path).map_err(ReadArchiveError::Io)?;
+    let archive = zip::ZipArchive::new(SharedFile::new(file).map_err(ReadArchiveError::Io)?)
+        .map_err(ReadArchiveError::Zip)?;
+
+    let mut archives = ZIP_ARCHIVES.lock().unwrap();
+
+    if archives.len() >= MAX_CACHED_ARCHIVES && !archives.contains_key(path) {
+        let least_recently_used = archives
+            .iter()
+            .min_by_key(|(_, cached)| cached.last_used)
+            .map(|(path, _)| path.clone());
+
+        if let Some(least_recently_used) = least_recently_used {
+            archives.remove(&least_recently_used);
+        }
+    }
+
+    archives.insert(
+        path.to_string(),
+        CachedArchive {
+            archive: archive.clone(),
+            stamp,
+            last_used: Instant::now(),
+        },
+    );
+
+    Ok(archive)
+}
+
+/// Drops the cached handle of an archive that changed on disk.
+pub fn invalidate_zip_archive(path: &str) {
+    ZIP_ARCHIVES.lock().unwrap().remove(path);
+}
+
+#[cfg(test)]
+fn is_cached(path: &str) -> bool {
+    ZIP_ARCHIVES.lock().unwrap().contains_key(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+    use std::io::Write;
+    use std::time::Duration;
+    use zip::write::FileOptions;
+
+    fn create_cbz(path: &std::path::Path, pages: usize, page_size: usize) {
+        let mut zip = zip::ZipWriter::new(fs::File::create(path).unwrap());
+
+        for page in 0..pages {
+            zip.start_file(
+                format!("{:03}.jpg", page),
+                FileOptions::<()>::default().compression_method(zip::CompressionMethod::Stored),
+            )
+            .unwrap();
+            zip.write_all(&vec![(page % 251) as u8; page_size]).unwrap();
+        }
+
+        zip.finish().unwrap();
+    }
+
+    fn read_entry(archive: &mut zip::ZipArchive<impl Read + Seek>, name: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_shared_file_clones_keep_their_own_position() {
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file.write_all(b"0123456789").unwrap();
+
+        let mut first = SharedFile::new(temp_file.reopen().unwrap()).unwrap();
+        let mut second = first.clone();
+
+        let mut buf = [0; 3];
+        first.seek(SeekFrom::End(-3)).unwrap();
+        first.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"789");
+
+        second.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"012");
+
+        assert!(second.seek(SeekFrom::Current(-10)).is_err());
+    }
+
+    #[test]
+    fn test_cached_archive_is_reused_until_the_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        create_cbz(&path, 3, 10);
+        let path_str = path.to_str().unwrap();
+
+        let mut archive = cached_zip_archive(path_str).unwrap();
+        assert_eq!(read_entry(&mut archive, "001.jpg"), vec![1; 10]);
+        assert!(is_cached(path_str));
+
+        let handles: Vec<Vec<u8>> = (0..3)
+            .into_par_iter()
+            .map(|page| {
+                let mut archive = cached_zip_archive(path_str).unwrap();
+                read_entry(&mut archive, &format!("{:03}.jpg", page))
+            })
+            .collect();
+        assert_eq!(handles, vec![vec![0; 10], vec![1; 10], vec![2; 10]]);
+
+        std::thread::sleep(Duration::from_millis(20));
+        create_cbz(&path, 4, 10);
+        assert_eq!(cached_zip_archive(path_str).unwrap().len(), 4);
+
+        invalidate_zip_archive(path_str);
+        assert!(!is_cached(path_str));
+    }
+
+    #[test]
+    fn test_writers_drop_the_cached_handle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        create_cbz(&path, 2, 10);
+        let path = path.to_str().unwrap();
+
+        cached_zip_archive(path).unwrap();
+        crate::archive::writer::update_zip_with_comicinfo(path, "<ComicInfo />").unwrap();
+        assert!(!is_cached(path));
+
+        // Without waiting for the modification time to move on, the next
+        // read still sees the new entry.
+        let mut archive = cached_zip_archive(path).unwrap();
+        assert_eq!(read_entry(&mut archive, "ComicInfo.xml"), b"<ComicInfo />");
+
+        crate::archive::writer::delete_comicinfo_xml(path).unwrap();
+        assert!(!is_cached(path));
+        assert!(
+            cached_zip_archive(path)
+                .unwrap()
+                .by_name("ComicInfo.xml")
+                .is_err()
+        );
+    }
+}
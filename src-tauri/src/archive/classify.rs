@@ -0,0 +1,489 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use image::GenericImageView;
+use serde::{Deserialize, Serialize};
+
+use super::analysis::{ImageInfo, SpreadDetectionOptions, UnreadableImage, find_spreads};
+use super::backend::open_backend;
+use super::order::sorted_image_files;
+use super::reader::read_archive;
+use super::types::{ErrorResponse, ReadArchiveError, ToErrorResponse};
+use super::writer::{PageSettings, described_page_settings};
+use crate::comicinfo::{ComicPageInfo, ComicPageType};
+
+/// Images are reduced to this size before their pixels are measured.
+const SAMPLE_SIZE: u32 = 64;
+
+/// Thresholds for [`classify_pages_impl`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct PageClassificationOptions {
+    /// Maximum standard deviation of the brightness, on a 0–255 scale, of a
+    /// near-blank page.
+    pub blank_max_deviation: f64,
+    /// Number of story pages on each side a page is compared with to find
+    /// advertisements.
+    pub outlier_window: usize,
+    /// Relative difference in aspect ratio from the surrounding pages that
+    /// marks a page as an advertisement.
+    pub aspect_tolerance: f64,
+    /// Difference in colourfulness, on a 0–1 scale, from the surrounding pages
+    /// that marks a page as an advertisement.
+    pub colour_tolerance: f64,
+}
+
+impl Default for PageClassificationOptions {
+    fn default() -> Self {
+        Self {
+            blank_max_deviation: 6.0,
+            outlier_window: 3,
+            aspect_tolerance: 0.15,
+            colour_tolerance: 0.2,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassificationReason {
+    FirstPage,
+    LastPage,
+    Blank,
+    Outlier,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PageTypeSuggestion {
+    pub file_name: String,
+    pub reason: ClassificationReason,
+    /// The page's current settings with the suggested type.
+    pub settings: PageSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PageClassificationReport {
+    pub suggestions: Vec<PageTypeSuggestion>,
+    /// The settings of every described page with all suggestions applied,
+    /// which `save_page_settings` takes as they are.
+    pub page_settings: HashMap<String, PageSettings>,
+    pub unreadable: Vec<UnreadableImage>,
+}
+
+/// What a page looks like, measured on a downscaled copy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PageFeatures {
+    width: u32,
+    height: u32,
+    /// Standard deviation of the brightness, 0–255.
+    deviation: f64,
+    /// Mean difference between the strongest and weakest channel, 0–1.
+    colourfulness: f64,
+}
+
+impl PageFeatures {
+    fn aspect_ratio(&self) -> f64 {
+        self.width as f64 / self.height.max(1) as f64
+    }
+}
+
+fn measure(data: &[u8]) -> Result<PageFeatures, String> {
+    let image = image::load_from_memory(data).map_err(|e| e.to_string())?;
+    let (width, height) = image.dimensions();
+    let sample = image.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_rgb8();
+
+    let pixels = sample.pixels().count().max(1) as f64;
+    let mut luma_sum = 0.0;
+    let mut luma_squares = 0.0;
+    let mut chroma_sum = 0.0;
+
+    for pixel in sample.pixels() {
+        let [r, g, b] = pixel.0.map(f64::from);
+        let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+        luma_sum += luma;
+        luma_squares += luma * luma;
+        chroma_sum += (r.max(g).max(b) - r.min(g).min(b)) / 255.0;
+    }
+
+    let mean = luma_sum / pixels;
+
+    Ok(PageFeatures {
+        width,
+        height,
+        deviation: (luma_squares / pixels - mean * mean).max(0.0).sqrt(),
+        colourfulness: chroma_sum / pixels,
+    })
+}
+
+fn read_features(
+    path: &str,
+    file_names: &[String],
+) -> Result<(HashMap<String, PageFeatures>, Vec<UnreadableImage>), ReadArchiveError> {
+    let backend = open_backend(path)?;
+    let features = Mutex::new(HashMap::new());
+    let unreadable = Mutex::new(Vec::new());
+
+    let on_error = |file_name: String, message: String| {
+        unreadable
+            .lock()
+            .unwrap()
+            .push(UnreadableImage { file_name, message });
+    };
+
+    let on_data = |file_name: String, data: Vec<u8>| match measure(&data) {
+        Ok(measured) => {
+            features.lock().unwrap().insert(file_name, measured);
+        }
+        Err(message) => on_error(file_name, message),
+    };
+
+    backend.read_files(path, file_names, &on_data, &on_error);
+
+    let mut unreadable = unreadable.into_inner().unwrap();
+    unreadable.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+    Ok((features.into_inner().unwrap(), unreadable))
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+
+    if values.len() % 2 == 0 {
+        Some((values[middle - 1] + values[middle]) / 2.0)
+    } else {
+        Some(values[middle])
+    }
+}
+
+/// Decides the type of every page that stands out.
+///
+/// The first page is the front cover. A near-blank page is deleted, even when
+/// it is the last page; otherwise the last page is the back cover. A page that
+/// differs in shape or colour from the story pages around it is an
+/// advertisement. Spreads are not compared, since they are meant to be wider.
+fn classify(
+    sorted: &[String],
+    features: &HashMap<String, PageFeatures>,
+    options: &PageClassificationOptions,
+) -> Vec<(usize, ClassificationReason, ComicPageType)> {
+    let features: Vec<Option<PageFeatures>> =
+        sorted.iter().map(|f| features.get(f).copied()).collect();
+    let last = sorted.len().saturating_sub(1);
+
+    let blank: Vec<bool> = features
+        .iter()
+        .map(|f| f.is_some_and(|f| f.deviation <= options.blank_max_deviation))
+        .collect();
+
+    let sizes: HashMap<String, ImageInfo> = sorted
+        .iter()
+        .zip(&features)
+        .filter_map(|(file_name, f)| {
+            f.map(|f| {
+                let info = ImageInfo {
+                    width: f.width as i32,
+                    height: f.height as i32,
+                    size: 0,
+                };
+                (file_name.clone(), info)
+            })
+        })
+        .collect();
+    let spreads: HashSet<usize> = find_spreads(sorted, &sizes, &SpreadDetectionOptions::default())
+        .into_iter()
+        .map(|(index, _)| index)
+        .collect();
+
+    let is_story =
+        |index: usize| index != 0 && index != last && !blank[index] && !spreads.contains(&index);
+
+    let mut classified = Vec::new();
+
+    for (index, page) in features.iter().enumerate() {
+        if index == 0 {
+            classified.push((
+                index,
+                ClassificationReason::FirstPage,
+                ComicPageType::FrontCover,
+            ));
+            continue;
+        }
+
+        if blank[index] {
+            classified.push((index, ClassificationReason::Blank, ComicPageType::Deleted));
+            continue;
+        }
+
+        if index == last {
+            classified.push((
+                index,
+                ClassificationReason::LastPage,
+                ComicPageType::BackCover,
+            ));
+            continue;
+        }
+
+        let Some(page) = page.filter(|_| is_story(index)) else {
+            continue;
+        };
+
+        let before = (0..index)
+            .rev()
+            .filter(|&i| is_story(i))
+            .take(options.outlier_window);
+        let after = (index + 1..=last)
+            .filter(|&i| is_story(i))
+            .take(options.outlier_window);
+        let neighbours: Vec<PageFeatures> =
+            before.chain(after).filter_map(|i| features[i]).collect();
+
+        if neighbours.len() < 2 {
+            continue;
+        }
+
+        let aspect = median(neighbours.iter().map(|n| n.aspect_ratio()).collect()).unwrap();
+        let colour = median(neighbours.iter().map(|n| n.colourfulness).collect()).unwrap();
+
+        let differs_in_shape =
+            (page.aspect_ratio() - aspect).abs() / aspect > options.aspect_tolerance;
+        let differs_in_colour = (page.colourfulness - colour).abs() > options.colour_tolerance;
+
+        if differs_in_shape || differs_in_colour {
+            classified.push((
+                index,
+                ClassificationReason::Outlier,
+                ComicPageType::Advertisement,
+            ));
+        }
+    }
+
+    classified
+}
+
+/// Suggests a page type for covers, near-blank pages and likely
+/// advertisements.
+///
+/// Nothing is saved. Each suggestion carries the page's current double page
+/// and bookmark settings. `save_page_settings` replaces the settings of every
+/// page, so suggestions have to be merged into the full page settings first;
+/// the report's `page_settings` is that merge with every suggestion accepted.
+pub fn classify_pages_impl(
+    path: String,
+    options: PageClassificationOptions,
+) -> Result<PageClassificationReport, ErrorResponse> {
+    let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
+
+    let sorted = sorted_image_files(&archive);
+    let (features, unreadable) =
+        read_features(&path, &sorted).map_err(|e| e.to_error_response())?;
+
+    let described = archive
+        .comic_info
+        .and_then(|ci| ci.pages)
+        .map(|p| p.page)
+        .unwrap_or_default();
+    let mut page_settings = described_page_settings(&sorted, &described);
+    let pages: HashMap<i32, ComicPageInfo> =
+        described.into_iter().map(|pg| (pg.image, pg)).collect();
+
+    let suggestions = classify(&sorted, &features, &options)
+        .into_iter()
+        .map(|(index, reason, page_type)| {
+            let image = index as i32;
+            let current = pages.get(&image);
+
+            PageTypeSuggestion {
+                file_name: sorted[index].clone(),
+                reason,
+                settings: PageSettings {
+                    page_type,
+                    double_page: current.is_some_and(|p| p.double_page),
+                    bookmark: current.map(|p| p.bookmark.clone()).unwrap_or_default(),
+                    image,
+                },
+            }
+        })
+        .collect::<Vec<PageTypeSuggestion>>();
+
+    for suggestion in &suggestions {
+        page_settings.insert(suggestion.file_name.clone(), suggestion.settings.clone());
+    }
+
+    Ok(PageClassificationReport {
+        suggestions,
+        page_settings,
+        unreadable,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::tests::write_cbz;
+    use crate::archive::writer::save_page_settings_impl;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use std::io::Cursor;
+
+    fn features(width: u32, height: u32, deviation: f64, colourfulness: f64) -> PageFeatures {
+        PageFeatures {
+            width,
+            height,
+            deviation,
+            colourfulness,
+        }
+    }
+
+    fn encode(image: RgbImage) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        image.write_to(&mut data, ImageFormat::Png).unwrap();
+        data.into_inner()
+    }
+
+    fn striped(width: u32, height: u32, colour: Rgb<u8>) -> Vec<u8> {
+        encode(RgbImage::from_fn(width, height, |x, _| {
+            if (x / 4) % 2 == 0 {
+                colour
+            } else {
+                Rgb([0, 0, 0])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_measure_blank_and_colourful_pages() {
+        let blank = measure(&encode(RgbImage::from_pixel(80, 120, Rgb([250, 250, 250])))).unwrap();
+        assert_eq!((blank.width, blank.height), (80, 120));
+        assert!(blank.deviation < 1.0);
+        assert!(blank.colourfulness < 0.01);
+
+        let colourful = measure(&striped(80, 120, Rgb([255, 0, 0]))).unwrap();
+        assert!(colourful.deviation > 20.0);
+        assert!(colourful.colourfulness > 0.4);
+
+        assert!(measure(b"not an image").is_err());
+    }
+
+    #[test]
+    fn test_classify_pages() {
+        let sorted: Vec<String> = (0..8).map(|i| format!("{:03}.png", i)).collect();
+        let story = features(100, 150, 60.0, 0.02);
+        let page_features = [
+            features(100, 150, 60.0, 0.6),
+            story,
+            story,
+            features(100, 150, 60.0, 0.5),
+            story,
+            features(200, 150, 60.0, 0.02),
+            story,
+            features(100, 150, 2.0, 0.0),
+        ];
+        let features: HashMap<String, PageFeatures> =
+            sorted.iter().cloned().zip(page_features).collect();
+
+        let classified = classify(&sorted, &features, &PageClassificationOptions::default());
+
+        assert_eq!(
+            classified,
+            vec![
+                (
+                    0,
+                    ClassificationReason::FirstPage,
+                    ComicPageType::FrontCover
+                ),
+                (
+                    3,
+                    ClassificationReason::Outlier,
+                    ComicPageType::Advertisement
+                ),
+                (7, ClassificationReason::Blank, ComicPageType::Deleted),
+            ]
+        );
+    }
+
+    fn create_cbz(path: &std::path::Path, comic_info: &[u8]) {
+        let page = striped(40, 60, Rgb([200, 200, 200]));
+
+        write_cbz(
+            path,
+            &[
+                ("001.png", &page),
+                ("002.png", &page),
+                ("003.png", &page),
+                ("ComicInfo.xml", comic_info),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_classify_pages_impl_keeps_current_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        create_cbz(
+            &path,
+            br#"<ComicInfo><Pages><Page Image="2" DoublePage="true" Bookmark="End" /></Pages></ComicInfo>"#,
+        );
+
+        let report = classify_pages_impl(
+            path.to_str().unwrap().to_string(),
+            PageClassificationOptions::default(),
+        )
+        .unwrap();
+
+        assert!(report.unreadable.is_empty());
+        assert_eq!(report.suggestions.len(), 2);
+        assert_eq!(report.suggestions[0].file_name, "001.png");
+        assert_eq!(
+            report.suggestions[0].settings.page_type,
+            ComicPageType::FrontCover
+        );
+
+        let back = &report.suggestions[1];
+        assert_eq!(back.reason, ClassificationReason::LastPage);
+        assert_eq!(back.settings.page_type, ComicPageType::BackCover);
+        assert_eq!(back.settings.image, 2);
+        assert!(back.settings.double_page);
+        assert_eq!(back.settings.bookmark, "End");
+    }
+
+    #[test]
+    fn test_saving_the_classified_page_settings_keeps_other_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        create_cbz(
+            &path,
+            br#"<ComicInfo><Pages><Page Image="1" Type="Story" Bookmark="Middle" ImageWidth="40" ImageHeight="60" /><Page Image="2" DoublePage="true" /></Pages></ComicInfo>"#,
+        );
+        let path = path.to_str().unwrap().to_string();
+
+        let report =
+            classify_pages_impl(path.clone(), PageClassificationOptions::default()).unwrap();
+        assert_eq!(report.suggestions.len(), 2);
+        assert_eq!(report.page_settings.len(), 3);
+
+        save_page_settings_impl(path.clone(), report.page_settings).unwrap();
+
+        let pages = read_archive(&path)
+            .unwrap()
+            .comic_info
+            .unwrap()
+            .pages
+            .unwrap()
+            .page;
+        let types: Vec<_> = pages.iter().map(|p| p.effective_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                ComicPageType::FrontCover,
+                ComicPageType::Story,
+                ComicPageType::BackCover
+            ]
+        );
+        assert_eq!(pages[1].bookmark, "Middle");
+        assert_eq!((pages[1].image_width, pages[1].image_height), (40, 60));
+        assert!(pages[2].double_page);
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs;
+use std::io::{BufReader, BufWriter, Write};
+
+use serde::{Deserialize, Serialize};
+
+use super::atomic_write::AtomicFile;
+use super::backend::invalidate_zip_archive;
+use super::manager::suppress_next_archive_event;
+use super::pages::ensure_zip;
+use super::types::{ErrorResponse, ToErrorResponse, WriteArchiveError, is_junk_entry};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CleanArchiveReport {
+    /// Junk entries found in the archive, removed unless `dry_run` is set.
+    pub removed: Vec<String>,
+    pub dry_run: bool,
+}
+
+fn junk_entries(path: &str) -> Result<Vec<String>, WriteArchiveError> {
+    let file = fs::File::open(path).map_err(WriteArchiveError::Io)?;
+    let archive = zip::ZipArchive::new(BufReader::new(file)).map_err(WriteArchiveError::Zip)?;
+
+    Ok(archive
+        .file_names()
+        .filter(|name| is_junk_entry(name))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Copies every entry that is not junk into a new archive, without
+/// decompressing it.
+fn remove_junk(path: &str) -> Result<(), WriteArchiveError> {
+    let mut output = AtomicFile::create(path).map_err(WriteArchiveError::Io)?;
+
+    {
+        let original_file = fs::File::open(path).map_err(WriteArchiveError::Io)?;
+        let mut original_archive =
+            zip::ZipArchive::new(BufReader::new(original_file)).map_err(WriteArchiveError::Zip)?;
+
+        let mut new_archive = zip::ZipWriter::new(BufWriter::new(output.as_file_mut()));
+
+        for i in 0..original_archive.len() {
+            let file = original_archive
+                .by_index_raw(i)
+                .map_err(WriteArchiveError::Zip)?;
+
+            if !is_junk_entry(file.name()) {
+                new_archive
+                    .raw_copy_file(file)
+                    .map_err(WriteArchiveError::Zip)?;
+            }
+        }
+
+        new_archive
+            .finish()
+            .map_err(WriteArchiveError::Zip)?
+            .flush()
+            .map_err(WriteArchiveError::Io)?;
+    }
+
+    output.commit().map_err(WriteArchiveError::Io)?;
+    invalidate_zip_archive(path);
+    Ok(())
+}
+
+/// Removes operating system metadata such as `__MACOSX/`, `._` resource forks,
+/// `.DS_Store` and `Thumbs.db` from a CBZ archive.
+///
+/// With `dry_run` the junk entries are only listed.
+pub fn clean_archive_impl(
+    path: String,
+    dry_run: bool,
+) -> Result<CleanArchiveReport, ErrorResponse> {
+    ensure_zip(&path).map_err(|e| e.to_error_response())?;
+
+    let removed = junk_entries(&path).map_err(|e| e.to_error_response())?;
+
+    if !dry_run && !removed.is_empty() {
+        suppress_next_archive_event(&path);
+        remove_junk(&path).map_err(|e| e.to_error_response())?;
+    }
+
+    Ok(CleanArchiveReport { removed, dry_run })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::reader::read_archive;
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn test_clean_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        {
+            let file = fs::File::create(&path).unwrap();
+            let mut zip = zip::ZipWriter::new(file);
+            let options = FileOptions::<()>::default();
+
+            zip.add_directory("__MACOSX/", options).unwrap();
+            for name in [
+                "001.jpg",
+                "__MACOSX/._001.jpg",
+                "chapter/.DS_Store",
+                "Thumbs.db",
+            ] {
+                zip.start_file(name, options).unwrap();
+                zip.write_all(b"data").unwrap();
+            }
+
+            zip.finish().unwrap();
+        }
+        let path = path.to_str().unwrap().to_string();
+
+        let report = clean_archive_impl(path.clone(), true).unwrap();
+        assert_eq!(
+            report.removed,
+            vec![
+                "__MACOSX/",
+                "__MACOSX/._001.jpg",
+                "chapter/.DS_Store",
+                "Thumbs.db"
+            ]
+        );
+        assert_eq!(read_archive(&path).unwrap().files.len(), 5);
+
+        let report = clean_archive_impl(path.clone(), false).unwrap();
+        assert_eq!(report.removed.len(), 4);
+
+        let names: Vec<String> = read_archive(&path)
+            .unwrap()
+            .files
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(names, vec!["001.jpg"]);
+    }
+}
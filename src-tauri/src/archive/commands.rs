@@ -1,14 +1,44 @@
 use crate::archive::manager::start_archive_watch_for_creation;
 
+use super::analysis::{
+    PageDimensionsReport, SpreadDetectionOptions, SpreadDetectionReport,
+    detect_double_page_spreads_impl, populate_page_dimensions_impl,
+};
+use super::classify::{PageClassificationOptions, PageClassificationReport, classify_pages_impl};
+use super::clean::{CleanArchiveReport, clean_archive_impl};
+use super::convert::{ConversionReport, ConvertProgressEvent, convert_to_cbz_impl};
+use super::fingerprint::{
+    ArchiveFingerprint, Fingerprinted, archive_fingerprint, archive_write_lock, check_fingerprint,
+    with_fingerprint, write_unchanged,
+};
+use super::history::{
+    DiffLine, HistoryChange, HistoryEntry, comicinfo_history, diff_versions, restore_version,
+    track_comicinfo_change,
+};
+use super::image_format::{
+    DisplayableImage, ImageFormatInfo, ThumbnailOptions, supported_formats, to_displayable,
+    to_thumbnail,
+};
 use super::manager::{start_archive_watcher, stop_archive_watcher};
-use super::reader::{get_file_data, read_archive, stream_file_data_from_archive};
-use super::types::{LoadCbzResponse, ToErrorResponse, is_image_file};
+use super::order::{PageOrderWarning, check_page_order, sorted_image_files};
+use super::pages::{insert_pages_impl, remove_pages_impl, reorder_pages_impl, replace_page_impl};
+use super::protocol::{allow_archive, forget_archive};
+use super::reader::{
+    entry_checksums, get_file_data, read_archive, read_archive_lenient, read_comicinfo_xml,
+    stream_file_data_from_archive,
+};
+use super::stream::{self, StreamHandle, prioritise};
+use super::thumbnail_cache::{ThumbnailCache, ThumbnailCacheStats, ThumbnailKey, thumbnail_cache};
+use super::types::{
+    Archive, ErrorDetails, ErrorResponse, ErrorResponseType, LoadCbzResponse, ToErrorResponse,
+};
 use super::writer::{delete_comicinfo_xml, save_comicinfo_xml_impl, save_page_settings_impl};
+use crate::comicinfo::recorded_page_filenames;
 use base64::{Engine, engine::general_purpose::STANDARD as BASE64_STANDARD};
 use log::debug;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::io::Read;
+use std::sync::PoisonError;
 use tauri::ipc::Channel;
 
 #[derive(Clone, Serialize)]
@@ -19,14 +49,126 @@ pub enum StreamProgressEvent {
     },
     Preview {
         file_name: String,
-        data_raw: Vec<u8>,
-        data_base64: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data_raw: Option<Vec<u8>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data_base64: Option<String>,
+        mime_type: String,
     },
     Error {
         file_name: String,
         message: String,
     },
-    Finished,
+    Finished {
+        cancelled: bool,
+    },
+}
+
+/// How the data of streamed previews is sent over IPC.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewEncoding {
+    Raw,
+    #[default]
+    Base64,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct StreamFileDataOptions {
+    pub encoding: PreviewEncoding,
+    /// Downscales each page before sending it when set.
+    pub thumbnail: Option<ThumbnailOptions>,
+    /// Lets the stream be stopped through `cancel_stream`.
+    pub stream_id: Option<String>,
+    /// Pages to send first, such as the ones in view.
+    pub priority: Vec<String>,
+}
+
+/// Turns streamed entries into previews. Thumbnails go through the
+/// thumbnail cache when there is one.
+pub(crate) struct PreviewStream<'a> {
+    path: String,
+    options: StreamFileDataOptions,
+    cache: Option<&'a ThumbnailCache>,
+    checksums: HashMap<String, u32>,
+}
+
+impl<'a> PreviewStream<'a> {
+    pub(crate) fn new(
+        path: String,
+        options: StreamFileDataOptions,
+        cache: Option<&'a ThumbnailCache>,
+    ) -> Self {
+        let checksums = if options.thumbnail.is_some() && cache.is_some() {
+            entry_checksums(&path)
+        } else {
+            HashMap::new()
+        };
+
+        Self {
+            path,
+            options,
+            cache,
+            checksums,
+        }
+    }
+
+    fn thumbnail_key(&self, crc32: u32) -> Option<ThumbnailKey> {
+        let thumbnail = self.options.thumbnail.as_ref()?;
+        Some(ThumbnailKey::new(&self.path, crc32, thumbnail))
+    }
+
+    /// Returns the cached thumbnail of an entry whose checksum is known
+    /// without extracting it.
+    pub(crate) fn cached(&self, file_name: &str) -> Option<DisplayableImage> {
+        let key = self.thumbnail_key(*self.checksums.get(file_name)?)?;
+        self.cache?.get(&key)
+    }
+
+    fn preview(&self, file_name: &str, data: Vec<u8>) -> Result<DisplayableImage, String> {
+        let Some(thumbnail) = &self.options.thumbnail else {
+            return to_displayable(file_name, data);
+        };
+
+        let Some(cache) = self.cache else {
+            return to_thumbnail(file_name, data, thumbnail);
+        };
+
+        let crc32 = self
+            .checksums
+            .get(file_name)
+            .copied()
+            .unwrap_or_else(|| crc32fast::hash(&data));
+        let key = ThumbnailKey::new(&self.path, crc32, thumbnail);
+
+        cache.get_or_insert_with(&key, || to_thumbnail(file_name, data, thumbnail))
+    }
+
+    pub(crate) fn event(&self, file_name: String, data: Vec<u8>) -> StreamProgressEvent {
+        match self.preview(&file_name, data) {
+            Ok(preview) => self.preview_event(file_name, preview),
+            Err(message) => StreamProgressEvent::Error { file_name, message },
+        }
+    }
+
+    pub(crate) fn preview_event(
+        &self,
+        file_name: String,
+        preview: DisplayableImage,
+    ) -> StreamProgressEvent {
+        let (data_raw, data_base64) = match self.options.encoding {
+            PreviewEncoding::Raw => (Some(preview.data), None),
+            PreviewEncoding::Base64 => (None, Some(BASE64_STANDARD.encode(&preview.data))),
+        };
+
+        StreamProgressEvent::Preview {
+            file_name,
+            data_raw,
+            data_base64,
+            mime_type: preview.mime_type.to_string(),
+        }
+    }
 }
 
 #[tauri::command]
@@ -36,26 +178,28 @@ pub fn load_cbz(app: tauri::AppHandle, path: String) -> LoadCbzResponse {
 
 // Internal implementation that can be called with or without AppHandle for testing
 pub(crate) fn load_cbz_impl(app: Option<tauri::AppHandle>, path: String) -> LoadCbzResponse {
-    let archive = match read_archive(&path) {
-        Ok(archive) => archive,
+    let (archive, diagnostics) = match read_archive_lenient(&path) {
+        Ok(result) => result,
         Err(err) => {
             return LoadCbzResponse {
                 image_files: vec![],
                 comic_info: None,
                 error: Some(err.to_error_response()),
+                diagnostics: vec![],
+                page_order_warning: None,
+                fingerprint: None,
             };
         }
     };
 
-    let image_files = archive
-        .files
-        .iter()
-        .filter(|f| is_image_file(&f.name))
-        .map(|f| f.name.clone())
-        .collect::<Vec<_>>();
+    let sorted = sorted_image_files(&archive);
+    let page_order_warning = page_order_warning(&path, &archive, &sorted);
+
+    allow_archive(&path);
 
-    let mut sorted = image_files;
-    sorted.sort();
+    let fingerprint = archive_fingerprint(&path)
+        .inspect_err(|e| debug!("Failed to fingerprint {}: {}", path, e))
+        .ok();
 
     let comic_info = archive.comic_info;
     let error = None; // If validation is needed, handle here
@@ -71,9 +215,29 @@ pub(crate) fn load_cbz_impl(app: Option<tauri::AppHandle>, path: String) -> Load
         image_files: sorted,
         comic_info,
         error,
+        diagnostics,
+        page_order_warning,
+        fingerprint,
     }
 }
 
+/// Warns when the archive's ComicInfo.xml was written against a different
+/// page order than `sorted`.
+fn page_order_warning(
+    path: &str,
+    archive: &Archive,
+    sorted: &[String],
+) -> Option<PageOrderWarning> {
+    let comic_info = archive.comic_info.as_ref()?;
+    let recorded = read_comicinfo_xml(path)
+        .ok()
+        .flatten()
+        .map(|xml| recorded_page_filenames(&xml))
+        .unwrap_or_default();
+
+    check_page_order(comic_info, &recorded, sorted)
+}
+
 #[tauri::command]
 pub fn get_cbz_file_data(
     path: String,
@@ -92,8 +256,8 @@ pub fn get_cbz_file_data(
 }
 
 #[tauri::command]
-pub fn get_comicinfo(path: String) -> Result<Option<crate::comicinfo::ComicInfo>, String> {
-    let archive = read_archive(&path).map_err(|e| e.to_string())?;
+pub fn get_comicinfo(path: String) -> Result<Option<crate::comicinfo::ComicInfo>, ErrorResponse> {
+    let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
     Ok(archive.comic_info)
 }
 
@@ -101,64 +265,270 @@ pub fn get_comicinfo(path: String) -> Result<Option<crate::comicinfo::ComicInfo>
 pub async fn save_page_settings(
     path: String,
     page_settings: HashMap<String, super::writer::PageSettings>,
-) -> Result<Vec<crate::comicinfo::ComicPageInfo>, String> {
-    let path_clone = path.clone();
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<Vec<crate::comicinfo::ComicPageInfo>>, ErrorResponse> {
     tauri::async_runtime::spawn_blocking(move || {
-        save_page_settings_impl(path_clone, page_settings)
+        write_unchanged(&path, &expected_fingerprint, || {
+            track_comicinfo_change(&path, HistoryChange::SavePageSettings, || {
+                save_page_settings_impl(path.clone(), page_settings)
+            })?;
+
+            let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
+            let pages = archive
+                .comic_info
+                .and_then(|ci| ci.pages)
+                .map(|pages| pages.page)
+                .unwrap_or_default();
+
+            Ok(pages)
+        })
     })
     .await
-    .map_err(|e| e.to_string())??;
+    .map_err(|e| e.to_string())?
+}
 
-    let archive = super::reader::read_archive(&path).map_err(|e| e.to_string())?;
-    let pages = archive
-        .comic_info
-        .and_then(|ci| ci.pages)
-        .map(|pages| pages.page)
-        .unwrap_or_default();
+#[tauri::command]
+pub async fn populate_page_dimensions(
+    path: String,
+    include_all_images: bool,
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<PageDimensionsReport>, ErrorResponse> {
+    tauri::async_runtime::spawn_blocking(move || {
+        write_unchanged(&path, &expected_fingerprint, || {
+            track_comicinfo_change(&path, HistoryChange::PopulatePageDimensions, || {
+                populate_page_dimensions_impl(path.clone(), include_all_images)
+            })
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn detect_double_page_spreads(
+    path: String,
+    options: Option<SpreadDetectionOptions>,
+    preview: bool,
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<SpreadDetectionReport>, ErrorResponse> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let detect =
+            || detect_double_page_spreads_impl(path.clone(), options.unwrap_or_default(), preview);
 
-    Ok(pages)
+        if preview {
+            with_fingerprint(&path, detect()?)
+        } else {
+            write_unchanged(&path, &expected_fingerprint, || {
+                track_comicinfo_change(&path, HistoryChange::DetectDoublePageSpreads, detect)
+            })
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
-pub fn get_raw_comicinfo_xml(path: String) -> Result<Option<String>, String> {
-    let file = std::fs::File::open(&path).map_err(|e| e.to_string())?;
-    let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+pub async fn classify_pages(
+    path: String,
+    options: Option<PageClassificationOptions>,
+) -> Result<PageClassificationReport, ErrorResponse> {
+    tauri::async_runtime::spawn_blocking(move || {
+        classify_pages_impl(path, options.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn reorder_pages(
+    path: String,
+    order: Vec<String>,
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<Vec<String>>, ErrorResponse> {
+    tauri::async_runtime::spawn_blocking(move || {
+        write_unchanged(&path, &expected_fingerprint, || {
+            track_comicinfo_change(&path, HistoryChange::ReorderPages, || {
+                reorder_pages_impl(path.clone(), order)
+            })
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
 
-    let result = archive.by_name("ComicInfo.xml");
-    match result {
-        Ok(mut comic_info_file) => {
-            let mut xml_content = String::new();
-            comic_info_file
-                .read_to_string(&mut xml_content)
-                .map_err(|e| e.to_string())?;
-            Ok(Some(xml_content))
+#[tauri::command]
+pub async fn remove_pages(
+    path: String,
+    file_names: Vec<String>,
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<Vec<String>>, ErrorResponse> {
+    tauri::async_runtime::spawn_blocking(move || {
+        write_unchanged(&path, &expected_fingerprint, || {
+            track_comicinfo_change(&path, HistoryChange::RemovePages, || {
+                remove_pages_impl(path.clone(), file_names)
+            })
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn insert_pages(
+    path: String,
+    position: usize,
+    file_paths: Vec<String>,
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<Vec<String>>, ErrorResponse> {
+    tauri::async_runtime::spawn_blocking(move || {
+        write_unchanged(&path, &expected_fingerprint, || {
+            track_comicinfo_change(&path, HistoryChange::InsertPages, || {
+                insert_pages_impl(path.clone(), position, file_paths)
+            })
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn replace_page(
+    path: String,
+    file_name: String,
+    file_path: String,
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<Vec<String>>, ErrorResponse> {
+    tauri::async_runtime::spawn_blocking(move || {
+        write_unchanged(&path, &expected_fingerprint, || {
+            track_comicinfo_change(&path, HistoryChange::ReplacePage, || {
+                replace_page_impl(path.clone(), file_name, file_path)
+            })
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn clean_archive(
+    path: String,
+    dry_run: bool,
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<CleanArchiveReport>, ErrorResponse> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let clean = || clean_archive_impl(path.clone(), dry_run);
+
+        if dry_run {
+            with_fingerprint(&path, clean()?)
+        } else {
+            write_unchanged(&path, &expected_fingerprint, || {
+                track_comicinfo_change(&path, HistoryChange::CleanArchive, clean)
+            })
         }
-        Err(zip::result::ZipError::FileNotFound) => Ok(None),
-        Err(e) => Err(e.to_string()),
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn get_supported_image_formats() -> Vec<ImageFormatInfo> {
+    supported_formats()
+}
+
+#[tauri::command]
+pub fn get_raw_comicinfo_xml(path: String) -> Result<Option<String>, ErrorResponse> {
+    read_comicinfo_xml(&path).map_err(|e| e.to_error_response())
+}
+
+#[tauri::command]
+pub fn save_comicinfo_xml(
+    path: String,
+    xml: String,
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<String>, ErrorResponse> {
+    write_unchanged(&path, &expected_fingerprint, || {
+        track_comicinfo_change(&path, HistoryChange::SaveComicInfoXml, || {
+            save_comicinfo_xml_impl(path.clone(), xml)
+        })
+    })
+}
+
+#[tauri::command]
+pub fn delete_cbz_comicinfo_xml(
+    path: String,
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<()>, ErrorResponse> {
+    write_unchanged(&path, &expected_fingerprint, || {
+        track_comicinfo_change(&path, HistoryChange::DeleteComicInfoXml, || {
+            delete_comicinfo_xml(&path)
+        })
+        .map_err(|e| e.to_error_response())
+    })
+}
+
+/// Lists the recorded versions of the archive's ComicInfo.xml, newest first.
+#[tauri::command]
+pub fn list_comicinfo_history(path: String) -> Result<Vec<HistoryEntry>, ErrorResponse> {
+    match comicinfo_history() {
+        Some(history) => history.list(&path).map_err(|e| e.to_error_response()),
+        None => Ok(Vec::new()),
     }
 }
 
 #[tauri::command]
-pub fn save_comicinfo_xml(path: String, xml: String) -> Result<String, String> {
-    save_comicinfo_xml_impl(path, xml)
+pub fn diff_comicinfo_versions(
+    path: String,
+    from: u64,
+    to: u64,
+) -> Result<Vec<DiffLine>, ErrorResponse> {
+    let history = comicinfo_history().ok_or_else(history_unavailable)?;
+    diff_versions(history, &path, from, to)
 }
 
+/// Writes a recorded version of ComicInfo.xml back into the archive and
+/// returns it; `None` when the version had no ComicInfo.xml.
 #[tauri::command]
-pub fn delete_cbz_comicinfo_xml(path: String) -> Result<(), String> {
-    delete_comicinfo_xml(&path)
+pub fn restore_comicinfo_version(
+    path: String,
+    version: u64,
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<Option<String>>, ErrorResponse> {
+    let history = comicinfo_history().ok_or_else(history_unavailable)?;
+    write_unchanged(&path, &expected_fingerprint, || {
+        restore_version(history, &path, version)
+    })
+}
+
+fn history_unavailable() -> ErrorResponse {
+    ErrorResponse::new(
+        ErrorResponseType::Other,
+        "ComicInfo history is not available",
+    )
+}
+
+fn watcher_failure(path: &str, message: impl Into<String>) -> ErrorResponse {
+    ErrorResponse::new(ErrorResponseType::WatcherFailure, message).with_details(
+        ErrorDetails::Path {
+            path: path.to_string(),
+        },
+    )
 }
 
 #[tauri::command]
-pub fn unload_cbz(path: String) -> Result<(), String> {
-    stop_archive_watcher(&path)
+pub fn unload_cbz(path: String) -> Result<(), ErrorResponse> {
+    forget_archive(&path);
+    stop_archive_watcher(&path).map_err(|e| watcher_failure(&path, e))
 }
 
 #[tauri::command]
-pub fn watch_for_creation(app: tauri::AppHandle, path: String) -> Result<(), String> {
+pub fn watch_for_creation(app: tauri::AppHandle, path: String) -> Result<(), ErrorResponse> {
     if start_archive_watch_for_creation(app, path.clone()) {
         Ok(())
     } else {
-        Err(format!("Failed to start watcher for {}", path))
+        Err(watcher_failure(
+            &path,
+            format!("Failed to start watcher for {}", path),
+        ))
     }
 }
 
@@ -166,8 +536,13 @@ pub fn watch_for_creation(app: tauri::AppHandle, path: String) -> Result<(), Str
 pub async fn stream_file_data(
     path: String,
     file_names: Vec<String>,
+    options: Option<StreamFileDataOptions>,
     on_event: Channel<StreamProgressEvent>,
 ) {
+    let options = options.unwrap_or_default();
+
+    let stream = StreamHandle::register(options.stream_id.clone());
+
     tauri::async_runtime::spawn_blocking(move || {
         let total = file_names.len();
         if let Err(e) = on_event.send(StreamProgressEvent::Started { total_files: total }) {
@@ -175,18 +550,36 @@ pub async fn stream_file_data(
             return;
         }
 
+        let file_names = prioritise(file_names, &options.priority);
+
         use std::sync::Arc;
         let on_event = Arc::new(on_event);
+        let previews = Arc::new(PreviewStream::new(path.clone(), options, thumbnail_cache()));
+
+        let mut uncached = Vec::new();
+        for file_name in file_names {
+            if stream.is_cancelled() {
+                break;
+            }
 
+            match previews.cached(&file_name) {
+                Some(preview) => {
+                    if let Err(e) = on_event.send(previews.preview_event(file_name, preview)) {
+                        debug!("Failed to send Preview event: {}", e);
+                    }
+                }
+                None => uncached.push(file_name),
+            }
+        }
+
+        let is_cancelled = stream.cancellation();
         let on_event_data = Arc::clone(&on_event);
-        let on_data = move |file_name, data: Vec<u8>| {
-            let data_base64 = BASE64_STANDARD.encode(&data);
-
-            if let Err(e) = on_event_data.send(StreamProgressEvent::Preview {
-                file_name,
-                data_raw: data,
-                data_base64,
-            }) {
+        let on_data = move |file_name: String, data: Vec<u8>| {
+            if is_cancelled() {
+                return;
+            }
+
+            if let Err(e) = on_event_data.send(previews.event(file_name, data)) {
                 debug!("Failed to send Preview event: {}", e);
             }
         };
@@ -198,17 +591,82 @@ pub async fn stream_file_data(
             }
         };
 
-        if let Err(e) = stream_file_data_from_archive(&path, file_names, on_data, on_error) {
-            let _ = on_event.send(StreamProgressEvent::Error {
-                file_name: path,
-                message: e.to_string(),
-            });
+        if !stream.is_cancelled() {
+            if let Err(e) = stream_file_data_from_archive(
+                &path,
+                uncached,
+                on_data,
+                on_error,
+                stream.cancellation(),
+            ) {
+                let _ = on_event.send(StreamProgressEvent::Error {
+                    file_name: path,
+                    message: e.to_string(),
+                });
+            }
         }
 
-        if let Err(e) = on_event.send(StreamProgressEvent::Finished) {
+        let cancelled = stream.is_cancelled();
+        if let Err(e) = on_event.send(StreamProgressEvent::Finished { cancelled }) {
             debug!("Failed to send Finished event: {}", e);
         }
     })
     .await
     .ok();
 }
+
+/// Stops a running `stream_file_data` call started with `stream_id`. Returns
+/// false when no such stream is running.
+#[tauri::command]
+pub fn cancel_stream(stream_id: String) -> bool {
+    stream::cancel_stream(&stream_id)
+}
+
+/// Removes every cached thumbnail, returning how many there were and their
+/// total size.
+#[tauri::command]
+pub fn clear_thumbnail_cache() -> Result<ThumbnailCacheStats, ErrorResponse> {
+    match thumbnail_cache() {
+        Some(cache) => cache.clear().map_err(|e| e.to_error_response()),
+        None => Ok(ThumbnailCacheStats::default()),
+    }
+}
+
+#[tauri::command]
+pub async fn convert_to_cbz(
+    path: String,
+    delete_source: bool,
+    dry_run: bool,
+    expected_fingerprint: ArchiveFingerprint,
+    on_event: Channel<ConvertProgressEvent>,
+) -> Result<ConversionReport, ErrorResponse> {
+    tauri::async_runtime::spawn_blocking(move || {
+        // The source may be deleted once converted, so it has to be the one
+        // the caller loaded.
+        let lock = archive_write_lock(&path);
+        let _guard = if dry_run {
+            None
+        } else {
+            let guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+            check_fingerprint(&path, &expected_fingerprint).map_err(|e| e.to_error_response())?;
+            Some(guard)
+        };
+
+        let send_event = |event| {
+            if let Err(e) = on_event.send(event) {
+                debug!("Failed to send conversion event: {}", e);
+            }
+        };
+
+        let report = convert_to_cbz_impl(&path, delete_source, dry_run, send_event)
+            .map_err(|e| e.to_error_response())?;
+
+        if report.source_deleted {
+            let _ = stop_archive_watcher(&path);
+        }
+
+        Ok(report)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,479 @@
+use super::atomic_write::AtomicFile;
+use super::backend::{ArchiveBackend, invalidate_zip_archive, open_backend};
+use super::reader::read_archive;
+use super::types::{Archive, ArchiveFormat, WriteArchiveError};
+use super::writer::populate_filenames_from_archive;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use zip::CompressionMethod;
+use zip::write::FileOptions;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "event", content = "data")]
+pub enum ConvertProgressEvent {
+    Started { total_files: usize },
+    Progress { file_name: String, completed: usize },
+    Finished { output_path: String },
+}
+
+/// What a conversion produced, or would produce when `dry_run` is set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConversionReport {
+    pub source_format: ArchiveFormat,
+    pub output_path: String,
+    /// Images in the order they are written to the CBZ.
+    pub image_files: Vec<String>,
+    /// Entries that are not images and are left out of the CBZ.
+    pub skipped_files: Vec<String>,
+    pub includes_comic_info: bool,
+    pub source_deleted: bool,
+    pub dry_run: bool,
+}
+
+/// The CBZ path next to the source: `comic.cbr` becomes `comic.cbz` and a
+/// folder `comic/` becomes `comic.cbz`.
+pub fn cbz_output_path(path: &str, format: ArchiveFormat) -> String {
+    match format {
+        ArchiveFormat::Directory => format!("{}.cbz", path.trim_end_matches(['/', '\\'])),
+        _ => Path::new(path)
+            .with_extension("cbz")
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+/// Business logic for converting a CBR, CB7, CBT or folder into a CBZ.
+///
+/// Images are stored in the same sorted order `load_cbz` shows them in and the
+/// parsed ComicInfo is carried over. The CBZ is written to a temporary file
+/// first and renamed into place once complete.
+pub fn convert_to_cbz_impl(
+    path: &str,
+    delete_source: bool,
+    dry_run: bool,
+    on_event: impl Fn(ConvertProgressEvent) + Send + Sync,
+) -> Result<ConversionReport, WriteArchiveError> {
+    let backend = open_backend(path)?;
+    let source_format = backend.format();
+
+    if source_format == ArchiveFormat::Zip {
+        return Err(WriteArchiveError::AlreadyInFormat(ArchiveFormat::Zip));
+    }
+
+    let output_path = cbz_output_path(path, source_format);
+    if Path::new(&output_path).exists() {
+        return Err(WriteArchiveError::OutputExists(output_path));
+    }
+
+    let archive = read_archive(path)?;
+
+    let image_files = archive.image_files.clone();
+    let skipped_files = archive
+        .files
+        .iter()
+        .map(|f| f.name.clone())
+        .filter(|name| name != "ComicInfo.xml" && !image_files.contains(name))
+        .collect();
+
+    let mut report = ConversionReport {
+        source_format,
+        output_path,
+        image_files,
+        skipped_files,
+        includes_comic_info: archive.comic_info.is_some(),
+        source_deleted: false,
+        dry_run,
+    };
+
+    if dry_run {
+        return Ok(report);
+    }
+
+    let comic_info_xml = match archive.comic_info.clone() {
+        Some(mut comic_info) => {
+            populate_filenames_from_archive(&mut comic_info, &archive);
+            Some(comic_info.to_xml().map_err(WriteArchiveError::ComicInfo)?)
+        }
+        None => None,
+    };
+
+    on_event(ConvertProgressEvent::Started {
+        total_files: report.image_files.len(),
+    });
+
+    write_cbz(
+        backend,
+        path,
+        &report.output_path,
+        &report.image_files,
+        comic_info_xml.as_deref(),
+        &on_event,
+    )?;
+
+    if delete_source {
+        delete_source_files(path, source_format, &archive)?;
+        report.source_deleted = true;
+    }
+
+    on_event(ConvertProgressEvent::Finished {
+        output_path: report.output_path.clone(),
+    });
+
+    Ok(report)
+}
+
+fn write_cbz(
+    backend: &dyn ArchiveBackend,
+    path: &str,
+    output_path: &str,
+    image_files: &[String],
+    comic_info_xml: Option<&str>,
+    on_event: &(impl Fn(ConvertProgressEvent) + Send + Sync),
+) -> Result<(), WriteArchiveError> {
+    let mut output = AtomicFile::create(output_path).map_err(WriteArchiveError::Io)?;
+
+    write_cbz_entries(
+        backend,
+        path,
+        output.as_file_mut(),
+        image_files,
+        comic_info_xml,
+        on_event,
+    )?;
+
+    output.commit().map_err(WriteArchiveError::Io)?;
+    invalidate_zip_archive(output_path);
+    Ok(())
+}
+
+/// Copies the images into the CBZ in page order while the backend reads them.
+///
+/// A page that arrives before the ones ahead of it waits in memory until they
+/// are written, so only pages the source stores out of page order are held at
+/// once. Reading stops at the first page that cannot be read.
+fn write_cbz_entries(
+    backend: &dyn ArchiveBackend,
+    path: &str,
+    output: &mut fs::File,
+    image_files: &[String],
+    comic_info_xml: Option<&str>,
+    on_event: &(impl Fn(ConvertProgressEvent) + Send + Sync),
+) -> Result<(), WriteArchiveError> {
+    let mut new_archive = zip::ZipWriter::new(BufWriter::new(output));
+    let options = FileOptions::<()>::default().compression_method(CompressionMethod::Stored);
+
+    let failed = AtomicBool::new(false);
+    let (sender, pages) = mpsc::channel();
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let sender = sender;
+
+            backend.read_files_cancellable(
+                path,
+                image_files,
+                &|file_name, data| {
+                    let _ = sender.send(Ok((file_name, data)));
+                },
+                &|file_name, message| {
+                    let _ = sender.send(Err(WriteArchiveError::EntryUnreadable {
+                        file_name,
+                        message,
+                    }));
+                },
+                &|| failed.load(Ordering::Relaxed),
+            );
+        });
+
+        let written = write_pages_in_order(&mut new_archive, pages, image_files, on_event);
+        if written.is_err() {
+            failed.store(true, Ordering::Relaxed);
+        }
+        written
+    })?;
+
+    if let Some(xml_content) = comic_info_xml {
+        new_archive
+            .start_file("ComicInfo.xml", options)
+            .map_err(WriteArchiveError::Zip)?;
+        new_archive
+            .write_all(xml_content.as_bytes())
+            .map_err(WriteArchiveError::Io)?;
+    }
+
+    new_archive
+        .finish()
+        .map_err(WriteArchiveError::Zip)?
+        .flush()
+        .map_err(WriteArchiveError::Io)?;
+
+    Ok(())
+}
+
+fn write_pages_in_order(
+    new_archive: &mut zip::ZipWriter<impl Write + io::Seek>,
+    pages: mpsc::Receiver<Result<(String, Vec<u8>), WriteArchiveError>>,
+    image_files: &[String],
+    on_event: &impl Fn(ConvertProgressEvent),
+) -> Result<(), WriteArchiveError> {
+    let options = FileOptions::<()>::default().compression_method(CompressionMethod::Stored);
+    let positions: HashMap<&str, usize> = image_files
+        .iter()
+        .enumerate()
+        .map(|(position, file_name)| (file_name.as_str(), position))
+        .collect();
+    let mut waiting = HashMap::new();
+    let mut written = 0;
+
+    for page in pages {
+        let (file_name, data) = page?;
+
+        if let Some(&position) = positions.get(file_name.as_str()) {
+            waiting.insert(position, data);
+        }
+
+        while let Some(data) = waiting.remove(&written) {
+            let file_name = &image_files[written];
+
+            new_archive
+                .start_file(file_name, options)
+                .map_err(WriteArchiveError::Zip)?;
+            new_archive
+                .write_all(&data)
+                .map_err(WriteArchiveError::Io)?;
+            written += 1;
+
+            on_event(ConvertProgressEvent::Progress {
+                file_name: file_name.clone(),
+                completed: written,
+            });
+        }
+    }
+
+    match image_files.get(written) {
+        Some(file_name) => Err(WriteArchiveError::EntryUnreadable {
+            file_name: file_name.clone(),
+            message: "the entry was not found in the archive".to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Removes the converted source. For folders only the images and the
+/// ComicInfo.xml sidecar are removed, together with any folders left empty, so
+/// unrelated files are never lost.
+fn delete_source_files(
+    path: &str,
+    format: ArchiveFormat,
+    archive: &Archive,
+) -> Result<(), WriteArchiveError> {
+    if format != ArchiveFormat::Directory {
+        return fs::remove_file(path).map_err(WriteArchiveError::Io);
+    }
+
+    let root = Path::new(path);
+    let mut folders = Vec::new();
+
+    for file in &archive.files {
+        if !archive.image_files.contains(&file.name) && file.name != "ComicInfo.xml" {
+            continue;
+        }
+
+        let file_path = root.join(&file.name);
+        fs::remove_file(&file_path).map_err(WriteArchiveError::Io)?;
+
+        folders.extend(
+            file_path
+                .ancestors()
+                .skip(1)
+                .take_while(|p| *p != root)
+                .map(Path::to_path_buf),
+        );
+    }
+
+    folders.sort();
+    folders.dedup();
+    folders.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+
+    for folder in folders {
+        let _ = fs::remove_dir(folder);
+    }
+    let _ = fs::remove_dir(root);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comicinfo::ComicInfo;
+    use std::io::Read;
+    use std::sync::Mutex;
+
+    fn create_tar(path: &Path, files: &[(&str, &[u8])]) {
+        let mut builder = tar::Builder::new(fs::File::create(path).unwrap());
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    fn zip_entries(path: &str) -> Vec<(String, Vec<u8>)> {
+        let mut archive = zip::ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+        (0..archive.len())
+            .map(|i| {
+                let mut file = archive.by_index(i).unwrap();
+                let mut data = Vec::new();
+                file.read_to_end(&mut data).unwrap();
+                (file.name().to_string(), data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cbz_output_path() {
+        assert_eq!(
+            cbz_output_path("/comics/vol.1.cbr", ArchiveFormat::Rar),
+            "/comics/vol.1.cbz"
+        );
+        assert_eq!(
+            cbz_output_path("/comics/vol.1/", ArchiveFormat::Directory),
+            "/comics/vol.1.cbz"
+        );
+    }
+
+    #[test]
+    fn test_convert_tar_keeps_order_and_comic_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("comic.cbt");
+        create_tar(
+            &source,
+            &[
+                ("page2.jpg", b"\xFF\xD8\xFFtwo"),
+                (
+                    "ComicInfo.xml",
+                    b"<ComicInfo><Title>Converted</Title></ComicInfo>",
+                ),
+                ("page1.jpg", b"\xFF\xD8\xFFone"),
+                ("notes.txt", b"notes"),
+            ],
+        );
+        let source = source.to_str().unwrap();
+
+        let events = Mutex::new(Vec::new());
+        let report = convert_to_cbz_impl(source, false, false, |event| {
+            events.lock().unwrap().push(event)
+        })
+        .expect("convert");
+
+        assert_eq!(report.source_format, ArchiveFormat::Tar);
+        assert_eq!(report.image_files, vec!["page1.jpg", "page2.jpg"]);
+        assert_eq!(report.skipped_files, vec!["notes.txt"]);
+        assert!(report.includes_comic_info);
+        assert!(!report.source_deleted);
+        assert!(Path::new(source).exists());
+
+        let entries = zip_entries(&report.output_path);
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["page1.jpg", "page2.jpg", "ComicInfo.xml"]);
+        assert_eq!(entries[0].1, b"\xFF\xD8\xFFone");
+
+        let xml = String::from_utf8(entries[2].1.clone()).unwrap();
+        let comic_info = ComicInfo::parse(&xml).unwrap();
+        assert_eq!(comic_info.title, Some("Converted".to_string()));
+
+        let events = events.into_inner().unwrap();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(
+            events[0],
+            ConvertProgressEvent::Started { total_files: 2 }
+        ));
+        assert!(matches!(
+            &events[1],
+            ConvertProgressEvent::Progress { file_name, completed: 1 } if file_name == "page1.jpg"
+        ));
+        assert!(matches!(
+            &events[2],
+            ConvertProgressEvent::Progress { file_name, completed: 2 } if file_name == "page2.jpg"
+        ));
+        assert!(matches!(events[3], ConvertProgressEvent::Finished { .. }));
+        assert!(
+            fs::read_dir(dir.path())
+                .unwrap()
+                .flatten()
+                .all(|entry| !entry.file_name().to_string_lossy().ends_with(".kikou-tmp"))
+        );
+    }
+
+    #[test]
+    fn test_dry_run_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("comic.cbt");
+        create_tar(&source, &[("page1.jpg", b"\xFF\xD8\xFFone")]);
+
+        let events = Mutex::new(Vec::new());
+        let report = convert_to_cbz_impl(source.to_str().unwrap(), true, true, |event| {
+            events.lock().unwrap().push(event)
+        })
+        .expect("dry run");
+
+        assert!(report.dry_run);
+        assert!(!report.source_deleted);
+        assert_eq!(report.image_files, vec!["page1.jpg"]);
+        assert!(!Path::new(&report.output_path).exists());
+        assert!(source.exists());
+        assert!(events.into_inner().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_convert_folder_and_delete_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("comic");
+        fs::create_dir_all(source.join("chapter1")).unwrap();
+        fs::write(source.join("chapter1").join("001.jpg"), b"\xFF\xD8\xFFone").unwrap();
+        fs::write(source.join("002.jpg"), b"\xFF\xD8\xFFtwo").unwrap();
+        fs::write(source.join("notes.txt"), b"keep me").unwrap();
+
+        let report =
+            convert_to_cbz_impl(source.to_str().unwrap(), true, false, |_| {}).expect("convert");
+
+        assert_eq!(
+            report.output_path,
+            dir.path().join("comic.cbz").to_str().unwrap()
+        );
+        assert_eq!(report.image_files, vec!["002.jpg", "chapter1/001.jpg"]);
+        assert!(report.source_deleted);
+        assert!(!source.join("chapter1").exists());
+        assert!(source.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn test_refuses_cbz_source_and_existing_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let cbz = dir.path().join("comic.cbz");
+        zip::ZipWriter::new(fs::File::create(&cbz).unwrap())
+            .finish()
+            .unwrap();
+
+        let result = convert_to_cbz_impl(cbz.to_str().unwrap(), false, false, |_| {});
+        assert!(matches!(
+            result,
+            Err(WriteArchiveError::AlreadyInFormat(ArchiveFormat::Zip))
+        ));
+
+        let tar = dir.path().join("comic.cbt");
+        create_tar(&tar, &[("page1.jpg", b"\xFF\xD8\xFFone")]);
+
+        let result = convert_to_cbz_impl(tar.to_str().unwrap(), false, false, |_| {});
+        assert!(matches!(result, Err(WriteArchiveError::OutputExists(_))));
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use super::types::{ErrorResponse, ReadArchiveError, ToErrorResponse, WriteArchiveError};
+
+/// Identifies the state of an archive on disk when it was loaded, so a write
+/// can tell whether another program changed the file in the meantime.
+///
+/// Folders are only ever written through their ComicInfo.xml sidecar, so the
+/// fingerprint of a folder is that of its sidecar.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveFingerprint {
+    pub size: u64,
+    pub modified_ms: u64,
+}
+
+impl ArchiveFingerprint {
+    fn of(metadata: &fs::Metadata) -> Self {
+        let modified_ms = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_millis() as u64);
+
+        Self {
+            size: metadata.len(),
+            modified_ms,
+        }
+    }
+}
+
+pub fn archive_fingerprint(path: &str) -> Result<ArchiveFingerprint, ReadArchiveError> {
+    let path = Path::new(path);
+
+    if !path.is_dir() {
+        return fs::metadata(path)
+            .map(|metadata| ArchiveFingerprint::of(&metadata))
+            .map_err(ReadArchiveError::Io);
+    }
+
+    match fs::metadata(path.join("ComicInfo.xml")) {
+        Ok(metadata) => Ok(ArchiveFingerprint::of(&metadata)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(ArchiveFingerprint::default()),
+        Err(e) => Err(ReadArchiveError::Io(e)),
+    }
+}
+
+/// The result of a write together with the fingerprint the archive has after
+/// it, which the caller passes to its next write.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fingerprinted<T> {
+    pub value: T,
+    pub fingerprint: ArchiveFingerprint,
+}
+
+/// Fails with [`WriteArchiveError::Conflict`] when the archive no longer
+/// matches the fingerprint it had when the caller loaded it.
+pub fn check_fingerprint(
+    path: &str,
+    expected: &ArchiveFingerprint,
+) -> Result<(), WriteArchiveError> {
+    let actual = archive_fingerprint(path)?;
+
+    if actual != *expected {
+        return Err(WriteArchiveError::Conflict {
+            expected: *expected,
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+static ARCHIVE_WRITE_LOCKS: OnceLock<Mutex<HashMap<String, Arc<Mutex<()>>>>> = OnceLock::new();
+
+/// The lock every write to the archive at `path` holds from checking its
+/// fingerprint until the new one is taken, so two windows that loaded the same
+/// state cannot both pass the check.
+pub fn archive_write_lock(path: &str) -> Arc<Mutex<()>> {
+    ARCHIVE_WRITE_LOCKS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(path.to_string())
+        .or_default()
+        .clone()
+}
+
+/// Runs `write` when the archive is still the one the caller loaded, and
+/// returns its result with the fingerprint the archive has afterwards. The
+/// check, the write and the new fingerprint all happen under
+/// [`archive_write_lock`].
+pub fn write_unchanged<T>(
+    path: &str,
+    expected: &ArchiveFingerprint,
+    write: impl FnOnce() -> Result<T, ErrorResponse>,
+) -> Result<Fingerprinted<T>, ErrorResponse> {
+    let lock = archive_write_lock(path);
+    let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+    check_fingerprint(path, expected).map_err(|e| e.to_error_response())?;
+    let value = write()?;
+    with_fingerprint(path, value)
+}
+
+/// Pairs `value` with the current fingerprint of the archive.
+pub fn with_fingerprint<T>(path: &str, value: T) -> Result<Fingerprinted<T>, ErrorResponse> {
+    let fingerprint = archive_fingerprint(path).map_err(|e| e.to_error_response())?;
+    Ok(Fingerprinted { value, fingerprint })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::types::ErrorResponseType;
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_fingerprint_changes_with_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        fs::write(&path, b"first").unwrap();
+        let path = path.to_str().unwrap();
+
+        let loaded = archive_fingerprint(path).unwrap();
+        assert_eq!(loaded.size, 5);
+        assert!(check_fingerprint(path, &loaded).is_ok());
+
+        fs::write(path, b"other").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+
+        match check_fingerprint(path, &loaded) {
+            Err(WriteArchiveError::Conflict { expected, actual }) => {
+                assert_eq!(expected, loaded);
+                assert_eq!(actual, archive_fingerprint(path).unwrap());
+            }
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_folder_fingerprint_follows_the_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let empty = archive_fingerprint(path).unwrap();
+        assert_eq!(empty, ArchiveFingerprint::default());
+
+        fs::write(dir.path().join("001.jpg"), b"page").unwrap();
+        assert!(check_fingerprint(path, &empty).is_ok());
+
+        fs::write(dir.path().join("ComicInfo.xml"), b"<ComicInfo />").unwrap();
+        assert!(matches!(
+            check_fingerprint(path, &empty),
+            Err(WriteArchiveError::Conflict { .. })
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_writes_from_the_same_state_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        fs::write(&path, b"loaded").unwrap();
+        let path = path.to_str().unwrap();
+
+        let loaded = archive_fingerprint(path).unwrap();
+        let barrier = Barrier::new(2);
+
+        let results: Vec<_> = thread::scope(|scope| {
+            let writers: Vec<_> = (0..2)
+                .map(|window| {
+                    let barrier = &barrier;
+                    scope.spawn(move || {
+                        barrier.wait();
+                        write_unchanged(path, &loaded, || {
+                            // Leaves the other window time to run its check.
+                            thread::sleep(Duration::from_millis(50));
+                            fs::write(path, format!("saved by window {}", window))
+                                .map_err(|e| e.to_error_response())
+                        })
+                    })
+                })
+                .collect();
+
+            writers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+
+        let saved: Vec<_> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+        let conflicts = results
+            .iter()
+            .filter(|r| matches!(r, Err(e) if e.error_type == ErrorResponseType::Conflict))
+            .count();
+
+        assert_eq!(saved.len(), 1);
+        assert_eq!(conflicts, 1);
+        assert_eq!(saved[0].fingerprint, archive_fingerprint(path).unwrap());
+    }
+}
@@ -0,0 +1,597 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use super::atomic_write::AtomicFile;
+use super::manager::suppress_next_archive_event;
+use super::reader::read_comicinfo_xml;
+use super::thumbnail_cache::archive_dir_name;
+use super::types::{ErrorDetails, ErrorResponse, ErrorResponseType, ToErrorResponse};
+use super::writer::{delete_comicinfo_xml, update_zip_with_comicinfo};
+
+/// Versions kept per archive before the oldest ones are removed.
+pub const MAX_VERSIONS: usize = 100;
+
+static COMICINFO_HISTORY: OnceLock<ComicInfoHistory> = OnceLock::new();
+
+/// What made the ComicInfo.xml of an archive change.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HistoryChange {
+    /// The ComicInfo.xml the archive had before its first recorded edit.
+    Initial,
+    /// A change made outside of Kikou, found before the next edit.
+    External,
+    SaveComicInfoXml,
+    SavePageSettings,
+    DeleteComicInfoXml,
+    ReorderPages,
+    RemovePages,
+    InsertPages,
+    ReplacePage,
+    CleanArchive,
+    PopulatePageDimensions,
+    DetectDoublePageSpreads,
+    Restore {
+        version: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub version: u64,
+    pub timestamp_ms: u64,
+    pub change: HistoryChange,
+    /// The user account that made the change.
+    pub author: String,
+    /// False when the change removed ComicInfo.xml.
+    pub has_comic_info: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryVersion {
+    #[serde(flatten)]
+    pub entry: HistoryEntry,
+    pub xml: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffLineKind {
+    Same,
+    Added,
+    Removed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub text: String,
+}
+
+fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_millis() as u64)
+}
+
+/// Every recorded version of the ComicInfo.xml of each edited archive, one
+/// directory per archive and one JSON file per version.
+pub struct ComicInfoHistory {
+    dir: PathBuf,
+    /// One lock per archive, so rewriting one archive does not hold up edits
+    /// to the others.
+    locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl ComicInfoHistory {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn archive_lock(&self, archive_path: &str) -> Arc<Mutex<()>> {
+        self.locks
+            .lock()
+            .unwrap()
+            .entry(archive_path.to_string())
+            .or_default()
+            .clone()
+    }
+
+    fn archive_dir(&self, archive_path: &str) -> PathBuf {
+        self.dir.join(archive_dir_name(archive_path))
+    }
+
+    fn version_path(&self, archive_path: &str, version: u64) -> PathBuf {
+        self.archive_dir(archive_path)
+            .join(format!("{:06}.json", version))
+    }
+
+    fn versions(&self, archive_path: &str) -> Vec<u64> {
+        let Ok(entries) = fs::read_dir(self.archive_dir(archive_path)) else {
+            return Vec::new();
+        };
+
+        let mut versions: Vec<u64> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                name.to_str()?.strip_suffix(".json")?.parse().ok()
+            })
+            .collect();
+        versions.sort_unstable();
+        versions
+    }
+
+    pub fn version(&self, archive_path: &str, version: u64) -> io::Result<Option<HistoryVersion>> {
+        let data = match fs::read(self.version_path(archive_path, version)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn latest(&self, archive_path: &str) -> io::Result<Option<HistoryVersion>> {
+        match self.versions(archive_path).last() {
+            Some(&version) => self.version(archive_path, version),
+            None => Ok(None),
+        }
+    }
+
+    /// The recorded versions of an archive, newest first.
+    pub fn list(&self, archive_path: &str) -> io::Result<Vec<HistoryEntry>> {
+        let mut entries = Vec::new();
+
+        for version in self.versions(archive_path).into_iter().rev() {
+            if let Some(version) = self.version(archive_path, version)? {
+                entries.push(version.entry);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Records `xml` as the newest version, unless it is the newest version
+    /// already. Callers hold the lock of the archive.
+    fn record(
+        &self,
+        archive_path: &str,
+        change: HistoryChange,
+        xml: Option<&str>,
+    ) -> io::Result<Option<HistoryEntry>> {
+        let versions = self.versions(archive_path);
+
+        if let Some(&latest) = versions.last() {
+            let latest = self.version(archive_path, latest)?;
+
+            if latest.is_some_and(|latest| latest.xml.as_deref() == xml) {
+                return Ok(None);
+            }
+        }
+
+        let entry = HistoryEntry {
+            version: versions.last().map_or(1, |latest| latest + 1),
+            timestamp_ms: now_ms(),
+            change,
+            author: current_user(),
+            has_comic_info: xml.is_some(),
+        };
+        let version = HistoryVersion {
+            entry: entry.clone(),
+            xml: xml.map(str::to_string),
+        };
+
+        fs::create_dir_all(self.archive_dir(archive_path))?;
+
+        let mut file = AtomicFile::create(self.version_path(archive_path, entry.version))?;
+        serde_json::to_writer(file.as_file_mut(), &version).map_err(io::Error::other)?;
+        file.commit()?;
+
+        let excess = (versions.len() + 1).saturating_sub(MAX_VERSIONS);
+
+        for &old in versions.iter().take(excess) {
+            fs::remove_file(self.version_path(archive_path, old))?;
+        }
+
+        Ok(Some(entry))
+    }
+
+    /// Runs `write` and records the ComicInfo.xml it left in the archive.
+    ///
+    /// The ComicInfo.xml from before the write is recorded first when the
+    /// history does not have it yet, either because the archive was never
+    /// edited or because another program changed it since.
+    pub fn track<T, E>(
+        &self,
+        archive_path: &str,
+        change: HistoryChange,
+        write: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let lock = self.archive_lock(archive_path);
+        let _guard = lock.lock().unwrap();
+
+        let before = read_comicinfo_xml(archive_path).ok().flatten();
+        let before_change = match self.latest(archive_path) {
+            Ok(None) => HistoryChange::Initial,
+            _ => HistoryChange::External,
+        };
+
+        if let Err(e) = self.record(archive_path, before_change, before.as_deref()) {
+            debug!("Failed to record ComicInfo.xml of {}: {}", archive_path, e);
+        }
+
+        let result = write()?;
+
+        let after = read_comicinfo_xml(archive_path).ok().flatten();
+
+        if let Err(e) = self.record(archive_path, change, after.as_deref()) {
+            debug!("Failed to record ComicInfo.xml of {}: {}", archive_path, e);
+        }
+
+        Ok(result)
+    }
+}
+
+/// Compares two texts line by line.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // lengths[i][j] is the longest common subsequence of old[i..] and new[j..].
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let line = |kind, text: &str| DiffLine {
+        kind,
+        text: text.to_string(),
+    };
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(line(DiffLineKind::Same, old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            lines.push(line(DiffLineKind::Removed, old[i]));
+            i += 1;
+        } else {
+            lines.push(line(DiffLineKind::Added, new[j]));
+            j += 1;
+        }
+    }
+
+    lines.extend(
+        old[i..]
+            .iter()
+            .map(|text| line(DiffLineKind::Removed, text)),
+    );
+    lines.extend(new[j..].iter().map(|text| line(DiffLineKind::Added, text)));
+    lines
+}
+
+/// Sets up the history shared by all commands. Called once at startup.
+pub fn init_comicinfo_history(dir: PathBuf) {
+    if COMICINFO_HISTORY.set(ComicInfoHistory::new(dir)).is_err() {
+        debug!("ComicInfo history was already initialised");
+    }
+}
+
+pub fn comicinfo_history() -> Option<&'static ComicInfoHistory> {
+    COMICINFO_HISTORY.get()
+}
+
+/// Runs `write` through [`ComicInfoHistory::track`] once the history is set
+/// up, and on its own before that.
+pub fn track_comicinfo_change<T, E>(
+    archive_path: &str,
+    change: HistoryChange,
+    write: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    match comicinfo_history() {
+        Some(history) => history.track(archive_path, change, write),
+        None => write(),
+    }
+}
+
+fn find_version(
+    history: &ComicInfoHistory,
+    archive_path: &str,
+    version: u64,
+) -> Result<HistoryVersion, ErrorResponse> {
+    history
+        .version(archive_path, version)
+        .map_err(|e| e.to_error_response())?
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                ErrorResponseType::NotFound,
+                format!(
+                    "Version {} is not in the history of {}",
+                    version, archive_path
+                ),
+            )
+            .with_details(ErrorDetails::HistoryVersion { version })
+        })
+}
+
+/// Compares the ComicInfo.xml of two recorded versions of an archive.
+pub fn diff_versions(
+    history: &ComicInfoHistory,
+    archive_path: &str,
+    from: u64,
+    to: u64,
+) -> Result<Vec<DiffLine>, ErrorResponse> {
+    let from = find_version(history, archive_path, from)?;
+    let to = find_version(history, archive_path, to)?;
+
+    Ok(diff_lines(
+        from.xml.as_deref().unwrap_or_default(),
+        to.xml.as_deref().unwrap_or_default(),
+    ))
+}
+
+/// Writes the ComicInfo.xml of a recorded version back into the archive,
+/// removing it when the version had none. Returns the restored XML.
+pub fn restore_version(
+    history: &ComicInfoHistory,
+    archive_path: &str,
+    version: u64,
+) -> Result<Option<String>, ErrorResponse> {
+    let restored = find_version(history, archive_path, version)?;
+    let change = HistoryChange::Restore { version };
+
+    history.track(archive_path, change, || {
+        suppress_next_archive_event(archive_path);
+
+        match &restored.xml {
+            Some(xml) => update_zip_with_comicinfo(archive_path, xml),
+            None => delete_comicinfo_xml(archive_path),
+        }
+        .map_err(|e| e.to_error_response())
+    })?;
+
+    Ok(restored.xml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::tests::write_cbz;
+    use std::path::Path;
+
+    fn create_cbz(path: &Path, comic_info: Option<&str>) {
+        let mut entries: Vec<(&str, &[u8])> = vec![("001.jpg", b"page")];
+
+        if let Some(comic_info) = comic_info {
+            entries.push(("ComicInfo.xml", comic_info.as_bytes()));
+        }
+
+        write_cbz(path, &entries);
+    }
+
+    fn changes(history: &ComicInfoHistory, path: &str) -> Vec<(u64, HistoryChange)> {
+        history
+            .list(path)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.version, entry.change))
+            .collect()
+    }
+
+    #[test]
+    fn test_track_records_the_original_and_external_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = ComicInfoHistory::new(dir.path().join("history"));
+        let path = dir.path().join("comic.cbz");
+        create_cbz(&path, Some("<ComicInfo><Title>One</Title></ComicInfo>"));
+        let path = path.to_str().unwrap();
+
+        let write = |xml: &'static str| {
+            move || update_zip_with_comicinfo(path, xml).map_err(|e| e.to_string())
+        };
+
+        history
+            .track(
+                path,
+                HistoryChange::SaveComicInfoXml,
+                write("<ComicInfo><Title>Two</Title></ComicInfo>"),
+            )
+            .unwrap();
+        history
+            .track(
+                path,
+                HistoryChange::SaveComicInfoXml,
+                write("<ComicInfo><Title>Two</Title></ComicInfo>"),
+            )
+            .unwrap();
+
+        update_zip_with_comicinfo(path, "<ComicInfo><Title>Three</Title></ComicInfo>").unwrap();
+
+        history
+            .track(path, HistoryChange::DeleteComicInfoXml, || {
+                delete_comicinfo_xml(path).map_err(|e| e.to_string())
+            })
+            .unwrap();
+
+        assert_eq!(
+            changes(&history, path),
+            vec![
+                (4, HistoryChange::DeleteComicInfoXml),
+                (3, HistoryChange::External),
+                (2, HistoryChange::SaveComicInfoXml),
+                (1, HistoryChange::Initial),
+            ]
+        );
+
+        let latest = history.version(path, 4).unwrap().unwrap();
+        assert!(!latest.entry.has_comic_info);
+        assert_eq!(latest.xml, None);
+        assert!(!latest.entry.author.is_empty());
+    }
+
+    #[test]
+    fn test_failed_writes_are_not_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = ComicInfoHistory::new(dir.path().join("history"));
+        let path = dir.path().join("comic.cbz");
+        create_cbz(&path, None);
+        let path = path.to_str().unwrap();
+
+        let result: Result<(), String> =
+            history.track(path, HistoryChange::SavePageSettings, || {
+                Err("failed".to_string())
+            });
+
+        assert!(result.is_err());
+        assert_eq!(changes(&history, path), vec![(1, HistoryChange::Initial)]);
+    }
+
+    #[test]
+    fn test_restore_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = ComicInfoHistory::new(dir.path().join("history"));
+        let path = dir.path().join("comic.cbz");
+        create_cbz(&path, None);
+        let path = path.to_str().unwrap();
+
+        history
+            .track(path, HistoryChange::SaveComicInfoXml, || {
+                update_zip_with_comicinfo(path, "<ComicInfo><Title>New</Title></ComicInfo>")
+            })
+            .unwrap();
+
+        assert_eq!(restore_version(&history, path, 1).unwrap(), None);
+        assert_eq!(read_comicinfo_xml(path).unwrap(), None);
+
+        assert_eq!(
+            restore_version(&history, path, 2).unwrap().as_deref(),
+            Some("<ComicInfo><Title>New</Title></ComicInfo>")
+        );
+        assert_eq!(
+            read_comicinfo_xml(path).unwrap().as_deref(),
+            Some("<ComicInfo><Title>New</Title></ComicInfo>")
+        );
+
+        assert_eq!(
+            changes(&history, path),
+            vec![
+                (4, HistoryChange::Restore { version: 2 }),
+                (3, HistoryChange::Restore { version: 1 }),
+                (2, HistoryChange::SaveComicInfoXml),
+                (1, HistoryChange::Initial),
+            ]
+        );
+
+        let err = restore_version(&history, path, 9).unwrap_err();
+        assert_eq!(err.error_type, ErrorResponseType::NotFound);
+        assert_eq!(
+            err.details,
+            Some(ErrorDetails::HistoryVersion { version: 9 })
+        );
+    }
+
+    #[test]
+    fn test_record_keeps_the_newest_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = ComicInfoHistory::new(dir.path().to_path_buf());
+
+        for n in 0..MAX_VERSIONS + 2 {
+            let xml = format!("<ComicInfo><Number>{}</Number></ComicInfo>", n);
+            history
+                .record("/a.cbz", HistoryChange::SaveComicInfoXml, Some(&xml))
+                .unwrap();
+        }
+
+        let versions = history.list("/a.cbz").unwrap();
+        assert_eq!(versions.len(), MAX_VERSIONS);
+        assert_eq!(versions[0].version, MAX_VERSIONS as u64 + 2);
+        assert_eq!(versions.last().unwrap().version, 3);
+        assert!(history.list("/b.cbz").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_writes_to_other_archives_do_not_wait() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = ComicInfoHistory::new(dir.path().join("history"));
+        let first = dir.path().join("first.cbz");
+        let second = dir.path().join("second.cbz");
+        create_cbz(&first, None);
+        create_cbz(&second, None);
+        let (first, second) = (first.to_str().unwrap(), second.to_str().unwrap());
+        let (done, second_done) = std::sync::mpsc::channel();
+        let history = &history;
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                history
+                    .track(first, HistoryChange::SaveComicInfoXml, || {
+                        // Blocks the first archive until the second is written.
+                        second_done
+                            .recv_timeout(std::time::Duration::from_secs(5))
+                            .map_err(|e| e.to_string())?;
+                        update_zip_with_comicinfo(first, "<ComicInfo />").map_err(|e| e.to_string())
+                    })
+                    .unwrap();
+            });
+
+            history
+                .track(second, HistoryChange::SaveComicInfoXml, || {
+                    update_zip_with_comicinfo(second, "<ComicInfo />").map_err(|e| e.to_string())
+                })
+                .unwrap();
+            done.send(()).unwrap();
+        });
+
+        assert_eq!(history.list(first).unwrap().len(), 2);
+        assert_eq!(history.list(second).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let diff = diff_lines("a\nb\nc", "a\nc\nd");
+        let kinds: Vec<(DiffLineKind, &str)> = diff
+            .iter()
+            .map(|line| (line.kind, line.text.as_str()))
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                (DiffLineKind::Same, "a"),
+                (DiffLineKind::Removed, "b"),
+                (DiffLineKind::Same, "c"),
+                (DiffLineKind::Added, "d"),
+            ]
+        );
+    }
+}
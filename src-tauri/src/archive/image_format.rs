@@ -0,0 +1,441 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use image::DynamicImage;
+use image::codecs::jpeg::JpegEncoder;
+use serde::{Deserialize, Serialize};
+
+/// Quality of the JPEG previews made for pages the webview cannot display.
+const TRANSCODE_JPEG_QUALITY: u8 = 90;
+
+/// Downscaling applied to pages streamed as thumbnails.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ThumbnailOptions {
+    /// Largest width or height of the thumbnail, in pixels.
+    pub max_dimension: u32,
+    /// JPEG quality from 1 to 100. Thumbnails with transparency are PNG and
+    /// ignore it.
+    pub quality: u8,
+}
+
+impl Default for ThumbnailOptions {
+    fn default() -> Self {
+        Self {
+            max_dimension: 400,
+            quality: 80,
+        }
+    }
+}
+
+/// Image formats that can be pages of a comic.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PageImageFormat {
+    Jpeg,
+    Png,
+    Gif,
+    Bmp,
+    Webp,
+    Avif,
+    Jxl,
+    Tiff,
+    Heif,
+}
+
+const ALL_FORMATS: &[PageImageFormat] = &[
+    PageImageFormat::Jpeg,
+    PageImageFormat::Png,
+    PageImageFormat::Gif,
+    PageImageFormat::Bmp,
+    PageImageFormat::Webp,
+    PageImageFormat::Avif,
+    PageImageFormat::Jxl,
+    PageImageFormat::Tiff,
+    PageImageFormat::Heif,
+];
+
+const AVIF_BRANDS: &[&[u8]] = &[b"avif", b"avis"];
+
+const HEIF_BRANDS: &[&[u8]] = &[
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"hevm", b"hevs", b"mif1", b"msf1",
+];
+
+/// File signatures, except for WebP, AVIF and HEIF which need more than a
+/// prefix.
+/// Bytes to read from the start of a file to recognise its format: the magic
+/// bytes of every format and the brands of an AVIF or HEIF `ftyp` box.
+pub const MAGIC_BYTES_LIMIT: usize = 256;
+
+const MAGIC_BYTES: &[(&[u8], PageImageFormat)] = &[
+    (b"\xFF\xD8\xFF", PageImageFormat::Jpeg),
+    (b"\x89PNG\r\n\x1A\n", PageImageFormat::Png),
+    (b"GIF87a", PageImageFormat::Gif),
+    (b"GIF89a", PageImageFormat::Gif),
+    (b"\xFF\x0A", PageImageFormat::Jxl),
+    (b"\0\0\0\x0CJXL \x0D\x0A\x87\x0A", PageImageFormat::Jxl),
+    (b"II*\0", PageImageFormat::Tiff),
+    (b"MM\0*", PageImageFormat::Tiff),
+    (b"BM", PageImageFormat::Bmp),
+];
+
+/// Reads the major and compatible brands of an ISO base media file, as used
+/// by AVIF and HEIF.
+fn ftyp_brands(data: &[u8]) -> Vec<&[u8]> {
+    if data.len() < 12 || &data[4..8] != b"ftyp" {
+        return Vec::new();
+    }
+
+    let box_size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let end = box_size.clamp(12, data.len());
+
+    let mut brands = vec![&data[8..12]];
+    brands.extend(data.get(16..end).unwrap_or_default().chunks_exact(4));
+    brands
+}
+
+impl PageImageFormat {
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            PageImageFormat::Jpeg => &["jpg", "jpeg"],
+            PageImageFormat::Png => &["png"],
+            PageImageFormat::Gif => &["gif"],
+            PageImageFormat::Bmp => &["bmp"],
+            PageImageFormat::Webp => &["webp"],
+            PageImageFormat::Avif => &["avif"],
+            PageImageFormat::Jxl => &["jxl"],
+            PageImageFormat::Tiff => &["tif", "tiff"],
+            PageImageFormat::Heif => &["heic", "heif"],
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            PageImageFormat::Jpeg => "image/jpeg",
+            PageImageFormat::Png => "image/png",
+            PageImageFormat::Gif => "image/gif",
+            PageImageFormat::Bmp => "image/bmp",
+            PageImageFormat::Webp => "image/webp",
+            PageImageFormat::Avif => "image/avif",
+            PageImageFormat::Jxl => "image/jxl",
+            PageImageFormat::Tiff => "image/tiff",
+            PageImageFormat::Heif => "image/heif",
+        }
+    }
+
+    pub fn from_extension(name: &str) -> Option<Self> {
+        let extension = Path::new(name).extension()?.to_str()?.to_lowercase();
+
+        ALL_FORMATS
+            .iter()
+            .copied()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+
+    /// Identifies an image from its magic bytes.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        let brands = ftyp_brands(data);
+
+        if brands.iter().any(|brand| AVIF_BRANDS.contains(brand)) {
+            return Some(PageImageFormat::Avif);
+        }
+
+        if brands.iter().any(|brand| HEIF_BRANDS.contains(brand)) {
+            return Some(PageImageFormat::Heif);
+        }
+
+        let is_webp = data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP");
+
+        MAGIC_BYTES
+            .iter()
+            .find(|(magic, _)| data.starts_with(magic))
+            .map(|(_, format)| *format)
+            .or(is_webp.then_some(PageImageFormat::Webp))
+    }
+
+    /// Whether the webview can show the format directly. WebKit on macOS
+    /// also renders JPEG XL and HEIF; the other platforms' webviews do not.
+    pub fn is_displayable(self) -> bool {
+        match self {
+            PageImageFormat::Jxl | PageImageFormat::Heif => cfg!(target_os = "macos"),
+            format => format != PageImageFormat::Tiff,
+        }
+    }
+
+    /// Whether the backend can decode the format to transcode or downscale
+    /// it. AVIF, JPEG XL and HEIF have no decoder here and reach the webview
+    /// as they are, when it can show them.
+    pub fn is_decodable(self) -> bool {
+        !matches!(
+            self,
+            PageImageFormat::Avif | PageImageFormat::Jxl | PageImageFormat::Heif
+        )
+    }
+}
+
+/// Describes a supported page format to the frontend.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImageFormatInfo {
+    pub format: PageImageFormat,
+    pub extensions: Vec<String>,
+    pub mime_type: String,
+    /// Shown by the webview as is.
+    pub displayable: bool,
+    /// Decoded by the backend, so previews and thumbnails can be made at any
+    /// size whether or not the webview shows the format.
+    pub previewable: bool,
+}
+
+pub fn supported_formats() -> Vec<ImageFormatInfo> {
+    ALL_FORMATS
+        .iter()
+        .map(|&format| ImageFormatInfo {
+            format,
+            extensions: format.extensions().iter().map(|e| e.to_string()).collect(),
+            mime_type: format.mime_type().to_string(),
+            displayable: format.is_displayable(),
+            previewable: format.is_decodable(),
+        })
+        .collect()
+}
+
+/// The format of an entry: its magic bytes when recognised, its extension
+/// otherwise.
+pub fn image_format_of(file_name: &str, data: &[u8]) -> Option<PageImageFormat> {
+    PageImageFormat::detect(data).or_else(|| PageImageFormat::from_extension(file_name))
+}
+
+/// Image data the webview can display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayableImage {
+    pub data: Vec<u8>,
+    pub mime_type: &'static str,
+}
+
+fn encode_preview(image: &DynamicImage, quality: u8) -> Result<DisplayableImage, String> {
+    let mut data = Cursor::new(Vec::new());
+
+    if image.color().has_alpha() {
+        image
+            .write_to(&mut data, image::ImageFormat::Png)
+            .map_err(|e| e.to_string())?;
+
+        return Ok(DisplayableImage {
+            data: data.into_inner(),
+            mime_type: PageImageFormat::Png.mime_type(),
+        });
+    }
+
+    JpegEncoder::new_with_quality(&mut data, quality.clamp(1, 100))
+        .encode_image(&image.to_rgb8())
+        .map_err(|e| e.to_string())?;
+
+    Ok(DisplayableImage {
+        data: data.into_inner(),
+        mime_type: PageImageFormat::Jpeg.mime_type(),
+    })
+}
+
+/// Returns the entry unchanged when the webview can display it, or a JPEG or
+/// PNG copy when it cannot.
+pub fn to_displayable(file_name: &str, data: Vec<u8>) -> Result<DisplayableImage, String> {
+    let format = image_format_of(file_name, &data)
+        .ok_or_else(|| format!("{} is not a recognised image", file_name))?;
+
+    if format.is_displayable() {
+        return Ok(DisplayableImage {
+            data,
+            mime_type: format.mime_type(),
+        });
+    }
+
+    if !format.is_decodable() {
+        return Err(format!(
+            "{} images cannot be previewed on this platform",
+            format.mime_type()
+        ));
+    }
+
+    let image = image::load_from_memory(&data).map_err(|e| e.to_string())?;
+    encode_preview(&image, TRANSCODE_JPEG_QUALITY)
+}
+
+/// Returns a copy of the entry that fits within `options.max_dimension`.
+/// Pages that already fit, and pages the backend cannot decode but the
+/// webview can show, are returned as [`to_displayable`] would.
+pub fn to_thumbnail(
+    file_name: &str,
+    data: Vec<u8>,
+    options: &ThumbnailOptions,
+) -> Result<DisplayableImage, String> {
+    let max_dimension = options.max_dimension.max(1);
+
+    let fits = imagesize::blob_size(&data).is_ok_and(|size| {
+        size.width <= max_dimension as usize && size.height <= max_dimension as usize
+    });
+
+    let format = image_format_of(file_name, &data)
+        .ok_or_else(|| format!("{} is not a recognised image", file_name))?;
+
+    if fits || (!format.is_decodable() && format.is_displayable()) {
+        return to_displayable(file_name, data);
+    }
+
+    if !format.is_decodable() {
+        return Err(format!(
+            "Thumbnails cannot be made from {} images",
+            format.mime_type()
+        ));
+    }
+
+    let image = image::load_from_memory(&data).map_err(|e| e.to_string())?;
+    encode_preview(
+        &image.thumbnail(max_dimension, max_dimension),
+        options.quality,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn encode(image: DynamicImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        image.write_to(&mut data, format).unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn test_detect_by_magic_bytes() {
+        let cases: &[(&[u8], PageImageFormat)] = &[
+            (b"\xFF\xD8\xFF\xE0", PageImageFormat::Jpeg),
+            (b"\x89PNG\r\n\x1a\n\0\0", PageImageFormat::Png),
+            (b"GIF89a", PageImageFormat::Gif),
+            (b"RIFF\0\0\0\0WEBPVP8 ", PageImageFormat::Webp),
+            (b"BM\0\0", PageImageFormat::Bmp),
+            (b"II*\0", PageImageFormat::Tiff),
+            (b"MM\0*", PageImageFormat::Tiff),
+            (b"\xFF\x0A\0", PageImageFormat::Jxl),
+            (b"\0\0\0\x0CJXL \x0D\x0A\x87\x0A", PageImageFormat::Jxl),
+            (
+                b"\0\0\0\x1Cftypavif\0\0\0\0avifmif1miaf",
+                PageImageFormat::Avif,
+            ),
+            (b"\0\0\0\x18ftypmif1\0\0\0\0mif1heic", PageImageFormat::Heif),
+            (b"\0\0\0\x18ftypmif1\0\0\0\0mif1avif", PageImageFormat::Avif),
+        ];
+
+        for (data, format) in cases {
+            assert_eq!(PageImageFormat::detect(data), Some(*format), "{:?}", data);
+        }
+
+        assert_eq!(PageImageFormat::detect(b"not an image"), None);
+    }
+
+    #[test]
+    fn test_image_format_of_prefers_magic_bytes() {
+        assert_eq!(
+            image_format_of("page.jpg", b"\x89PNG\r\n\x1a\n"),
+            Some(PageImageFormat::Png)
+        );
+        assert_eq!(
+            image_format_of("page.JXL", b"unknown"),
+            Some(PageImageFormat::Jxl)
+        );
+        assert_eq!(image_format_of("page.txt", b"unknown"), None);
+    }
+
+    #[test]
+    fn test_to_displayable() {
+        let png = encode(
+            DynamicImage::ImageRgb8(RgbImage::new(4, 4)),
+            image::ImageFormat::Png,
+        );
+        let preview = to_displayable("mislabeled.jpg", png.clone()).unwrap();
+        assert_eq!(preview.data, png);
+        assert_eq!(preview.mime_type, "image/png");
+
+        let tiff = encode(
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([10, 20, 30]))),
+            image::ImageFormat::Tiff,
+        );
+        let preview = to_displayable("page.tif", tiff).unwrap();
+        assert_eq!(preview.mime_type, "image/jpeg");
+        assert_eq!(
+            PageImageFormat::detect(&preview.data),
+            Some(PageImageFormat::Jpeg)
+        );
+
+        let tiff = encode(
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([10, 20, 30, 40]))),
+            image::ImageFormat::Tiff,
+        );
+        let preview = to_displayable("page.tiff", tiff).unwrap();
+        assert_eq!(preview.mime_type, "image/png");
+
+        assert!(to_displayable("notes.txt", b"text".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_to_thumbnail() {
+        let options = ThumbnailOptions {
+            max_dimension: 10,
+            quality: 50,
+        };
+
+        let large = encode(
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 20, Rgb([200, 100, 50]))),
+            image::ImageFormat::Png,
+        );
+        let thumbnail = to_thumbnail("page.png", large, &options).unwrap();
+        let size = imagesize::blob_size(&thumbnail.data).unwrap();
+        assert_eq!(thumbnail.mime_type, "image/jpeg");
+        assert_eq!((size.width, size.height), (10, 5));
+
+        let small = encode(
+            DynamicImage::ImageRgb8(RgbImage::new(8, 8)),
+            image::ImageFormat::Png,
+        );
+        let thumbnail = to_thumbnail("page.png", small.clone(), &options).unwrap();
+        assert_eq!(thumbnail.data, small);
+
+        let transparent = encode(
+            DynamicImage::ImageRgba8(RgbaImage::new(30, 30)),
+            image::ImageFormat::Png,
+        );
+        let thumbnail = to_thumbnail("page.png", transparent, &options).unwrap();
+        assert_eq!(thumbnail.mime_type, "image/png");
+
+        // AVIF cannot be downscaled here, so the webview gets the page as is.
+        let avif = b"\0\0\0\x1Cftypavif".to_vec();
+        let thumbnail = to_thumbnail("page.avif", avif.clone(), &options).unwrap();
+        assert_eq!(thumbnail.data, avif);
+        assert_eq!(thumbnail.mime_type, "image/avif");
+
+        if !cfg!(target_os = "macos") {
+            assert!(to_thumbnail("page.jxl", b"\xFF\x0A\0".to_vec(), &options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_supported_formats() {
+        let formats = supported_formats();
+        let avif = formats
+            .iter()
+            .find(|f| f.format == PageImageFormat::Avif)
+            .unwrap();
+
+        assert_eq!(formats.len(), ALL_FORMATS.len());
+        assert_eq!(avif.mime_type, "image/avif");
+        assert!(avif.displayable);
+        assert!(!avif.previewable);
+        assert!(
+            formats
+                .iter()
+                .find(|f| f.format == PageImageFormat::Tiff)
+                .unwrap()
+                .previewable
+        );
+    }
+}
@@ -1,17 +1,31 @@
+pub mod analysis;
+pub mod atomic_write;
+pub mod backend;
+pub mod classify;
+pub mod clean;
 pub mod commands;
+pub mod convert;
 pub mod event;
+pub mod fingerprint;
+pub mod history;
+pub mod image_format;
 pub mod manager;
+pub mod order;
+pub mod pages;
+pub mod protocol;
 pub mod reader;
+pub mod stream;
+pub mod thumbnail_cache;
 pub mod types;
 pub mod watcher;
 pub mod writer;
 
 pub use commands::*;
 pub use reader::read_archive;
-pub use types::is_image_file;
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use super::types::{ErrorDetails, ErrorResponseType, is_image_file, is_junk_entry};
     use super::writer::PageSettings;
     use super::*;
     use crate::comicinfo::{ComicInfo, ComicPageType};
@@ -28,6 +42,19 @@ mod tests {
         dir.to_str().unwrap().to_string()
     }
 
+    pub(crate) fn write_cbz(path: &std::path::Path, entries: &[(&str, &[u8])]) {
+        let file = std::fs::File::create(path).unwrap();
+        let mut zip = zip::ZipWriter::new(file);
+        let options = ZipFileOptions::<()>::default();
+
+        for (name, data) in entries {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+
+        zip.finish().unwrap();
+    }
+
     #[test]
     fn test_save_page_settings_creates_comicinfo() {
         let path = test_path("test_save.cbz");
@@ -42,10 +69,12 @@ mod tests {
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
 
             zip.start_file("image1.jpg", options).expect("start file");
-            zip.write_all(b"fake image data").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data")
+                .expect("write data");
 
             zip.start_file("image2.jpg", options).expect("start file");
-            zip.write_all(b"fake image data 2").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data 2")
+                .expect("write data");
 
             zip.finish().expect("finish zip");
         }
@@ -112,7 +141,7 @@ mod tests {
             let options =
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
             zip.start_file("image.jpg", options).expect("start file");
-            zip.write_all(b"data").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFdata").expect("write data");
             zip.finish().expect("finish zip");
         }
         let result = read_archive(&path);
@@ -130,9 +159,28 @@ mod tests {
         assert!(is_image_file("test.bmp"));
         assert!(is_image_file("test.webp"));
         assert!(is_image_file("TEST.JPG"));
+        assert!(is_image_file("test.avif"));
+        assert!(is_image_file("test.jxl"));
+        assert!(is_image_file("test.tif"));
+        assert!(is_image_file("test.HEIC"));
         assert!(!is_image_file("test.txt"));
         assert!(!is_image_file("test.xml"));
         assert!(!is_image_file("ComicInfo.xml"));
+        assert!(!is_image_file("__MACOSX/._001.jpg"));
+        assert!(!is_image_file("chapter/._001.jpg"));
+    }
+
+    #[test]
+    fn test_is_junk_entry() {
+        assert!(is_junk_entry("__MACOSX/"));
+        assert!(is_junk_entry("__MACOSX/chapter/001.jpg"));
+        assert!(is_junk_entry("._001.jpg"));
+        assert!(is_junk_entry("chapter/.DS_Store"));
+        assert!(is_junk_entry("Thumbs.db"));
+        assert!(is_junk_entry("chapter\\desktop.ini"));
+        assert!(!is_junk_entry("chapter/001.jpg"));
+        assert!(!is_junk_entry("ComicInfo.xml"));
+        assert!(!is_junk_entry("chapter/"));
     }
 
     #[test]
@@ -143,6 +191,15 @@ mod tests {
         assert!(result.error.is_some());
         assert!(result.image_files.is_empty());
         assert!(result.comic_info.is_none());
+
+        let err = result.error.unwrap();
+        assert_eq!(err.error_type, ErrorResponseType::FailedToLoadArchive);
+        assert_eq!(
+            err.details,
+            Some(ErrorDetails::Io {
+                error_kind: "NotFound".to_string()
+            })
+        );
     }
 
     #[test]
@@ -169,6 +226,153 @@ mod tests {
         let _ = std::fs::remove_file(&path);
     }
 
+    #[test]
+    fn test_load_cbz_reports_invalid_comicinfo_values() {
+        let path = test_path("test_lenient_comicinfo.cbz");
+        let _ = std::fs::remove_file(&path);
+        {
+            let file = std::fs::File::create(&path).expect("create cbz");
+            let mut zip = zip::ZipWriter::new(file);
+
+            let options =
+                ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
+
+            zip.start_file("001.jpg", options).expect("start file");
+            zip.write_all(b"\xFF\xD8\xFFpage").expect("write data");
+            zip.start_file("ComicInfo.xml", options)
+                .expect("start file");
+            zip.write_all(b"<ComicInfo><Title>T</Title><PageCount>INVALID</PageCount></ComicInfo>")
+                .expect("write data");
+
+            zip.finish().expect("finish zip");
+        }
+
+        let result = commands::load_cbz_impl(None, path.clone());
+
+        assert!(result.error.is_none(), "{:?}", result.error);
+        assert_eq!(result.image_files, vec!["001.jpg"]);
+        assert_eq!(
+            result.comic_info.as_ref().and_then(|ci| ci.title.clone()),
+            Some("T".to_string())
+        );
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].element, "PageCount");
+        assert_eq!(result.diagnostics[0].value, "INVALID");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_load_cbz_sorts_pages_naturally_and_warns_about_old_order() {
+        let path = test_path("test_natural_order.cbz");
+        let _ = std::fs::remove_file(&path);
+        {
+            let file = std::fs::File::create(&path).expect("create cbz");
+            let mut zip = zip::ZipWriter::new(file);
+
+            let options =
+                ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
+
+            for name in ["page10.jpg", "page2.jpg", "page1.jpg"] {
+                zip.start_file(name, options).expect("start file");
+                zip.write_all(b"\xFF\xD8\xFFpage").expect("write data");
+            }
+            zip.start_file("ComicInfo.xml", options)
+                .expect("start file");
+            zip.write_all(
+                br#"<ComicInfo><Pages><Page Image="1" Bookmark="B" /></Pages></ComicInfo>"#,
+            )
+            .expect("write data");
+
+            zip.finish().expect("finish zip");
+        }
+
+        let result = commands::load_cbz_impl(None, path.clone());
+
+        assert_eq!(
+            result.image_files,
+            vec!["page1.jpg", "page2.jpg", "page10.jpg"]
+        );
+        let warning = result.page_order_warning.expect("page order warning");
+        assert_eq!(warning.mismatches.len(), 1);
+        assert_eq!(warning.mismatches[0].recorded, "page10.jpg");
+        assert_eq!(warning.mismatches[0].current, "page2.jpg");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_load_cbz_finds_pages_by_their_magic_bytes() {
+        let path = test_path("test_magic_bytes.cbz");
+        let _ = std::fs::remove_file(&path);
+        {
+            let file = std::fs::File::create(&path).expect("create cbz");
+            let mut zip = zip::ZipWriter::new(file);
+
+            let options =
+                ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
+
+            let entries: [(&str, &[u8]); 4] = [
+                ("0002", b"\x89PNG\r\n\x1A\npage"),
+                ("0001.dat", b"\xFF\xD8\xFFpage"),
+                ("0003.jpg", b"<html>not a page</html>"),
+                ("notes.txt", b"notes"),
+            ];
+            for (name, data) in entries {
+                zip.start_file(name, options).expect("start file");
+                zip.write_all(data).expect("write data");
+            }
+
+            zip.finish().expect("finish zip");
+        }
+
+        let result = commands::load_cbz_impl(None, path.clone());
+
+        assert_eq!(result.error, None);
+        assert_eq!(result.image_files, vec!["0001.dat", "0002"]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_load_directory_and_save_sidecar() {
+        let path = test_path("test_loose_folder");
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(format!("{}/extras", path)).expect("create folder");
+        std::fs::write(format!("{}/002.jpg", path), b"\xFF\xD8\xFFpage 2").expect("write page");
+        std::fs::write(format!("{}/001.jpg", path), b"\xFF\xD8\xFFpage 1").expect("write page");
+        std::fs::write(format!("{}/extras/003.jpg", path), b"\xFF\xD8\xFFpage 3")
+            .expect("write page");
+        std::fs::write(format!("{}/notes.txt", path), b"notes").expect("write notes");
+
+        let result = commands::load_cbz_impl(None, path.clone());
+        assert!(result.error.is_none(), "{:?}", result.error);
+        assert_eq!(
+            result.image_files,
+            vec!["001.jpg", "002.jpg", "extras/003.jpg"]
+        );
+
+        let data = commands::get_cbz_file_data(path.clone(), "extras/003.jpg".to_string());
+        assert_eq!(data.data.as_deref(), Some(&b"\xFF\xD8\xFFpage 3"[..]));
+
+        let mut settings: HashMap<String, PageSettings> = HashMap::new();
+        settings.insert(
+            "001.jpg".to_string(),
+            PageSettings {
+                page_type: ComicPageType::FrontCover,
+                double_page: false,
+                bookmark: "".to_string(),
+                image: 0,
+            },
+        );
+        super::writer::save_page_settings_impl(path.clone(), settings).expect("save page settings");
+
+        let xml = std::fs::read_to_string(format!("{}/ComicInfo.xml", path)).expect("sidecar");
+        let ci = ComicInfo::parse(&xml).expect("parse sidecar");
+        let pages = ci.pages.expect("pages").page;
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].type_, Some(ComicPageType::FrontCover));
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
     #[test]
     fn test_get_raw_comicinfo_xml_and_validate() {
         let path = test_path("test_get_raw.cbz");
@@ -198,7 +402,8 @@ mod tests {
         let xml = result.unwrap();
         assert!(xml.contains("<Title>Test</Title>"));
 
-        let validation_result = crate::comicinfo::commands::validate_comicinfo_xml(xml.clone());
+        let validation_result =
+            crate::comicinfo::commands::validate_comicinfo_xml(xml.clone(), None);
         assert!(validation_result.is_ok());
 
         let _ = std::fs::remove_file(&path);
@@ -213,8 +418,15 @@ mod tests {
   </Pages>
 </ComicInfo>"#;
 
-        let result = crate::comicinfo::commands::validate_comicinfo_xml(invalid_xml.to_string());
-        assert!(result.is_err());
+        let err = crate::comicinfo::commands::validate_comicinfo_xml(invalid_xml.to_string(), None)
+            .unwrap_err();
+        assert_eq!(err.error_type, ErrorResponseType::ComicInfoXmlInvalid);
+        match err.details {
+            Some(ErrorDetails::Validation { issues }) => {
+                assert!(issues.iter().any(|issue| issue.field.starts_with("Pages")));
+            }
+            other => panic!("expected validation issues, got {:?}", other),
+        }
     }
 
     #[test]
@@ -230,10 +442,12 @@ mod tests {
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
 
             zip.start_file("cover.jpg", options).expect("start file");
-            zip.write_all(b"fake cover").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake cover")
+                .expect("write data");
 
             zip.start_file("page1.jpg", options).expect("start file");
-            zip.write_all(b"fake page 1").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake page 1")
+                .expect("write data");
 
             zip.finish().expect("finish zip");
         }
@@ -304,8 +518,56 @@ mod tests {
         assert!(result.is_ok());
 
         let invalid_xml = r#"not valid xml"#;
-        let result = super::writer::save_comicinfo_xml_impl(path.clone(), invalid_xml.to_string());
-        assert!(result.is_err());
+        let err = super::writer::save_comicinfo_xml_impl(path.clone(), invalid_xml.to_string())
+            .unwrap_err();
+        assert_eq!(err.error_type, ErrorResponseType::FailedToParseComicInfoXml);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_save_comicinfo_xml_rejects_archives_changed_since_load() {
+        let path = test_path("test_save_xml_conflict.cbz");
+        let _ = std::fs::remove_file(&path);
+        {
+            let file = std::fs::File::create(&path).expect("create cbz");
+            let zip = zip::ZipWriter::new(file);
+            zip.finish().expect("finish zip");
+        }
+
+        let xml = "<ComicInfo><Title>Mine</Title></ComicInfo>".to_string();
+        let loaded = load_cbz_impl(None, path.clone())
+            .fingerprint
+            .expect("fingerprint");
+
+        super::writer::save_comicinfo_xml_impl(
+            path.clone(),
+            "<ComicInfo><Title>Theirs</Title></ComicInfo>".to_string(),
+        )
+        .expect("external save");
+
+        let err = save_comicinfo_xml(path.clone(), xml.clone(), loaded).unwrap_err();
+        assert_eq!(err.error_type, ErrorResponseType::Conflict);
+        assert!(matches!(err.details, Some(ErrorDetails::Conflict { .. })));
+        assert!(
+            read_archive(&path)
+                .unwrap()
+                .comic_info
+                .and_then(|ci| ci.title)
+                .is_some_and(|title| title == "Theirs")
+        );
+
+        let reloaded = load_cbz_impl(None, path.clone())
+            .fingerprint
+            .expect("fingerprint");
+        let saved = save_comicinfo_xml(path.clone(), xml.clone(), reloaded).expect("save");
+        assert_eq!(
+            saved.fingerprint,
+            super::fingerprint::archive_fingerprint(&path).unwrap()
+        );
+
+        // The returned fingerprint is enough for the next write.
+        assert!(save_comicinfo_xml(path.clone(), xml, saved.fingerprint).is_ok());
 
         let _ = std::fs::remove_file(&path);
     }
@@ -326,7 +588,8 @@ mod tests {
             zip.write_all(b"<ComicInfo></ComicInfo>")
                 .expect("write xml");
             zip.start_file("image1.jpg", options).expect("start file");
-            zip.write_all(b"fake image data").expect("write image");
+            zip.write_all(b"\xFF\xD8\xFFfake image data")
+                .expect("write image");
             zip.finish().expect("finish zip");
         }
 
@@ -369,8 +632,14 @@ mod tests {
             for i in 0..5 {
                 let name = format!("image{}.jpg", i);
                 zip.start_file(&name, options).expect("start file");
-                zip.write_all(format!("fake image data {}", i).as_bytes())
-                    .expect("write data");
+                zip.write_all(
+                    &[
+                        &b"\xFF\xD8\xFF"[..],
+                        format!("fake image data {}", i).as_bytes(),
+                    ]
+                    .concat(),
+                )
+                .expect("write data");
             }
 
             // build initial ComicInfo.xml with 5 pages having distinct bookmarks
@@ -513,8 +782,14 @@ mod tests {
             for i in 0..4 {
                 let name = format!("image{}.jpg", i);
                 zip.start_file(&name, options).expect("start file");
-                zip.write_all(format!("fake image data {}", i).as_bytes())
-                    .expect("write data");
+                zip.write_all(
+                    &[
+                        &b"\xFF\xD8\xFF"[..],
+                        format!("fake image data {}", i).as_bytes(),
+                    ]
+                    .concat(),
+                )
+                .expect("write data");
             }
 
             zip.finish().expect("finish zip");
@@ -568,4 +843,94 @@ mod tests {
 
         let _ = std::fs::remove_file(&path);
     }
+
+    #[test]
+    fn test_preview_stream_sends_one_encoding() {
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(20, 10)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+
+        let previews = PreviewStream::new("a.cbz".to_string(), Default::default(), None);
+        match previews.event("a.png".to_string(), png.clone()) {
+            StreamProgressEvent::Preview {
+                data_raw,
+                data_base64,
+                mime_type,
+                ..
+            } => {
+                assert_eq!(data_raw, None);
+                assert!(data_base64.is_some());
+                assert_eq!(mime_type, "image/png");
+            }
+            _ => panic!("expected a preview"),
+        }
+
+        let options = StreamFileDataOptions {
+            encoding: PreviewEncoding::Raw,
+            thumbnail: Some(super::image_format::ThumbnailOptions {
+                max_dimension: 4,
+                quality: 60,
+            }),
+            ..Default::default()
+        };
+        let previews = PreviewStream::new("a.cbz".to_string(), options, None);
+        match previews.event("a.png".to_string(), png) {
+            StreamProgressEvent::Preview {
+                data_raw,
+                data_base64,
+                mime_type,
+                ..
+            } => {
+                let size = imagesize::blob_size(&data_raw.unwrap()).unwrap();
+                assert_eq!((size.width, size.height), (4, 2));
+                assert_eq!(data_base64, None);
+                assert_eq!(mime_type, "image/jpeg");
+            }
+            _ => panic!("expected a preview"),
+        }
+
+        match previews.event("notes.txt".to_string(), b"text".to_vec()) {
+            StreamProgressEvent::Error { file_name, .. } => assert_eq!(file_name, "notes.txt"),
+            _ => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn test_preview_stream_reuses_cached_thumbnails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(20, 10)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        zip.start_file("001.png", ZipFileOptions::<()>::default())
+            .unwrap();
+        zip.write_all(&png).unwrap();
+        zip.finish().unwrap();
+
+        let cache = super::thumbnail_cache::ThumbnailCache::new(dir.path().join("cache"), 1 << 20);
+        let options = StreamFileDataOptions {
+            encoding: PreviewEncoding::Raw,
+            thumbnail: Some(super::image_format::ThumbnailOptions {
+                max_dimension: 4,
+                quality: 60,
+            }),
+            ..Default::default()
+        };
+        let path = path.to_str().unwrap().to_string();
+
+        let previews = PreviewStream::new(path.clone(), options.clone(), Some(&cache));
+        assert_eq!(previews.cached("001.png"), None);
+        previews.event("001.png".to_string(), png);
+
+        let previews = PreviewStream::new(path, options, Some(&cache));
+        let cached = previews.cached("001.png").unwrap();
+        let size = imagesize::blob_size(&cached.data).unwrap();
+        assert_eq!((size.width, size.height), (4, 2));
+    }
 }
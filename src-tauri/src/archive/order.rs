@@ -0,0 +1,319 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use serde::{Deserialize, Serialize};
+
+use super::image_format::PageImageFormat;
+use super::types::{Archive, is_image_file, is_junk_entry};
+use crate::comicinfo::ComicInfo;
+
+/// A `<Page>` whose image index points at a different file than the one it
+/// was written for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PageOrderMismatch {
+    pub image: i32,
+    pub recorded: String,
+    pub current: String,
+}
+
+/// Raised when an existing ComicInfo.xml was written against a different page
+/// ordering than the one used now.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PageOrderWarning {
+    pub message: String,
+    pub mismatches: Vec<PageOrderMismatch>,
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        number.push(c);
+    }
+
+    number
+}
+
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        let ordering = match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                let x = x.trim_start_matches('0');
+                let y = y.trim_start_matches('0');
+
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (Some(x), Some(y)) => {
+                a.next();
+                b.next();
+                x.to_lowercase().cmp(y.to_lowercase())
+            }
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Compares paths folder by folder. The files of a folder come before the
+/// contents of its subfolders.
+fn compare_paths(a: &str, b: &str) -> Ordering {
+    let mut a_folders: Vec<&str> = a.split(['/', '\\']).collect();
+    let mut b_folders: Vec<&str> = b.split(['/', '\\']).collect();
+    let a_file = a_folders.pop().unwrap_or_default();
+    let b_file = b_folders.pop().unwrap_or_default();
+
+    for level in 0.. {
+        let ordering = match (a_folders.get(level), b_folders.get(level)) {
+            (Some(x), Some(y)) => natural_cmp(x, y),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (None, None) => return natural_cmp(a_file, b_file),
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// Orders page names naturally: runs of digits compare by their numeric value
+/// and letters ignore case, so `page2.jpg` comes before `page10.jpg`.
+///
+/// Pages in nested folders come after the pages of the folder containing
+/// them, and folders are ordered naturally by name, so `cover.jpg` comes
+/// before `chapter 2/001.jpg`, which comes before `chapter 10/001.jpg`.
+///
+/// Names that only differ in case or leading zeros fall back to a plain
+/// comparison, so the order is always total.
+pub fn compare_page_names(a: &str, b: &str) -> Ordering {
+    compare_paths(a, b).then_with(|| a.cmp(b))
+}
+
+pub fn sort_page_names(names: &mut [String]) {
+    names.sort_by(|a, b| compare_page_names(a, b));
+}
+
+/// Returns the image names among `names` in page order.
+///
+/// `headers` holds the first bytes of the entries that could be read. Those
+/// entries are images when their magic bytes are, whatever their name says;
+/// the others are judged by their extension.
+pub fn sorted_image_names(
+    names: impl IntoIterator<Item = String>,
+    headers: &HashMap<String, Vec<u8>>,
+) -> Vec<String> {
+    let mut images: Vec<String> = names
+        .into_iter()
+        .filter(|name| match headers.get(name) {
+            Some(header) => PageImageFormat::detect(header).is_some() && !is_junk_entry(name),
+            None => is_image_file(name),
+        })
+        .collect();
+    sort_page_names(&mut images);
+    images
+}
+
+/// Returns the image names of an already read archive in page order.
+pub fn sorted_image_files(archive: &Archive) -> Vec<String> {
+    archive.image_files.clone()
+}
+
+/// Checks that the `<Page>` entries of `comic_info` still describe the images
+/// they were written for.
+///
+/// Pages written with a filename comment are checked against it. Without
+/// comments, the ComicInfo is assumed to be written against the old plain
+/// sort order, and pages whose image differs between both orders are
+/// reported.
+pub fn check_page_order(
+    comic_info: &ComicInfo,
+    recorded: &HashMap<i32, String>,
+    image_names: &[String],
+) -> Option<PageOrderWarning> {
+    let pages = comic_info.pages.as_ref()?;
+
+    let mut plain = image_names.to_vec();
+    plain.sort();
+
+    let mut mismatches = Vec::new();
+
+    for page in &pages.page {
+        let Some(current) = usize::try_from(page.image)
+            .ok()
+            .and_then(|index| image_names.get(index))
+        else {
+            continue;
+        };
+
+        let written_for = match recorded.get(&page.image) {
+            Some(filename) => filename,
+            None if recorded.is_empty() => &plain[page.image as usize],
+            None => continue,
+        };
+
+        if written_for != current {
+            mismatches.push(PageOrderMismatch {
+                image: page.image,
+                recorded: written_for.clone(),
+                current: current.clone(),
+            });
+        }
+    }
+
+    if mismatches.is_empty() {
+        return None;
+    }
+
+    let message = if recorded.is_empty() {
+        format!(
+            "ComicInfo.xml may have been written for the previous page order; {} page(s) now describe a different image",
+            mismatches.len()
+        )
+    } else {
+        format!(
+            "ComicInfo.xml was written for a different page order; {} page(s) now describe a different image",
+            mismatches.len()
+        )
+    };
+
+    Some(PageOrderWarning {
+        message,
+        mismatches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_sort_page_names_naturally() {
+        let mut pages = names(&[
+            "page10.jpg",
+            "Page2.jpg",
+            "page1.jpg",
+            "page02.jpg",
+            "extra/page1.jpg",
+            "page1b.jpg",
+        ]);
+
+        sort_page_names(&mut pages);
+
+        assert_eq!(
+            pages,
+            names(&[
+                "page1.jpg",
+                "page1b.jpg",
+                "Page2.jpg",
+                "page02.jpg",
+                "page10.jpg",
+                "extra/page1.jpg",
+            ])
+        );
+    }
+
+    #[test]
+    fn test_sort_page_names_in_nested_folders() {
+        let mut pages = names(&[
+            "chapter 10/001.jpg",
+            "chapter 2/extras/001.jpg",
+            "zzz.jpg",
+            "chapter 2/002.jpg",
+            "chapter 2/001.jpg",
+            "cover.jpg",
+        ]);
+
+        sort_page_names(&mut pages);
+
+        assert_eq!(
+            pages,
+            names(&[
+                "cover.jpg",
+                "zzz.jpg",
+                "chapter 2/001.jpg",
+                "chapter 2/002.jpg",
+                "chapter 2/extras/001.jpg",
+                "chapter 10/001.jpg",
+            ])
+        );
+    }
+
+    #[test]
+    fn test_sorted_image_names_skips_other_files() {
+        let sorted = sorted_image_names(
+            names(&["10.png", "ComicInfo.xml", "9.png"]),
+            &HashMap::new(),
+        );
+        assert_eq!(sorted, names(&["9.png", "10.png"]));
+    }
+
+    #[test]
+    fn test_sorted_image_names_reads_magic_bytes() {
+        let headers: HashMap<String, Vec<u8>> = [
+            ("0002", b"\x89PNG\r\n\x1A\n".to_vec()),
+            ("0001.dat", b"\xFF\xD8\xFF\xE0".to_vec()),
+            ("0003.jpg", b"<html>".to_vec()),
+            ("__MACOSX/._0004.jpg", b"\xFF\xD8\xFF\xE0".to_vec()),
+        ]
+        .into_iter()
+        .map(|(name, header)| (name.to_string(), header))
+        .collect();
+
+        let sorted = sorted_image_names(
+            names(&[
+                "0003.jpg",
+                "0002",
+                "0001.dat",
+                "__MACOSX/._0004.jpg",
+                "0005.jpg",
+            ]),
+            &headers,
+        );
+        assert_eq!(sorted, names(&["0001.dat", "0002", "0005.jpg"]));
+    }
+
+    #[test]
+    fn test_check_page_order() {
+        let comic_info = ComicInfo::parse(
+            r#"<ComicInfo><Pages><Page Image="0" /><Page Image="1" Bookmark="B" /></Pages></ComicInfo>"#,
+        )
+        .unwrap();
+        let images = sorted_image_names(names(&["p1.jpg", "p10.jpg", "p2.jpg"]), &HashMap::new());
+
+        let warning = check_page_order(&comic_info, &HashMap::new(), &images).unwrap();
+        assert_eq!(
+            warning.mismatches,
+            vec![PageOrderMismatch {
+                image: 1,
+                recorded: "p10.jpg".to_string(),
+                current: "p2.jpg".to_string(),
+            }]
+        );
+
+        let recorded = HashMap::from([(0, "p1.jpg".to_string()), (1, "p2.jpg".to_string())]);
+        assert_eq!(check_page_order(&comic_info, &recorded, &images), None);
+
+        let recorded = HashMap::from([(1, "p10.jpg".to_string())]);
+        let warning = check_page_order(&comic_info, &recorded, &images).unwrap();
+        assert_eq!(warning.mismatches.len(), 1);
+        assert!(warning.message.starts_with("ComicInfo.xml was written"));
+    }
+}
@@ -0,0 +1,680 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use zip::CompressionMethod;
+use zip::write::FileOptions;
+
+use super::atomic_write::AtomicFile;
+use super::backend::{detect_format, ensure_writable, invalidate_zip_archive};
+use super::image_format::{MAGIC_BYTES_LIMIT, PageImageFormat};
+use super::manager::suppress_next_archive_event;
+use super::order::sorted_image_files;
+use super::reader::read_archive;
+use super::types::{
+    Archive, ArchiveFormat, ErrorResponse, ReadArchiveError, ToErrorResponse, WriteArchiveError,
+};
+use crate::comicinfo::ComicInfo;
+
+/// Name of the image at `index` once the pages are renumbered: the 1-based
+/// page number, zero-padded to the width of the page count, with the
+/// original extension.
+pub(crate) fn page_file_name(index: usize, count: usize, original: &str) -> String {
+    let width = count.to_string().len().max(3);
+    let number = format!("{:0width$}", index + 1, width = width);
+
+    match Path::new(original).extension() {
+        Some(extension) => format!("{}.{}", number, extension.to_string_lossy()),
+        None => number,
+    }
+}
+
+pub(crate) fn ensure_zip(path: &str) -> Result<(), WriteArchiveError> {
+    ensure_writable(path)?;
+
+    match detect_format(path)? {
+        ArchiveFormat::Zip => Ok(()),
+        format => Err(WriteArchiveError::RequiresZip(format)),
+    }
+}
+
+/// Where the bytes of a page come from when the archive is rewritten.
+enum PageSource {
+    /// An existing entry, copied without being decompressed.
+    Entry(String),
+    /// An image file on disk.
+    File(PathBuf),
+}
+
+/// A page of the rewritten archive.
+struct PlannedPage {
+    source: PageSource,
+    name: String,
+    /// Position of the image whose `<Page>` metadata the page keeps.
+    original_index: Option<usize>,
+}
+
+impl PlannedPage {
+    fn existing(sorted: &[String], index: usize, name: String) -> Self {
+        Self {
+            source: PageSource::Entry(sorted[index].clone()),
+            name,
+            original_index: Some(index),
+        }
+    }
+}
+
+/// Writes a CBZ archive whose images are exactly `pages`, in order.
+///
+/// Entries that are not among the current `images` are copied unchanged. When `xml_content` is
+/// given it replaces ComicInfo.xml.
+fn write_pages(
+    path: &str,
+    images: &[String],
+    pages: &[PlannedPage],
+    xml_content: Option<&str>,
+) -> Result<(), WriteArchiveError> {
+    let images: HashSet<&str> = images.iter().map(String::as_str).collect();
+    let mut output = AtomicFile::create(path).map_err(WriteArchiveError::Io)?;
+
+    {
+        let original_file = fs::File::open(path).map_err(WriteArchiveError::Io)?;
+        let mut original_archive =
+            zip::ZipArchive::new(BufReader::new(original_file)).map_err(WriteArchiveError::Zip)?;
+
+        let mut new_archive = zip::ZipWriter::new(BufWriter::new(output.as_file_mut()));
+
+        for i in 0..original_archive.len() {
+            let file = original_archive
+                .by_index_raw(i)
+                .map_err(WriteArchiveError::Zip)?;
+
+            let skip = images.contains(file.name())
+                || (xml_content.is_some() && file.name() == "ComicInfo.xml");
+
+            if !skip {
+                new_archive
+                    .raw_copy_file(file)
+                    .map_err(WriteArchiveError::Zip)?;
+            }
+        }
+
+        for page in pages {
+            match &page.source {
+                PageSource::Entry(name) => {
+                    let index = original_archive.index_for_name(name).ok_or_else(|| {
+                        WriteArchiveError::Read(ReadArchiveError::EntryNotFound(name.clone()))
+                    })?;
+                    let file = original_archive
+                        .by_index_raw(index)
+                        .map_err(WriteArchiveError::Zip)?;
+
+                    if file.name() == page.name {
+                        new_archive.raw_copy_file(file)
+                    } else {
+                        new_archive.raw_copy_file_rename(file, &page.name)
+                    }
+                    .map_err(WriteArchiveError::Zip)?;
+                }
+                PageSource::File(file_path) => {
+                    let data = fs::read(file_path).map_err(WriteArchiveError::Io)?;
+                    let options =
+                        FileOptions::<()>::default().compression_method(CompressionMethod::Stored);
+
+                    new_archive
+                        .start_file(page.name.as_str(), options)
+                        .map_err(WriteArchiveError::Zip)?;
+                    new_archive
+                        .write_all(&data)
+                        .map_err(WriteArchiveError::Io)?;
+                }
+            }
+        }
+
+        if let Some(xml_content) = xml_content {
+            let xml_options =
+                FileOptions::<()>::default().compression_method(CompressionMethod::Stored);
+
+            new_archive
+                .start_file("ComicInfo.xml", xml_options)
+                .map_err(WriteArchiveError::Zip)?;
+            new_archive
+                .write_all(xml_content.as_bytes())
+                .map_err(WriteArchiveError::Io)?;
+        }
+
+        new_archive
+            .finish()
+            .map_err(WriteArchiveError::Zip)?
+            .flush()
+            .map_err(WriteArchiveError::Io)?;
+    }
+
+    output.commit().map_err(WriteArchiveError::Io)?;
+    invalidate_zip_archive(path);
+    Ok(())
+}
+
+/// Re-indexes the `<Pages>` block for the planned pages.
+///
+/// Every `<Page>` follows its image to its new position. Pages of removed
+/// images are dropped, and replaced images lose their stored dimensions.
+/// A `PageCount` that was set is updated to the new number of pages.
+fn reindex_pages(comic_info: &mut ComicInfo, pages: &[PlannedPage]) {
+    if comic_info.page_count > 0 {
+        comic_info.page_count = pages.len() as i32;
+    }
+
+    let Some(described) = comic_info.pages.as_mut() else {
+        return;
+    };
+
+    let positions: HashMap<i32, usize> = pages
+        .iter()
+        .enumerate()
+        .filter_map(|(position, page)| page.original_index.map(|index| (index as i32, position)))
+        .collect();
+
+    described.page.retain_mut(|page| {
+        let Some(&position) = positions.get(&page.image) else {
+            return false;
+        };
+
+        let planned = &pages[position];
+
+        if let PageSource::File(_) = planned.source {
+            page.image_width = -1;
+            page.image_height = -1;
+            page.image_size = 0;
+        }
+
+        page.image = position as i32;
+        page.filename = Some(planned.name.clone());
+        true
+    });
+
+    described.page.sort_by_key(|p| p.image);
+}
+
+/// Rewrites a CBZ archive with the planned pages and a re-indexed
+/// ComicInfo.xml. Returns the new image names in order.
+fn apply_pages(
+    path: &str,
+    archive: &Archive,
+    pages: Vec<PlannedPage>,
+) -> Result<Vec<String>, ErrorResponse> {
+    let xml_content = match archive.comic_info.clone() {
+        Some(mut comic_info) => {
+            reindex_pages(&mut comic_info, &pages);
+            Some(comic_info.to_xml().map_err(|e| e.to_error_response())?)
+        }
+        None => None,
+    };
+
+    suppress_next_archive_event(path);
+    write_pages(path, &archive.image_files, &pages, xml_content.as_deref())
+        .map_err(|e| e.to_error_response())?;
+
+    Ok(pages.into_iter().map(|page| page.name).collect())
+}
+
+/// Checks that `file_path` exists on disk and holds an image, judged by its
+/// magic bytes.
+fn check_image_source(file_path: &str) -> Result<PathBuf, WriteArchiveError> {
+    let path = PathBuf::from(file_path);
+
+    if !path.is_file() {
+        return Err(WriteArchiveError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} does not exist", path.display()),
+        )));
+    }
+
+    let mut header = Vec::new();
+    fs::File::open(&path)
+        .and_then(|file| file.take(MAGIC_BYTES_LIMIT as u64).read_to_end(&mut header))
+        .map_err(WriteArchiveError::Io)?;
+
+    if PageImageFormat::detect(&header).is_none() {
+        return Err(WriteArchiveError::NotAnImage(file_path.to_string()));
+    }
+
+    Ok(path)
+}
+
+fn image_index(sorted: &[String], file_name: &str) -> Result<usize, WriteArchiveError> {
+    sorted
+        .iter()
+        .position(|name| name == file_name)
+        .ok_or_else(|| {
+            WriteArchiveError::Read(ReadArchiveError::EntryNotFound(file_name.to_string()))
+        })
+}
+
+/// Checks that `order` lists every image of the archive exactly once.
+fn check_page_order(sorted: &[String], order: &[String]) -> Result<(), WriteArchiveError> {
+    let images: HashSet<&str> = sorted.iter().map(String::as_str).collect();
+    let mut seen = HashSet::new();
+
+    for name in order {
+        if !images.contains(name.as_str()) {
+            return Err(WriteArchiveError::InvalidPageOrder(format!(
+                "{} is not an image in the archive",
+                name
+            )));
+        }
+
+        if !seen.insert(name.as_str()) {
+            return Err(WriteArchiveError::InvalidPageOrder(format!(
+                "{} is listed more than once",
+                name
+            )));
+        }
+    }
+
+    if let Some(missing) = sorted.iter().find(|name| !seen.contains(name.as_str())) {
+        return Err(WriteArchiveError::InvalidPageOrder(format!(
+            "{} is missing",
+            missing
+        )));
+    }
+
+    Ok(())
+}
+
+/// Puts the images of a CBZ archive in the given order.
+///
+/// The images are renamed to zero-padded page numbers so that their sorted
+/// order is the new order, and every `<Page>` is moved along with its image.
+/// Returns the new image names in order.
+pub fn reorder_pages_impl(path: String, order: Vec<String>) -> Result<Vec<String>, ErrorResponse> {
+    ensure_zip(&path).map_err(|e| e.to_error_response())?;
+    let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
+
+    let sorted = sorted_image_files(&archive);
+    check_page_order(&sorted, &order).map_err(|e| e.to_error_response())?;
+
+    let positions: HashMap<&str, usize> = sorted
+        .iter()
+        .enumerate()
+        .map(|(index, name)| (name.as_str(), index))
+        .collect();
+    let pages = order
+        .iter()
+        .enumerate()
+        .map(|(position, name)| {
+            let new_name = page_file_name(position, order.len(), name);
+            PlannedPage::existing(&sorted, positions[name.as_str()], new_name)
+        })
+        .collect();
+
+    apply_pages(&path, &archive, pages)
+}
+
+/// Removes images from a CBZ archive, together with their `<Page>` entries.
+///
+/// The remaining images keep their names. Returns the remaining image names
+/// in order.
+pub fn remove_pages_impl(
+    path: String,
+    file_names: Vec<String>,
+) -> Result<Vec<String>, ErrorResponse> {
+    ensure_zip(&path).map_err(|e| e.to_error_response())?;
+    let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
+
+    let sorted = sorted_image_files(&archive);
+    let mut removed = HashSet::new();
+
+    for file_name in &file_names {
+        removed.insert(image_index(&sorted, file_name).map_err(|e| e.to_error_response())?);
+    }
+
+    let pages = (0..sorted.len())
+        .filter(|index| !removed.contains(index))
+        .map(|index| PlannedPage::existing(&sorted, index, sorted[index].clone()))
+        .collect();
+
+    apply_pages(&path, &archive, pages)
+}
+
+/// Inserts image files from disk into a CBZ archive so that they become the
+/// pages starting at `position`.
+///
+/// Every image is renamed to its zero-padded page number so that the sorted
+/// order matches the new page order. Returns the new image names in order.
+pub fn insert_pages_impl(
+    path: String,
+    position: usize,
+    file_paths: Vec<String>,
+) -> Result<Vec<String>, ErrorResponse> {
+    ensure_zip(&path).map_err(|e| e.to_error_response())?;
+    let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
+
+    let sorted = sorted_image_files(&archive);
+
+    if position > sorted.len() {
+        return Err(WriteArchiveError::InvalidPageOrder(format!(
+            "cannot insert at page {} of an archive with {} pages",
+            position,
+            sorted.len()
+        ))
+        .to_error_response());
+    }
+
+    let sources = file_paths
+        .iter()
+        .map(|file_path| check_image_source(file_path))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_error_response())?;
+
+    let total = sorted.len() + sources.len();
+    let mut pages = Vec::with_capacity(total);
+
+    for index in 0..position {
+        pages.push(PlannedPage::existing(
+            &sorted,
+            index,
+            page_file_name(index, total, &sorted[index]),
+        ));
+    }
+
+    for (offset, source) in sources.into_iter().enumerate() {
+        let name = page_file_name(position + offset, total, &source.to_string_lossy());
+        pages.push(PlannedPage {
+            source: PageSource::File(source),
+            name,
+            original_index: None,
+        });
+    }
+
+    for index in position..sorted.len() {
+        let new_position = pages.len();
+        pages.push(PlannedPage::existing(
+            &sorted,
+            index,
+            page_file_name(new_position, total, &sorted[index]),
+        ));
+    }
+
+    apply_pages(&path, &archive, pages)
+}
+
+/// Replaces the image `file_name` of a CBZ archive with an image file from
+/// disk.
+///
+/// The page keeps its name, with the extension of the new file, and its
+/// `<Page>` settings; the stored dimensions are cleared. Returns the image
+/// names in order.
+pub fn replace_page_impl(
+    path: String,
+    file_name: String,
+    file_path: String,
+) -> Result<Vec<String>, ErrorResponse> {
+    ensure_zip(&path).map_err(|e| e.to_error_response())?;
+    let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
+
+    let sorted = sorted_image_files(&archive);
+    let replaced = image_index(&sorted, &file_name).map_err(|e| e.to_error_response())?;
+    let source = check_image_source(&file_path).map_err(|e| e.to_error_response())?;
+
+    let new_name = match source.extension() {
+        Some(extension) => Path::new(&file_name)
+            .with_extension(extension)
+            .to_string_lossy()
+            .into_owned(),
+        None => file_name.clone(),
+    };
+
+    if new_name != file_name && archive.files.iter().any(|f| f.name == new_name) {
+        return Err(WriteArchiveError::OutputExists(new_name).to_error_response());
+    }
+
+    let pages = (0..sorted.len())
+        .map(|index| {
+            if index != replaced {
+                return PlannedPage::existing(&sorted, index, sorted[index].clone());
+            }
+
+            PlannedPage {
+                source: PageSource::File(source.clone()),
+                name: new_name.clone(),
+                original_index: Some(index),
+            }
+        })
+        .collect();
+
+    apply_pages(&path, &archive, pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::tests::write_cbz;
+    use crate::archive::types::{ErrorDetails, ErrorResponseType};
+    use crate::comicinfo::ComicPageType;
+    use std::io::Read;
+
+    fn read_entry(path: &str, name: &str) -> Vec<u8> {
+        let mut archive = zip::ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+        let mut data = Vec::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_page_file_name() {
+        assert_eq!(page_file_name(0, 12, "cover.JPG"), "001.JPG");
+        assert_eq!(page_file_name(41, 1200, "dir/page.png"), "0042.png");
+        assert_eq!(page_file_name(2, 3, "noext"), "003");
+    }
+
+    #[test]
+    fn test_check_page_order() {
+        let sorted = ["a.jpg", "b.jpg"].map(String::from);
+
+        assert!(check_page_order(&sorted, &["b.jpg", "a.jpg"].map(String::from)).is_ok());
+        assert!(check_page_order(&sorted, &["b.jpg"].map(String::from)).is_err());
+        assert!(check_page_order(&sorted, &["b.jpg", "b.jpg"].map(String::from)).is_err());
+        assert!(check_page_order(&sorted, &["a.jpg", "c.jpg"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_reorder_pages_moves_page_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comic.cbz");
+        write_cbz(
+            &path,
+            &[
+                ("a.jpg", b"\xFF\xD8\xFFpage a"),
+                ("b.jpg", b"\xFF\xD8\xFFpage b"),
+                ("c.png", b"\xFF\xD8\xFFpage c"),
+                ("notes.txt", b"notes"),
+                (
+                    "ComicInfo.xml",
+                    br#"<ComicInfo><Title>T</Title><Pages><Page Image="0" Type="Story" Bookmark="A" /><Page Image="2" Type="FrontCover" /></Pages></ComicInfo>"#,
+                ),
+            ],
+        );
+        let path = path.to_str().unwrap().to_string();
+
+        let new_names = reorder_pages_impl(
+            path.clone(),
+            ["c.png", "a.jpg", "b.jpg"].map(String::from).to_vec(),
+        )
+        .unwrap();
+
+        assert_eq!(new_names, vec!["001.png", "002.jpg", "003.jpg"]);
+        assert_eq!(read_entry(&path, "001.png"), b"\xFF\xD8\xFFpage c");
+        assert_eq!(read_entry(&path, "002.jpg"), b"\xFF\xD8\xFFpage a");
+        assert_eq!(read_entry(&path, "notes.txt"), b"notes");
+
+        let archive = read_archive(&path).unwrap();
+        assert_eq!(
+            sorted_image_files(&archive),
+            vec!["001.png", "002.jpg", "003.jpg"]
+        );
+
+        let comic_info = archive.comic_info.unwrap();
+        assert_eq!(comic_info.title.as_deref(), Some("T"));
+        let pages = comic_info.pages.unwrap().page;
+        assert_eq!(pages[0].image, 0);
+        assert_eq!(pages[0].type_, Some(ComicPageType::FrontCover));
+        assert_eq!(pages[1].image, 1);
+        assert_eq!(pages[1].bookmark, "A");
+
+        let xml = String::from_utf8(read_entry(&path, "ComicInfo.xml")).unwrap();
+        assert!(xml.contains("<!-- filename: 001.png -->"));
+        assert!(xml.contains("<!-- filename: 002.jpg -->"));
+    }
+
+    #[test]
+    fn test_reorder_pages_requires_cbz() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("001.jpg"), b"page").unwrap();
+
+        let err = reorder_pages_impl(
+            dir.path().to_str().unwrap().to_string(),
+            vec!["001.jpg".to_string()],
+        )
+        .unwrap_err();
+
+        assert!(err.message.contains("Only CBZ archives"));
+        assert_eq!(err.error_type, ErrorResponseType::UnsupportedFormat);
+        assert_eq!(
+            err.details,
+            Some(ErrorDetails::Format {
+                format: ArchiveFormat::Directory,
+                convert_to: None
+            })
+        );
+    }
+
+    fn write_comic(dir: &Path) -> String {
+        let path = dir.join("comic.cbz");
+        write_cbz(
+            &path,
+            &[
+                ("001.jpg", b"\xFF\xD8\xFFpage 1"),
+                ("002.jpg", b"\xFF\xD8\xFFpage 2"),
+                ("003.jpg", b"\xFF\xD8\xFFpage 3"),
+                (
+                    "ComicInfo.xml",
+                    br#"<ComicInfo><PageCount>3</PageCount><Pages><Page Image="0" Type="FrontCover" /><Page Image="1" Bookmark="Two" ImageWidth="10" ImageHeight="20" /><Page Image="2" Bookmark="Three" /></Pages></ComicInfo>"#,
+                ),
+            ],
+        );
+        path.to_str().unwrap().to_string()
+    }
+
+    fn pages_of(path: &str) -> Vec<(i32, String)> {
+        read_archive(path)
+            .unwrap()
+            .comic_info
+            .unwrap()
+            .pages
+            .unwrap()
+            .page
+            .into_iter()
+            .map(|p| (p.image, p.bookmark))
+            .collect()
+    }
+
+    #[test]
+    fn test_remove_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_comic(dir.path());
+
+        let names = remove_pages_impl(path.clone(), vec!["002.jpg".to_string()]).unwrap();
+
+        assert_eq!(names, vec!["001.jpg", "003.jpg"]);
+        assert_eq!(read_entry(&path, "003.jpg"), b"\xFF\xD8\xFFpage 3");
+        assert_eq!(
+            pages_of(&path),
+            vec![(0, String::new()), (1, "Three".to_string())]
+        );
+        assert_eq!(
+            read_archive(&path).unwrap().comic_info.unwrap().page_count,
+            2
+        );
+
+        let err = remove_pages_impl(path, vec!["missing.jpg".to_string()]).unwrap_err();
+        assert_eq!(err.error_type, ErrorResponseType::NotFound);
+        assert_eq!(
+            err.details,
+            Some(ErrorDetails::Entry {
+                file_name: "missing.jpg".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_insert_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_comic(dir.path());
+        let source = dir.path().join("extra.png");
+        fs::write(&source, b"\xFF\xD8\xFFextra").unwrap();
+
+        let names =
+            insert_pages_impl(path.clone(), 1, vec![source.to_str().unwrap().to_string()]).unwrap();
+
+        assert_eq!(names, vec!["001.jpg", "002.png", "003.jpg", "004.jpg"]);
+        assert_eq!(read_entry(&path, "002.png"), b"\xFF\xD8\xFFextra");
+        assert_eq!(read_entry(&path, "003.jpg"), b"\xFF\xD8\xFFpage 2");
+        assert_eq!(
+            pages_of(&path),
+            vec![
+                (0, String::new()),
+                (2, "Two".to_string()),
+                (3, "Three".to_string())
+            ]
+        );
+
+        let err = insert_pages_impl(path.clone(), 9, vec![]).unwrap_err();
+        assert_eq!(err.error_type, ErrorResponseType::ValidationFailed);
+
+        let notes = dir.path().join("notes.png");
+        fs::write(&notes, b"notes").unwrap();
+        let err =
+            insert_pages_impl(path, 0, vec![notes.to_str().unwrap().to_string()]).unwrap_err();
+        assert_eq!(err.error_type, ErrorResponseType::UnsupportedFormat);
+        assert!(err.message.contains("not a supported image"));
+    }
+
+    #[test]
+    fn test_replace_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_comic(dir.path());
+        let source = dir.path().join("new.png");
+        fs::write(&source, b"\xFF\xD8\xFFreplacement").unwrap();
+
+        let names = replace_page_impl(
+            path.clone(),
+            "002.jpg".to_string(),
+            source.to_str().unwrap().to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(names, vec!["001.jpg", "002.png", "003.jpg"]);
+        assert_eq!(read_entry(&path, "002.png"), b"\xFF\xD8\xFFreplacement");
+
+        let pages = read_archive(&path)
+            .unwrap()
+            .comic_info
+            .unwrap()
+            .pages
+            .unwrap()
+            .page;
+        assert_eq!(pages[1].image, 1);
+        assert_eq!(pages[1].bookmark, "Two");
+        assert_eq!(pages[1].image_width, -1);
+        assert_eq!(pages[1].image_height, -1);
+
+        let xml = String::from_utf8(read_entry(&path, "ComicInfo.xml")).unwrap();
+        assert!(xml.contains("<!-- filename: 002.png -->"));
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use percent_encoding::percent_decode_str;
+use tauri::http::{Request, Response, StatusCode, Uri, header};
+
+use super::image_format::{DisplayableImage, ThumbnailOptions, to_displayable, to_thumbnail};
+use super::reader::{entry_checksums, get_file_data};
+use super::thumbnail_cache::{ThumbnailKey, thumbnail_cache};
+use super::types::ReadArchiveError;
+
+/// Serves archive pages to the webview as
+/// `kikou://archive/<archive path>/<entry name>?w=<max dimension>&q=<quality>`,
+/// with both the archive path and the entry name percent-encoded as a single
+/// segment. Platforms that route custom schemes through
+/// `http://kikou.localhost/` use `/archive/<archive path>/<entry name>`.
+pub const PROTOCOL: &str = "kikou";
+
+/// Archives the user has opened, the only ones the protocol serves from.
+static OPENED_ARCHIVES: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+pub fn allow_archive(path: &str) {
+    OPENED_ARCHIVES.lock().unwrap().insert(path.to_string());
+}
+
+pub fn forget_archive(path: &str) {
+    OPENED_ARCHIVES.lock().unwrap().remove(path);
+}
+
+fn is_opened(path: &str) -> bool {
+    OPENED_ARCHIVES.lock().unwrap().contains(path)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PageRequest {
+    archive: String,
+    entry: String,
+    thumbnail: Option<ThumbnailOptions>,
+}
+
+fn decode_segment(segment: &str) -> Option<String> {
+    let decoded = percent_decode_str(segment).decode_utf8().ok()?;
+
+    if decoded.is_empty() {
+        return None;
+    }
+
+    Some(decoded.into_owned())
+}
+
+fn parse_page_request(uri: &Uri) -> Option<PageRequest> {
+    let mut segments = uri.path().trim_start_matches('/').split('/');
+
+    if uri.host() != Some("archive") && segments.next() != Some("archive") {
+        return None;
+    }
+
+    let archive = decode_segment(segments.next()?)?;
+    let entry = decode_segment(segments.next()?)?;
+
+    if segments.next().is_some() {
+        return None;
+    }
+
+    let mut thumbnail: Option<ThumbnailOptions> = None;
+
+    for (key, value) in url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes()) {
+        match key.as_ref() {
+            "w" => thumbnail.get_or_insert_default().max_dimension = value.parse().ok()?,
+            "q" => thumbnail.get_or_insert_default().quality = value.parse().ok()?,
+            _ => {}
+        }
+    }
+
+    Some(PageRequest {
+        archive,
+        entry,
+        thumbnail,
+    })
+}
+
+/// Parses a single `bytes=` range. Returns `None` when the range cannot be
+/// satisfied for a body of `len` bytes.
+fn parse_range(value: &str, len: usize) -> Option<Range<usize>> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: usize = suffix.parse().ok()?;
+            len.saturating_sub(suffix)..len
+        }
+        (start, "") => start.parse().ok()?..len,
+        (start, end) => {
+            let end: usize = end.parse().ok()?;
+            start.parse().ok()?..len.min(end.saturating_add(1))
+        }
+    };
+
+    if range.start >= range.end {
+        return None;
+    }
+
+    Some(range)
+}
+
+type ProtocolError = (StatusCode, String);
+
+fn read_error(err: ReadArchiveError) -> ProtocolError {
+    let status = match &err {
+        ReadArchiveError::EntryNotFound(_)
+        | ReadArchiveError::Zip(zip::result::ZipError::FileNotFound) => StatusCode::NOT_FOUND,
+        ReadArchiveError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            StatusCode::NOT_FOUND
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    (status, err.to_string())
+}
+
+fn etag(crc32: u32, thumbnail: Option<&ThumbnailOptions>) -> String {
+    match thumbnail {
+        Some(options) => format!(
+            "\"{:08x}-{}-{}\"",
+            crc32, options.max_dimension, options.quality
+        ),
+        None => format!("\"{:08x}\"", crc32),
+    }
+}
+
+fn page_image(page: &PageRequest, crc32: u32, data: Vec<u8>) -> Result<DisplayableImage, String> {
+    let Some(options) = &page.thumbnail else {
+        return to_displayable(&page.entry, data);
+    };
+
+    let Some(cache) = thumbnail_cache() else {
+        return to_thumbnail(&page.entry, data, options);
+    };
+
+    cache.get_or_insert_with(&ThumbnailKey::new(&page.archive, crc32, options), || {
+        to_thumbnail(&page.entry, data, options)
+    })
+}
+
+fn serve(request: &Request<Vec<u8>>) -> Result<Response<Vec<u8>>, ProtocolError> {
+    let page = parse_page_request(request.uri()).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Expected {}://archive/<archive>/<entry>", PROTOCOL),
+        )
+    })?;
+
+    if !is_opened(&page.archive) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("{} has not been opened", page.archive),
+        ));
+    }
+
+    let recorded_crc32 = entry_checksums(&page.archive).get(&page.entry).copied();
+    let if_none_match = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok());
+
+    if let Some(crc32) = recorded_crc32 {
+        let etag = etag(crc32, page.thumbnail.as_ref());
+
+        if if_none_match == Some(etag.as_str()) {
+            return Ok(not_modified(etag));
+        }
+    }
+
+    let data = get_file_data(&page.archive, &page.entry).map_err(read_error)?;
+    let crc32 = recorded_crc32.unwrap_or_else(|| crc32fast::hash(&data));
+    let etag = etag(crc32, page.thumbnail.as_ref());
+
+    if if_none_match == Some(etag.as_str()) {
+        return Ok(not_modified(etag));
+    }
+
+    let image = page_image(&page, crc32, data)
+        .map_err(|message| (StatusCode::UNSUPPORTED_MEDIA_TYPE, message))?;
+    let len = image.data.len();
+
+    let response = Response::builder()
+        .header(header::CONTENT_TYPE, image.mime_type)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::ETAG, etag);
+
+    let Some(range) = request.headers().get(header::RANGE) else {
+        return Ok(response
+            .header(header::CONTENT_LENGTH, len)
+            .body(image.data)
+            .unwrap());
+    };
+
+    let Some(range) = range
+        .to_str()
+        .ok()
+        .and_then(|value| parse_range(value, len))
+    else {
+        return Ok(response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .body(Vec::new())
+            .unwrap());
+    };
+
+    Ok(response
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", range.start, range.end - 1, len),
+        )
+        .header(header::CONTENT_LENGTH, range.len())
+        .body(image.data[range].to_vec())
+        .unwrap())
+}
+
+fn not_modified(etag: String) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(StatusCode::NOT_MODIFIED)
+        .header(header::ETAG, etag)
+        .body(Vec::new())
+        .unwrap()
+}
+
+/// Answers a request made to the [`PROTOCOL`] scheme.
+pub fn page_response(request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    serve(request).unwrap_or_else(|(status, message)| {
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(message.into_bytes())
+            .unwrap()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::tests::write_cbz;
+    use std::io::Cursor;
+
+    fn page_url(archive: &str, entry: &str, query: &str) -> String {
+        let encode = |segment: &str| {
+            percent_encoding::utf8_percent_encode(segment, percent_encoding::NON_ALPHANUMERIC)
+                .to_string()
+        };
+
+        format!(
+            "kikou://archive/{}/{}{}",
+            encode(archive),
+            encode(entry),
+            query
+        )
+    }
+
+    fn get(url: &str, headers: &[(header::HeaderName, &str)]) -> Response<Vec<u8>> {
+        let mut request = Request::builder().uri(url);
+
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+
+        page_response(&request.body(Vec::new()).unwrap())
+    }
+
+    fn comic_with_png(dir: &std::path::Path) -> (String, Vec<u8>) {
+        let mut png = Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(40, 20)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+
+        let path = dir.join("my comic.cbz");
+        write_cbz(&path, &[("pages/001.png", &png)]);
+
+        (path.to_str().unwrap().to_string(), png)
+    }
+
+    #[test]
+    fn test_parse_page_request() {
+        let uri: Uri = "kikou://archive/%2Fcomics%2Fa%20b.cbz/pages%2F001.jpg?w=300&q=70"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            parse_page_request(&uri),
+            Some(PageRequest {
+                archive: "/comics/a b.cbz".to_string(),
+                entry: "pages/001.jpg".to_string(),
+                thumbnail: Some(ThumbnailOptions {
+                    max_dimension: 300,
+                    quality: 70,
+                }),
+            })
+        );
+
+        let uri: Uri = "http://kikou.localhost/archive/a.cbz/001.jpg"
+            .parse()
+            .unwrap();
+        assert_eq!(parse_page_request(&uri).unwrap().thumbnail, None);
+
+        for url in [
+            "kikou://other/a.cbz/001.jpg",
+            "kikou://archive/a.cbz",
+            "kikou://archive/a.cbz/pages/001.jpg",
+            "kikou://archive/a.cbz/001.jpg?w=big",
+        ] {
+            assert_eq!(parse_page_request(&url.parse().unwrap()), None, "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-9", 100), Some(0..10));
+        assert_eq!(parse_range("bytes=90-", 100), Some(90..100));
+        assert_eq!(parse_range("bytes=-10", 100), Some(90..100));
+        assert_eq!(parse_range("bytes=50-500", 100), Some(50..100));
+        assert_eq!(parse_range("bytes=100-", 100), None);
+        assert_eq!(
+            parse_range(&format!("bytes=0-{}", usize::MAX), 100),
+            Some(0..100)
+        );
+        assert_eq!(parse_range("items=0-9", 100), None);
+    }
+
+    #[test]
+    fn test_serves_only_opened_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, png) = comic_with_png(dir.path());
+        let url = page_url(&path, "pages/001.png", "");
+
+        assert_eq!(get(&url, &[]).status(), StatusCode::FORBIDDEN);
+
+        allow_archive(&path);
+
+        let response = get(&url, &[]);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        assert_eq!(response.body(), &png);
+
+        let missing = get(&page_url(&path, "pages/002.png", ""), &[]);
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        forget_archive(&path);
+        assert_eq!(get(&url, &[]).status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_serves_ranges_thumbnails_and_etags() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, png) = comic_with_png(dir.path());
+        allow_archive(&path);
+
+        let url = page_url(&path, "pages/001.png", "");
+
+        let response = get(&url, &[(header::RANGE, "bytes=0-7")]);
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.body(), &png[..8]);
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            format!("bytes 0-7/{}", png.len()).as_str()
+        );
+
+        let response = get(&url, &[(header::RANGE, "bytes=100000-")]);
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+        let etag = get(&url, &[]).headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let response = get(&url, &[(header::IF_NONE_MATCH, &etag)]);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let response = get(&page_url(&path, "pages/001.png", "?w=10"), &[]);
+        let size = imagesize::blob_size(response.body()).unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/jpeg");
+        assert_eq!((size.width, size.height), (10, 5));
+        assert_ne!(response.headers()[header::ETAG], etag.as_str());
+
+        forget_archive(&path);
+    }
+}
@@ -1,32 +1,129 @@
-use super::types::{Archive, ArchiveFile, ReadArchiveError};
+use super::backend::{ArchiveBackend, FileStamp, open_backend};
+use super::image_format::MAGIC_BYTES_LIMIT;
+use super::order::sorted_image_names;
+use super::types::{Archive, ArchiveFile, ArchiveFormat, ReadArchiveError, is_junk_entry};
 use crate::comicinfo::ComicInfo;
-use rayon::prelude::*;
-use std::io::Read;
-use std::sync::Arc;
-
-fn open_zip_archive(path: &str) -> Result<zip::ZipArchive<std::fs::File>, ReadArchiveError> {
-    let file = std::fs::File::open(path).map_err(ReadArchiveError::Io)?;
-    let archive = zip::ZipArchive::new(file).map_err(ReadArchiveError::Zip)?;
-    Ok(archive)
+use crate::comicinfo::lenient::ParseDiagnostic;
+use log::debug;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+use std::time::Instant;
+
+const COMIC_INFO_FILE: &str = "ComicInfo.xml";
+
+/// Number of archives whose pages are kept around.
+const MAX_CACHED_IMAGE_FILES: usize = 32;
+
+struct CachedImageFiles {
+    stamp: FileStamp,
+    image_files: Vec<String>,
+    last_used: Instant,
 }
 
-pub fn read_archive(path: &str) -> Result<Archive, ReadArchiveError> {
-    let mut archive = open_zip_archive(path)?;
+/// The pages found in each archive file, for as long as it is unchanged.
+/// Formats without random access extract every entry to read its first
+/// bytes, which should not happen on every read.
+static IMAGE_FILES: Lazy<Mutex<HashMap<String, CachedImageFiles>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
 
-    let mut files = Vec::new();
-    let mut comic_info = None;
+fn read_magic_bytes(
+    backend: &dyn ArchiveBackend,
+    path: &str,
+    files: &[ArchiveFile],
+) -> HashMap<String, Vec<u8>> {
+    let names: Vec<String> = files
+        .iter()
+        .map(|f| f.name.clone())
+        .filter(|name| name != COMIC_INFO_FILE && !is_junk_entry(name))
+        .collect();
+    let headers = Mutex::new(HashMap::with_capacity(names.len()));
+
+    backend.read_file_headers(
+        path,
+        &names,
+        MAGIC_BYTES_LIMIT,
+        &|file_name, data, _| {
+            headers.lock().unwrap().insert(file_name, data);
+        },
+        &|file_name, message| debug!("Failed to read the start of {}: {}", file_name, message),
+    );
+
+    headers.into_inner().unwrap()
+}
+
+/// Returns the entries of `files` that hold images, in page order.
+fn image_files(backend: &dyn ArchiveBackend, path: &str, files: &[ArchiveFile]) -> Vec<String> {
+    let names = files.iter().map(|f| f.name.clone());
+
+    // Folders change without their own modification time changing, and their
+    // files are cheap to read anyway.
+    let stamp = match backend.format() {
+        ArchiveFormat::Directory => None,
+        _ => fs::metadata(path)
+            .ok()
+            .map(|metadata| FileStamp::of(&metadata)),
+    };
+
+    let Some(stamp) = stamp else {
+        return sorted_image_names(names, &read_magic_bytes(backend, path, files));
+    };
+
+    if let Some(cached) = IMAGE_FILES.lock().unwrap().get_mut(path) {
+        if cached.stamp == stamp {
+            cached.last_used = Instant::now();
+            return cached.image_files.clone();
+        }
+    }
+
+    let image_files = sorted_image_names(names, &read_magic_bytes(backend, path, files));
+    let mut cache = IMAGE_FILES.lock().unwrap();
 
-    for i in 0..archive.len() {
-        let zip_file = archive.by_index(i).map_err(ReadArchiveError::Zip)?;
-        let name = zip_file.name().to_string();
-        files.push(ArchiveFile { name });
+    if cache.len() >= MAX_CACHED_IMAGE_FILES && !cache.contains_key(path) {
+        let least_recently_used = cache
+            .iter()
+            .min_by_key(|(_, cached)| cached.last_used)
+            .map(|(path, _)| path.clone());
+
+        if let Some(least_recently_used) = least_recently_used {
+            cache.remove(&least_recently_used);
+        }
+    }
+
+    cache.insert(
+        path.to_string(),
+        CachedImageFiles {
+            stamp,
+            image_files: image_files.clone(),
+            last_used: Instant::now(),
+        },
+    );
+    image_files
+}
+
+fn read_comicinfo_entry(
+    backend: &dyn ArchiveBackend,
+    path: &str,
+    files: &[ArchiveFile],
+) -> Result<Option<String>, ReadArchiveError> {
+    if !files.iter().any(|f| f.name == COMIC_INFO_FILE) {
+        return Ok(None);
     }
 
-    if let Ok(mut comic_info_file) = archive.by_name("ComicInfo.xml") {
-        let mut xml_content = String::new();
-        comic_info_file
-            .read_to_string(&mut xml_content)
-            .map_err(ReadArchiveError::Io)?;
+    let data = backend.read_file(path, COMIC_INFO_FILE)?;
+    String::from_utf8(data)
+        .map(Some)
+        .map_err(|e| ReadArchiveError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+}
+
+pub fn read_archive(path: &str) -> Result<Archive, ReadArchiveError> {
+    let backend = open_backend(path)?;
+    let files = backend.list_files(path)?;
+
+    let mut comic_info = None;
+
+    if let Some(xml_content) = read_comicinfo_entry(backend, path, &files)? {
         match ComicInfo::parse(&xml_content) {
             Ok(parsed_comic_info) => {
                 comic_info = Some(parsed_comic_info);
@@ -37,53 +134,90 @@ pub fn read_archive(path: &str) -> Result<Archive, ReadArchiveError> {
         }
     }
 
-    Ok(Archive { files, comic_info })
+    let image_files = image_files(backend, path, &files);
+
+    Ok(Archive {
+        files,
+        image_files,
+        comic_info,
+    })
+}
+
+/// Like [`read_archive`], but ComicInfo.xml values that do not match their
+/// schema type are replaced by a fallback and reported instead of failing.
+pub fn read_archive_lenient(
+    path: &str,
+) -> Result<(Archive, Vec<ParseDiagnostic>), ReadArchiveError> {
+    let backend = open_backend(path)?;
+    let files = backend.list_files(path)?;
+
+    let image_files = image_files(backend, path, &files);
+
+    let Some(xml_content) = read_comicinfo_entry(backend, path, &files)? else {
+        return Ok((
+            Archive {
+                files,
+                image_files,
+                comic_info: None,
+            },
+            Vec::new(),
+        ));
+    };
+
+    let (comic_info, diagnostics) = ComicInfo::parse_lenient(&xml_content)
+        .map_err(ReadArchiveError::FailedToParseComicInfoXml)?;
+
+    Ok((
+        Archive {
+            files,
+            image_files,
+            comic_info: Some(comic_info),
+        },
+        diagnostics,
+    ))
+}
+
+/// Returns the raw ComicInfo.xml contents, or `None` when the archive has none.
+pub fn read_comicinfo_xml(path: &str) -> Result<Option<String>, ReadArchiveError> {
+    let backend = open_backend(path)?;
+    let files = backend.list_files(path)?;
+
+    read_comicinfo_entry(backend, path, &files)
+}
+
+/// Returns the sorted names of the image entries in the archive.
+pub fn list_image_files(path: &str) -> Result<Vec<String>, ReadArchiveError> {
+    let backend = open_backend(path)?;
+
+    let files = backend.list_files(path)?;
+
+    Ok(image_files(backend, path, &files))
 }
 
 pub fn get_file_data(path: &str, file_name: &str) -> Result<Vec<u8>, ReadArchiveError> {
-    let mut archive = open_zip_archive(path)?;
-
-    let mut zip_file = archive.by_name(file_name).map_err(ReadArchiveError::Zip)?;
-    let mut data = Vec::new();
-    zip_file
-        .read_to_end(&mut data)
-        .map_err(ReadArchiveError::Io)?;
-    Ok(data)
+    open_backend(path)?.read_file(path, file_name)
 }
 
+/// Returns the recorded CRC32 of each entry, or an empty map when the format
+/// does not record them or the archive cannot be read.
+pub fn entry_checksums(path: &str) -> HashMap<String, u32> {
+    open_backend(path)
+        .and_then(|backend| backend.entry_checksums(path))
+        .unwrap_or_default()
+}
+
+/// Reads `file_names` in order as far as the format allows, until
+/// `is_cancelled` returns true.
 pub fn stream_file_data_from_archive(
     path: &str,
     file_names: Vec<String>,
     on_data: impl Fn(String, Vec<u8>) + Send + Sync + 'static,
     on_error: impl Fn(String, String) + Send + Sync + 'static,
+    is_cancelled: impl Fn() -> bool + Send + Sync + 'static,
 ) -> Result<(), ReadArchiveError> {
-    let _ = open_zip_archive(path)?;
-
-    let on_data = Arc::new(on_data);
-    let on_error = Arc::new(on_error);
-    let path = Arc::new(path.to_string());
-
-    file_names
-        .par_iter()
-        .for_each(|file_name| match open_zip_archive(&path) {
-            Ok(mut archive) => match archive.by_name(file_name) {
-                Ok(mut zip_file) => {
-                    let mut data = Vec::new();
-                    if let Err(e) = zip_file.read_to_end(&mut data) {
-                        on_error(file_name.clone(), e.to_string());
-                        return;
-                    }
-
-                    on_data(file_name.clone(), data);
-                }
-                Err(e) => {
-                    on_error(file_name.clone(), e.to_string());
-                }
-            },
-            Err(e) => {
-                on_error(file_name.clone(), e.to_string());
-            }
-        });
+    let backend = open_backend(path)?;
+
+    backend.read_files_cancellable(path, &file_names, &on_data, &on_error, &is_cancelled);
 
     Ok(())
 }
@@ -153,6 +287,7 @@ mod tests {
             vec!["file1.txt".to_string(), "file2.txt".to_string()],
             on_data,
             on_error,
+            || false,
         );
 
         assert!(
@@ -200,6 +335,7 @@ mod tests {
             vec!["nonexistent.txt".to_string()],
             on_data,
             on_error,
+            || false,
         );
 
         assert!(result.is_ok());
@@ -225,7 +361,8 @@ mod tests {
             error_calls_clone.lock().unwrap().push((file_name, error));
         };
 
-        let result = stream_file_data_from_archive(archive.path(), vec![], on_data, on_error);
+        let result =
+            stream_file_data_from_archive(archive.path(), vec![], on_data, on_error, || false);
 
         assert!(result.is_ok());
         assert_eq!(data_calls.lock().unwrap().len(), 0);
@@ -242,8 +379,33 @@ mod tests {
             vec!["file.txt".to_string()],
             on_data,
             on_error,
+            || false,
         );
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_stream_file_data_stops_when_cancelled() {
+        let archive =
+            TestArchive::new(vec![("file1.txt", b"content1"), ("file2.txt", b"content2")]);
+
+        let data_calls = Arc::new(Mutex::new(Vec::new()));
+        let data_calls_clone = data_calls.clone();
+
+        let on_data = move |file_name: String, _data: Vec<u8>| {
+            data_calls_clone.lock().unwrap().push(file_name);
+        };
+
+        let result = stream_file_data_from_archive(
+            archive.path(),
+            vec!["file1.txt".to_string(), "file2.txt".to_string()],
+            on_data,
+            |_, _| {},
+            || true,
+        );
+
+        assert!(result.is_ok());
+        assert!(data_calls.lock().unwrap().is_empty());
+    }
 }
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+
+/// Cancellation flags of the streams that are running, by stream ID.
+static ACTIVE_STREAMS: Lazy<Mutex<HashMap<String, Arc<AtomicBool>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A running stream that can be cancelled through [`cancel_stream`] while it
+/// is alive. Dropping it unregisters the stream.
+pub struct StreamHandle {
+    id: Option<String>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl StreamHandle {
+    /// Registers a stream. Streams without an ID cannot be cancelled. Starting
+    /// a stream with the ID of a running one cancels the running one.
+    pub fn register(id: Option<String>) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        if let Some(id) = &id {
+            let previous = ACTIVE_STREAMS
+                .lock()
+                .unwrap()
+                .insert(id.clone(), Arc::clone(&cancelled));
+
+            if let Some(previous) = previous {
+                previous.store(true, Ordering::SeqCst);
+            }
+        }
+
+        Self { id, cancelled }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// A check that can be handed to other threads.
+    pub fn cancellation(&self) -> impl Fn() -> bool + Send + Sync + 'static {
+        let cancelled = Arc::clone(&self.cancelled);
+        move || cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for StreamHandle {
+    fn drop(&mut self) {
+        let Some(id) = &self.id else {
+            return;
+        };
+
+        let mut streams = ACTIVE_STREAMS.lock().unwrap();
+
+        if streams
+            .get(id)
+            .is_some_and(|flag| Arc::ptr_eq(flag, &self.cancelled))
+        {
+            streams.remove(id);
+        }
+    }
+}
+
+/// Asks the stream with `id` to stop. Returns false when no such stream is
+/// running.
+pub fn cancel_stream(id: &str) -> bool {
+    match ACTIVE_STREAMS.lock().unwrap().get(id) {
+        Some(cancelled) => {
+            cancelled.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Moves the entries listed in `priority` to the front, in the order given
+/// there, followed by the remaining entries in their original order.
+pub fn prioritise(file_names: Vec<String>, priority: &[String]) -> Vec<String> {
+    let (mut first, rest): (Vec<String>, Vec<String>) = file_names
+        .into_iter()
+        .partition(|name| priority.contains(name));
+
+    first.sort_by_key(|name| priority.iter().position(|p| p == name));
+    first.extend(rest);
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_cancel_stream() {
+        let stream = StreamHandle::register(Some("test-cancel".to_string()));
+        let is_cancelled = stream.cancellation();
+
+        assert!(!stream.is_cancelled());
+        assert!(cancel_stream("test-cancel"));
+        assert!(stream.is_cancelled());
+        assert!(is_cancelled());
+
+        drop(stream);
+        assert!(!cancel_stream("test-cancel"));
+        assert!(!cancel_stream("unknown"));
+    }
+
+    #[test]
+    fn test_restarting_a_stream_cancels_the_previous_one() {
+        let first = StreamHandle::register(Some("test-restart".to_string()));
+        let second = StreamHandle::register(Some("test-restart".to_string()));
+
+        assert!(first.is_cancelled());
+        assert!(!second.is_cancelled());
+
+        drop(first);
+        assert!(cancel_stream("test-restart"));
+        assert!(second.is_cancelled());
+    }
+
+    #[test]
+    fn test_prioritise() {
+        let ordered = prioritise(
+            names(&["1.jpg", "2.jpg", "3.jpg", "4.jpg", "5.jpg"]),
+            &names(&["4.jpg", "missing.jpg", "3.jpg"]),
+        );
+
+        assert_eq!(
+            ordered,
+            names(&["4.jpg", "3.jpg", "1.jpg", "2.jpg", "5.jpg"])
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::image_format::{DisplayableImage, PageImageFormat, ThumbnailOptions};
+
+/// Total size cached thumbnails may take before the least recently used ones
+/// are removed.
+pub const DEFAULT_MAX_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+
+static THUMBNAIL_CACHE: OnceLock<ThumbnailCache> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThumbnailCacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Identifies a thumbnail by the archive it was made for, the CRC32 of the
+/// entry it was made from and the requested thumbnail size. Changed entries
+/// get a new key, so a stale thumbnail is never returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThumbnailKey {
+    archive_dir: String,
+    file_name: String,
+}
+
+impl ThumbnailKey {
+    pub fn new(archive_path: &str, crc32: u32, options: &ThumbnailOptions) -> Self {
+        Self {
+            archive_dir: archive_dir_name(archive_path),
+            file_name: format!(
+                "{:08x}-{}-{}",
+                crc32, options.max_dimension, options.quality
+            ),
+        }
+    }
+}
+
+/// Name of the directory that app data about an archive is kept in.
+pub(crate) fn archive_dir_name(archive_path: &str) -> String {
+    Sha256::digest(archive_path.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    used: SystemTime,
+}
+
+fn cached_files(dir: &Path) -> Vec<CachedFile> {
+    let Ok(archive_dirs) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    archive_dirs
+        .flatten()
+        .filter_map(|archive_dir| fs::read_dir(archive_dir.path()).ok())
+        .flat_map(|entries| entries.flatten())
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+
+            Some(CachedFile {
+                path: entry.path(),
+                size: metadata.len(),
+                used: metadata.modified().ok()?,
+            })
+        })
+        .collect()
+}
+
+fn stats_of(files: &[CachedFile]) -> ThumbnailCacheStats {
+    ThumbnailCacheStats {
+        entries: files.len(),
+        bytes: files.iter().map(|f| f.size).sum(),
+    }
+}
+
+/// Thumbnails stored on disk, one directory per archive.
+///
+/// The modification time of a thumbnail is bumped every time it is read, so
+/// it doubles as the last use when the cache outgrows `max_bytes`.
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    bytes: Mutex<u64>,
+}
+
+impl ThumbnailCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        let bytes = stats_of(&cached_files(&dir)).bytes;
+
+        Self {
+            dir,
+            max_bytes,
+            bytes: Mutex::new(bytes),
+        }
+    }
+
+    fn path_of(&self, key: &ThumbnailKey) -> PathBuf {
+        self.dir.join(&key.archive_dir).join(&key.file_name)
+    }
+
+    pub fn get(&self, key: &ThumbnailKey) -> Option<DisplayableImage> {
+        let path = self.path_of(key);
+        let data = fs::read(&path).ok()?;
+        let format = PageImageFormat::detect(&data)?;
+
+        if let Err(e) = fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+        {
+            debug!("Failed to mark thumbnail {:?} as used: {}", path, e);
+        }
+
+        Some(DisplayableImage {
+            data,
+            mime_type: format.mime_type(),
+        })
+    }
+
+    pub fn put(&self, key: &ThumbnailKey, image: &DisplayableImage) -> io::Result<()> {
+        let path = self.path_of(key);
+        let dir = self.dir.join(&key.archive_dir);
+        fs::create_dir_all(&dir)?;
+
+        let mut temp_file = tempfile::NamedTempFile::new_in(&dir)?;
+        temp_file.write_all(&image.data)?;
+        temp_file.persist(&path).map_err(|e| e.error)?;
+
+        let mut bytes = self.bytes.lock().unwrap();
+        *bytes += image.data.len() as u64;
+
+        if *bytes > self.max_bytes {
+            *bytes = self.evict();
+        }
+
+        Ok(())
+    }
+
+    /// Returns the cached thumbnail for `key`, or makes and caches it.
+    pub fn get_or_insert_with(
+        &self,
+        key: &ThumbnailKey,
+        make: impl FnOnce() -> Result<DisplayableImage, String>,
+    ) -> Result<DisplayableImage, String> {
+        if let Some(image) = self.get(key) {
+            return Ok(image);
+        }
+
+        let image = make()?;
+
+        if let Err(e) = self.put(key, &image) {
+            debug!("Failed to cache thumbnail {:?}: {}", self.path_of(key), e);
+        }
+
+        Ok(image)
+    }
+
+    /// Removes the least recently used thumbnails until the cache fits within
+    /// `max_bytes`, returning the remaining size.
+    fn evict(&self) -> u64 {
+        let mut files = cached_files(&self.dir);
+        files.sort_by_key(|f| f.used);
+
+        let mut bytes = stats_of(&files).bytes;
+
+        for file in files {
+            if bytes <= self.max_bytes {
+                break;
+            }
+
+            if fs::remove_file(&file.path).is_ok() {
+                bytes -= file.size;
+            }
+        }
+
+        bytes
+    }
+
+    /// Removes every thumbnail made for the archive at `archive_path`.
+    pub fn invalidate_archive(&self, archive_path: &str) {
+        let dir = self.dir.join(archive_dir_name(archive_path));
+
+        if !dir.exists() {
+            return;
+        }
+
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            debug!("Failed to invalidate thumbnails of {}: {}", archive_path, e);
+        }
+
+        *self.bytes.lock().unwrap() = stats_of(&cached_files(&self.dir)).bytes;
+    }
+
+    /// Removes every thumbnail, returning what was removed.
+    pub fn clear(&self) -> io::Result<ThumbnailCacheStats> {
+        let mut bytes = self.bytes.lock().unwrap();
+        let stats = stats_of(&cached_files(&self.dir));
+
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+
+        *bytes = 0;
+
+        Ok(stats)
+    }
+}
+
+/// Sets up the cache shared by all commands. Called once at startup.
+pub fn init_thumbnail_cache(dir: PathBuf) {
+    if THUMBNAIL_CACHE
+        .set(ThumbnailCache::new(dir, DEFAULT_MAX_CACHE_BYTES))
+        .is_err()
+    {
+        debug!("Thumbnail cache was already initialised");
+    }
+}
+
+pub fn thumbnail_cache() -> Option<&'static ThumbnailCache> {
+    THUMBNAIL_CACHE.get()
+}
+
+/// Drops the cached thumbnails of an archive that changed on disk.
+pub fn invalidate_thumbnails(archive_path: &str) {
+    if let Some(cache) = thumbnail_cache() {
+        cache.invalidate_archive(archive_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(max_dimension: u32) -> ThumbnailOptions {
+        ThumbnailOptions {
+            max_dimension,
+            quality: 80,
+        }
+    }
+
+    fn jpeg(size: usize) -> DisplayableImage {
+        let mut data = b"\xFF\xD8\xFF".to_vec();
+        data.resize(size, 0);
+
+        DisplayableImage {
+            data,
+            mime_type: "image/jpeg",
+        }
+    }
+
+    #[test]
+    fn test_keys_depend_on_archive_checksum_and_size() {
+        let key = ThumbnailKey::new("/a.cbz", 1, &options(200));
+
+        assert_eq!(key, ThumbnailKey::new("/a.cbz", 1, &options(200)));
+        assert_ne!(key, ThumbnailKey::new("/b.cbz", 1, &options(200)));
+        assert_ne!(key, ThumbnailKey::new("/a.cbz", 2, &options(200)));
+        assert_ne!(key, ThumbnailKey::new("/a.cbz", 1, &options(400)));
+    }
+
+    #[test]
+    fn test_get_or_insert_with_reuses_cached_thumbnails() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ThumbnailCache::new(dir.path().join("thumbnails"), 1024);
+        let key = ThumbnailKey::new("/a.cbz", 1, &options(200));
+
+        assert_eq!(cache.get(&key), None);
+
+        let made = cache.get_or_insert_with(&key, || Ok(jpeg(10))).unwrap();
+        let cached = cache
+            .get_or_insert_with(&key, || panic!("thumbnail should be cached"))
+            .unwrap();
+        assert_eq!(made, cached);
+
+        let reopened = ThumbnailCache::new(dir.path().join("thumbnails"), 1024);
+        assert_eq!(reopened.get(&key), Some(made));
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ThumbnailCache::new(dir.path().to_path_buf(), 250);
+        let keys: Vec<ThumbnailKey> = (0..3)
+            .map(|crc32| ThumbnailKey::new("/a.cbz", crc32, &options(200)))
+            .collect();
+
+        cache.put(&keys[0], &jpeg(100)).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.put(&keys[1], &jpeg(100)).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert!(cache.get(&keys[0]).is_some());
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.put(&keys[2], &jpeg(100)).unwrap();
+
+        assert!(cache.get(&keys[0]).is_some());
+        assert!(cache.get(&keys[1]).is_none());
+        assert!(cache.get(&keys[2]).is_some());
+    }
+
+    #[test]
+    fn test_invalidate_and_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ThumbnailCache::new(dir.path().join("thumbnails"), 1024);
+        let a = ThumbnailKey::new("/a.cbz", 1, &options(200));
+        let b = ThumbnailKey::new("/b.cbz", 1, &options(200));
+
+        cache.put(&a, &jpeg(10)).unwrap();
+        cache.put(&b, &jpeg(20)).unwrap();
+
+        cache.invalidate_archive("/a.cbz");
+        assert!(cache.get(&a).is_none());
+        assert!(cache.get(&b).is_some());
+
+        assert_eq!(
+            cache.clear().unwrap(),
+            ThumbnailCacheStats {
+                entries: 1,
+                bytes: 20
+            }
+        );
+        assert!(cache.get(&b).is_none());
+    }
+}
@@ -1,7 +1,13 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
-use crate::comicinfo::info::ComicInfoParseError;
+use crate::comicinfo::info::{ComicInfoError, ComicInfoParseError};
+use crate::comicinfo::lenient::ParseDiagnostic;
+use crate::comicinfo::validation::{ValidationIssue, ValidationSeverity};
+
+use super::fingerprint::ArchiveFingerprint;
+use super::image_format::PageImageFormat;
+use super::order::PageOrderWarning;
 
 #[derive(Serialize, Deserialize)]
 pub struct ArchiveFile {
@@ -11,6 +17,8 @@ pub struct ArchiveFile {
 #[derive(Serialize, Deserialize)]
 pub struct Archive {
     pub files: Vec<ArchiveFile>,
+    /// The entries holding images, in page order.
+    pub image_files: Vec<String>,
     pub comic_info: Option<crate::comicinfo::ComicInfo>,
 }
 
@@ -19,6 +27,41 @@ pub struct LoadCbzResponse {
     pub image_files: Vec<String>,
     pub comic_info: Option<crate::comicinfo::ComicInfo>,
     pub error: Option<ErrorResponse>,
+    #[serde(default)]
+    pub diagnostics: Vec<ParseDiagnostic>,
+    #[serde(default)]
+    pub page_order_warning: Option<PageOrderWarning>,
+    /// State of the archive on disk when it was loaded. Write commands take
+    /// it back to detect changes made by other programs in the meantime.
+    #[serde(default)]
+    pub fingerprint: Option<ArchiveFingerprint>,
+}
+
+/// Container formats that comic pages can be read from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    #[serde(rename = "cbz")]
+    Zip,
+    #[serde(rename = "cbr")]
+    Rar,
+    #[serde(rename = "cb7")]
+    SevenZip,
+    #[serde(rename = "cbt")]
+    Tar,
+    #[serde(rename = "directory")]
+    Directory,
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveFormat::Zip => write!(f, "CBZ"),
+            ArchiveFormat::Rar => write!(f, "CBR"),
+            ArchiveFormat::SevenZip => write!(f, "CB7"),
+            ArchiveFormat::Tar => write!(f, "CBT"),
+            ArchiveFormat::Directory => write!(f, "folder"),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -29,14 +72,59 @@ pub enum ErrorResponseType {
     FailedToParseComicInfoXml,
     #[serde(rename = "ComicInfoXmlInvalid")]
     ComicInfoXmlInvalid,
+    #[serde(rename = "ReadOnlyArchiveFormat")]
+    ReadOnlyArchiveFormat,
+    #[serde(rename = "Conflict")]
+    Conflict,
+    #[serde(rename = "NotFound")]
+    NotFound,
+    #[serde(rename = "PermissionDenied")]
+    PermissionDenied,
+    #[serde(rename = "UnsupportedFormat")]
+    UnsupportedFormat,
+    #[serde(rename = "ValidationFailed")]
+    ValidationFailed,
+    #[serde(rename = "WatcherFailure")]
+    WatcherFailure,
     #[serde(rename = "Other")]
     Other,
 }
 
+/// Machine-readable context for an [`ErrorResponse`], so the frontend can
+/// react to an error without parsing its message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ErrorDetails {
+    /// The file or folder the error is about.
+    Path { path: String },
+    /// The entry inside the archive the error is about.
+    Entry { file_name: String },
+    /// The underlying IO error, as the name of its `std::io::ErrorKind`.
+    Io { error_kind: String },
+    /// The archive on disk no longer matches the fingerprint it was loaded
+    /// with.
+    Conflict {
+        expected: ArchiveFingerprint,
+        actual: ArchiveFingerprint,
+    },
+    /// The archive format the operation does not support, and the format to
+    /// convert to first when there is one.
+    Format {
+        format: ArchiveFormat,
+        convert_to: Option<ArchiveFormat>,
+    },
+    /// The problems that made validation fail.
+    Validation { issues: Vec<ValidationIssue> },
+    /// The ComicInfo.xml history version the error is about.
+    HistoryVersion { version: u64 },
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ErrorResponse {
     pub error_type: ErrorResponseType,
     pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<ErrorDetails>,
 }
 
 impl ErrorResponse {
@@ -44,14 +132,29 @@ impl ErrorResponse {
         ErrorResponse {
             error_type,
             message: message.into(),
+            details: None,
         }
     }
+
+    pub fn with_details(mut self, details: ErrorDetails) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl From<String> for ErrorResponse {
+    fn from(message: String) -> Self {
+        ErrorResponse::new(ErrorResponseType::Other, message)
+    }
 }
 
 #[derive(Debug)]
 pub enum ReadArchiveError {
     Io(std::io::Error),
     Zip(zip::result::ZipError),
+    Rar(String),
+    SevenZip(String),
+    EntryNotFound(String),
     FailedToParseComicInfoXml(ComicInfoParseError),
 }
 
@@ -60,6 +163,11 @@ impl fmt::Display for ReadArchiveError {
         match self {
             ReadArchiveError::Io(err) => write!(f, "IO error: {}.", err),
             ReadArchiveError::Zip(err) => write!(f, "Zip error: {}", err),
+            ReadArchiveError::Rar(err) => write!(f, "Rar error: {}", err),
+            ReadArchiveError::SevenZip(err) => write!(f, "7z error: {}", err),
+            ReadArchiveError::EntryNotFound(name) => {
+                write!(f, "File not found in archive: {}", name)
+            }
             ReadArchiveError::FailedToParseComicInfoXml(err) => {
                 write!(f, "Failed to parse ComicInfo XML: {}", err)
             }
@@ -75,6 +183,32 @@ pub trait ToErrorResponse {
     fn to_error_response(&self) -> ErrorResponse;
 }
 
+/// Maps the IO error kinds the frontend can act on to their own error types;
+/// anything else falls back to `fallback`.
+fn io_error_type(err: &std::io::Error, fallback: ErrorResponseType) -> ErrorResponseType {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => ErrorResponseType::NotFound,
+        std::io::ErrorKind::PermissionDenied => ErrorResponseType::PermissionDenied,
+        _ => fallback,
+    }
+}
+
+fn io_details(err: &std::io::Error) -> ErrorDetails {
+    ErrorDetails::Io {
+        error_kind: format!("{:?}", err.kind()),
+    }
+}
+
+impl ToErrorResponse for std::io::Error {
+    fn to_error_response(&self) -> ErrorResponse {
+        ErrorResponse::new(
+            io_error_type(self, ErrorResponseType::Other),
+            format!("IO error: {}", self),
+        )
+        .with_details(io_details(self))
+    }
+}
+
 impl ToErrorResponse for ReadArchiveError {
     fn to_error_response(&self) -> ErrorResponse {
         match self {
@@ -86,30 +220,206 @@ impl ToErrorResponse for ReadArchiveError {
                     _ => format!("IO error: {}", err),
                 };
                 ErrorResponse::new(ErrorResponseType::FailedToLoadArchive, msg)
+                    .with_details(io_details(err))
             }
             ReadArchiveError::Zip(err) => ErrorResponse::new(
                 ErrorResponseType::FailedToLoadArchive,
                 format!("Zip error: {}", err),
             ),
-            ReadArchiveError::FailedToParseComicInfoXml(err) => ErrorResponse::new(
-                ErrorResponseType::FailedToParseComicInfoXml,
-                format!("Failed to parse ComicInfo XML: {}", err),
+            ReadArchiveError::Rar(err) => ErrorResponse::new(
+                ErrorResponseType::FailedToLoadArchive,
+                format!("Rar error: {}", err),
+            ),
+            ReadArchiveError::SevenZip(err) => ErrorResponse::new(
+                ErrorResponseType::FailedToLoadArchive,
+                format!("7z error: {}", err),
+            ),
+            ReadArchiveError::EntryNotFound(name) => ErrorResponse::new(
+                ErrorResponseType::NotFound,
+                format!("File not found in archive: {}", name),
+            )
+            .with_details(ErrorDetails::Entry {
+                file_name: name.clone(),
+            }),
+            ReadArchiveError::FailedToParseComicInfoXml(err) => err.to_error_response(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WriteArchiveError {
+    Io(std::io::Error),
+    Zip(zip::result::ZipError),
+    Read(ReadArchiveError),
+    /// The container cannot be rewritten in place; it has to be converted first.
+    ReadOnlyFormat {
+        format: ArchiveFormat,
+        convert_to: ArchiveFormat,
+    },
+    ComicInfo(ComicInfoError),
+    AlreadyInFormat(ArchiveFormat),
+    OutputExists(String),
+    EntryUnreadable {
+        file_name: String,
+        message: String,
+    },
+    /// Entries can only be added, removed or renamed inside CBZ archives.
+    RequiresZip(ArchiveFormat),
+    InvalidPageOrder(String),
+    NotAnImage(String),
+    /// The archive changed on disk since the caller loaded it.
+    Conflict {
+        expected: ArchiveFingerprint,
+        actual: ArchiveFingerprint,
+    },
+}
+
+impl fmt::Display for WriteArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteArchiveError::Io(err) => write!(f, "IO error: {}", err),
+            WriteArchiveError::Zip(err) => write!(f, "Zip error: {}", err),
+            WriteArchiveError::Read(err) => write!(f, "{}", err),
+            WriteArchiveError::ReadOnlyFormat { format, convert_to } => write!(
+                f,
+                "{} archives are read-only. Convert the archive to {} to edit its ComicInfo.xml.",
+                format, convert_to
+            ),
+            WriteArchiveError::ComicInfo(err) => write!(f, "{}", err),
+            WriteArchiveError::AlreadyInFormat(format) => {
+                write!(f, "The archive is already a {} archive.", format)
+            }
+            WriteArchiveError::OutputExists(path) => {
+                write!(f, "Refusing to overwrite existing file: {}", path)
+            }
+            WriteArchiveError::EntryUnreadable { file_name, message } => {
+                write!(f, "Failed to read {}: {}", file_name, message)
+            }
+            WriteArchiveError::RequiresZip(format) => write!(
+                f,
+                "Only CBZ archives can be restructured; this is a {} archive.",
+                format
+            ),
+            WriteArchiveError::InvalidPageOrder(message) => {
+                write!(f, "Invalid page order: {}", message)
+            }
+            WriteArchiveError::NotAnImage(path) => {
+                write!(f, "{} is not a supported image file", path)
+            }
+            WriteArchiveError::Conflict { .. } => write!(
+                f,
+                "The archive was changed by another program since it was loaded. Reload it before saving again."
             ),
         }
     }
 }
 
+impl std::error::Error for WriteArchiveError {}
+
+impl From<ReadArchiveError> for WriteArchiveError {
+    fn from(err: ReadArchiveError) -> Self {
+        WriteArchiveError::Read(err)
+    }
+}
+
+impl ToErrorResponse for WriteArchiveError {
+    fn to_error_response(&self) -> ErrorResponse {
+        let message = self.to_string();
+
+        match self {
+            WriteArchiveError::Io(err) => {
+                ErrorResponse::new(io_error_type(err, ErrorResponseType::Other), message)
+                    .with_details(io_details(err))
+            }
+            WriteArchiveError::Zip(_) => ErrorResponse::new(ErrorResponseType::Other, message),
+            WriteArchiveError::Read(err) => err.to_error_response(),
+            WriteArchiveError::ReadOnlyFormat { format, convert_to } => {
+                ErrorResponse::new(ErrorResponseType::ReadOnlyArchiveFormat, message).with_details(
+                    ErrorDetails::Format {
+                        format: *format,
+                        convert_to: Some(*convert_to),
+                    },
+                )
+            }
+            WriteArchiveError::ComicInfo(err) => err.to_error_response(),
+            WriteArchiveError::AlreadyInFormat(format) | WriteArchiveError::RequiresZip(format) => {
+                ErrorResponse::new(ErrorResponseType::UnsupportedFormat, message).with_details(
+                    ErrorDetails::Format {
+                        format: *format,
+                        convert_to: None,
+                    },
+                )
+            }
+            WriteArchiveError::OutputExists(path) => {
+                ErrorResponse::new(ErrorResponseType::Conflict, message)
+                    .with_details(ErrorDetails::Path { path: path.clone() })
+            }
+            WriteArchiveError::EntryUnreadable { file_name, .. } => {
+                ErrorResponse::new(ErrorResponseType::Other, message).with_details(
+                    ErrorDetails::Entry {
+                        file_name: file_name.clone(),
+                    },
+                )
+            }
+            WriteArchiveError::InvalidPageOrder(reason) => {
+                ErrorResponse::new(ErrorResponseType::ValidationFailed, message).with_details(
+                    ErrorDetails::Validation {
+                        issues: vec![ValidationIssue {
+                            severity: ValidationSeverity::Error,
+                            field: "order".to_string(),
+                            message: reason.clone(),
+                        }],
+                    },
+                )
+            }
+            WriteArchiveError::NotAnImage(path) => {
+                ErrorResponse::new(ErrorResponseType::UnsupportedFormat, message)
+                    .with_details(ErrorDetails::Path { path: path.clone() })
+            }
+            WriteArchiveError::Conflict { expected, actual } => {
+                ErrorResponse::new(ErrorResponseType::Conflict, message).with_details(
+                    ErrorDetails::Conflict {
+                        expected: *expected,
+                        actual: *actual,
+                    },
+                )
+            }
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FileDataResponse {
     pub data: Option<Vec<u8>>,
     pub error: Option<ErrorResponse>,
 }
 
-const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "bmp", "webp"];
+/// Files that operating systems leave behind, compared case-insensitively.
+const JUNK_FILE_NAMES: &[&str] = &[".ds_store", "thumbs.db", "desktop.ini"];
+
+/// Returns whether an entry is operating system metadata rather than content:
+/// anything under `__MACOSX/`, AppleDouble `._` resource forks, and files such
+/// as `.DS_Store` and `Thumbs.db`.
+pub fn is_junk_entry(name: &str) -> bool {
+    let mut components = name.split(['/', '\\']).filter(|c| !c.is_empty()).peekable();
+
+    while let Some(component) = components.next() {
+        if component == "__MACOSX" {
+            return true;
+        }
+
+        if components.peek().is_none() {
+            let lower = component.to_lowercase();
+            return component.starts_with("._") || JUNK_FILE_NAMES.contains(&lower.as_str());
+        }
+    }
+
+    false
+}
 
+/// Returns whether an entry is a page, judged by the extensions of the
+/// supported [`PageImageFormat`]s. Only used for entries whose first bytes
+/// cannot be read; see [`super::order::sorted_image_names`].
 pub fn is_image_file(name: &str) -> bool {
-    let name_lower = name.to_lowercase();
-    IMAGE_EXTENSIONS
-        .iter()
-        .any(|ext| name_lower.ends_with(&format!(".{}", ext)))
+    PageImageFormat::from_extension(name).is_some() && !is_junk_entry(name)
 }
@@ -1,4 +1,6 @@
+use crate::archive::backend::invalidate_zip_archive;
 use crate::archive::event::{ArchiveEventEmitter, ArchiveEventType};
+use crate::archive::thumbnail_cache::invalidate_thumbnails;
 
 use log::debug;
 use notify::{RecommendedWatcher, RecursiveMode, Result as NotifyResult, Watcher, event::Event};
@@ -136,6 +138,14 @@ impl<E: ArchiveEventEmitter + Send + Sync + Clone + 'static> ArchiveWatcher<E> {
         Ok((stop_tx, handle))
     }
 
+    /// Drops everything cached for the archive before telling the frontend
+    /// to reload it.
+    fn report_reload(event_emitter: &E, watch_path: &str) {
+        invalidate_zip_archive(watch_path);
+        invalidate_thumbnails(watch_path);
+        event_emitter.send_event(ArchiveEventType::Reload, watch_path);
+    }
+
     fn run_file_watcher(
         watch_path: &str,
         event_emitter: &E,
@@ -143,6 +153,12 @@ impl<E: ArchiveEventEmitter + Send + Sync + Clone + 'static> ArchiveWatcher<E> {
         suppress_flag: &Arc<AtomicBool>,
     ) -> WatcherResult {
         debug!("Creating file watcher for {}", watch_path);
+        let is_directory = std::path::Path::new(watch_path).is_dir();
+        let recursive_mode = if is_directory {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
         let (tx, rx) = mpsc::channel();
         let watcher: NotifyResult<RecommendedWatcher> =
             notify::recommended_watcher(move |res: NotifyResult<Event>| {
@@ -150,10 +166,7 @@ impl<E: ArchiveEventEmitter + Send + Sync + Clone + 'static> ArchiveWatcher<E> {
             });
         let mut watcher = match watcher {
             Ok(mut w) => {
-                if let Err(e) = w.watch(
-                    std::path::Path::new(watch_path),
-                    RecursiveMode::NonRecursive,
-                ) {
+                if let Err(e) = w.watch(std::path::Path::new(watch_path), recursive_mode) {
                     return WatcherResult::Error(format!("Failed to start watching: {}", e));
                 }
                 w
@@ -168,7 +181,12 @@ impl<E: ArchiveEventEmitter + Send + Sync + Clone + 'static> ArchiveWatcher<E> {
                 Ok(event_res) => match event_res {
                     Ok(event) => {
                         debug!("Watcher event for {}: {:?}", watch_path, event);
-                        match event.kind {
+                        let kind = if is_directory {
+                            Self::directory_event_kind(watch_path, &event)
+                        } else {
+                            event.kind
+                        };
+                        match kind {
                             notify::EventKind::Modify(_) => {
                                 debug!("File modified: {}, debouncing...", watch_path);
                                 // If a remove event is already pending, keep it (remove always wins)
@@ -180,7 +198,7 @@ impl<E: ArchiveEventEmitter + Send + Sync + Clone + 'static> ArchiveWatcher<E> {
                                     }
                                 }
                                 pending_event = Some(DebouncedEvent {
-                                    kind: event.kind,
+                                    kind,
                                     timestamp: Instant::now(),
                                 });
                             }
@@ -188,12 +206,12 @@ impl<E: ArchiveEventEmitter + Send + Sync + Clone + 'static> ArchiveWatcher<E> {
                                 debug!("File removed: {}, debouncing...", watch_path);
                                 // Remove event always replaces any previous event
                                 pending_event = Some(DebouncedEvent {
-                                    kind: event.kind,
+                                    kind,
                                     timestamp: Instant::now(),
                                 });
                             }
                             _ => {
-                                debug!("Other file event for {}: {:?}", watch_path, event.kind);
+                                debug!("Other file event for {}: {:?}", watch_path, kind);
                             }
                         }
                     }
@@ -227,11 +245,11 @@ impl<E: ArchiveEventEmitter + Send + Sync + Clone + 'static> ArchiveWatcher<E> {
                             }
                             match debounced_event.kind {
                                 notify::EventKind::Modify(_) => {
-                                    event_emitter.send_event(ArchiveEventType::Reload, watch_path);
+                                    Self::report_reload(event_emitter, watch_path);
                                 }
                                 notify::EventKind::Remove(_) => {
                                     let _ = watcher.unwatch(std::path::Path::new(watch_path));
-                                    event_emitter.send_event(ArchiveEventType::Reload, watch_path);
+                                    Self::report_reload(event_emitter, watch_path);
 
                                     return WatcherResult::FileRemoved;
                                 }
@@ -252,6 +270,24 @@ impl<E: ArchiveEventEmitter + Send + Sync + Clone + 'static> ArchiveWatcher<E> {
         }
     }
 
+    /// Maps an event inside a watched folder onto the kinds used for single
+    /// files: files being added or removed inside the folder count as a
+    /// modification, only removing the folder itself counts as a removal.
+    fn directory_event_kind(watch_path: &str, event: &Event) -> notify::EventKind {
+        let removes_folder = event
+            .paths
+            .iter()
+            .any(|p| p == std::path::Path::new(watch_path));
+
+        match event.kind {
+            notify::EventKind::Remove(_) if removes_folder => event.kind,
+            notify::EventKind::Create(_) | notify::EventKind::Remove(_) => {
+                notify::EventKind::Modify(notify::event::ModifyKind::Any)
+            }
+            kind => kind,
+        }
+    }
+
     /// Watch for creation of the file. Returns true if successful, false otherwise.
     pub fn watch_for_creation(&mut self) -> Result<bool, String> {
         if self.is_running() {
@@ -730,4 +766,32 @@ mod tests {
             let _ = fs::remove_file(test_file);
         }
     }
+
+    #[test]
+    fn test_watcher_reloads_on_nested_folder_changes() {
+        use std::fs;
+        use std::thread;
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let nested = dir.path().join("chapter1");
+        fs::create_dir(&nested).expect("Failed to create nested dir");
+        let watch_path = dir.path().to_str().unwrap().to_string();
+
+        let mock_emitter = MockEventEmitter::new();
+        let mut watcher: ArchiveWatcher<MockEventEmitter> =
+            ArchiveWatcher::new(watch_path.clone(), mock_emitter.clone());
+
+        assert!(watcher.start(), "Failed to start watcher");
+        thread::sleep(Duration::from_millis(200));
+
+        fs::write(nested.join("001.jpg"), b"page").expect("Failed to add page");
+        thread::sleep(Duration::from_millis(1200));
+
+        let events = mock_emitter.get_events();
+        assert_eq!(events, vec![(ArchiveEventType::Reload, watch_path)]);
+        assert!(watcher.is_running(), "Watcher should keep running");
+
+        let _ = watcher.stop();
+    }
 }
@@ -1,79 +1,23 @@
 use std::collections::HashMap;
-use std::fs;
-use std::io::{BufReader, BufWriter, Write};
-use zip::CompressionMethod;
-use zip::write::FileOptions;
 
 use crate::comicinfo::{ComicInfo, ComicPageInfo, ComicPageType, Pages};
 use log::debug;
 
+use super::backend::{ensure_writable, invalidate_zip_archive, open_backend};
 use super::manager::suppress_next_archive_event;
+use super::order::sorted_image_files;
 use super::reader::read_archive;
-use super::types::is_image_file;
-
-pub fn update_zip_with_comicinfo(path: &str, xml_content: &str) -> Result<(), String> {
-    let temp_path = format!("{}.tmp", path);
-
-    {
-        let original_file = fs::File::open(path).map_err(|e| e.to_string())?;
-        let mut original_archive =
-            zip::ZipArchive::new(BufReader::new(original_file)).map_err(|e| e.to_string())?;
-
-        let temp_file = fs::File::create(&temp_path).map_err(|e| e.to_string())?;
-        let mut new_archive = zip::ZipWriter::new(BufWriter::new(temp_file));
-
-        for i in 0..original_archive.len() {
-            let file = original_archive
-                .by_index_raw(i)
-                .map_err(|e| e.to_string())?;
-            if file.name() != "ComicInfo.xml" {
-                new_archive.raw_copy_file(file).map_err(|e| e.to_string())?;
-            }
-        }
-
-        let xml_options =
-            FileOptions::<()>::default().compression_method(CompressionMethod::Stored);
-
-        new_archive
-            .start_file("ComicInfo.xml", xml_options)
-            .map_err(|e| e.to_string())?;
-        new_archive
-            .write_all(xml_content.as_bytes())
-            .map_err(|e| e.to_string())?;
-
-        new_archive.finish().map_err(|e| e.to_string())?;
-    }
-
-    fs::rename(&temp_path, path).map_err(|e| e.to_string())?;
+use super::types::{ErrorResponse, ToErrorResponse, WriteArchiveError};
 
+pub fn update_zip_with_comicinfo(path: &str, xml_content: &str) -> Result<(), WriteArchiveError> {
+    open_backend(path)?.write_comic_info(path, Some(xml_content))?;
+    invalidate_zip_archive(path);
     Ok(())
 }
 
-pub fn delete_comicinfo_xml(path: &str) -> Result<(), String> {
-    let temp_path = format!("{}.tmp", path);
-
-    {
-        let original_file = fs::File::open(path).map_err(|e| e.to_string())?;
-        let mut original_archive =
-            zip::ZipArchive::new(BufReader::new(original_file)).map_err(|e| e.to_string())?;
-
-        let temp_file = fs::File::create(&temp_path).map_err(|e| e.to_string())?;
-        let mut new_archive = zip::ZipWriter::new(BufWriter::new(temp_file));
-
-        for i in 0..original_archive.len() {
-            let file = original_archive
-                .by_index_raw(i)
-                .map_err(|e| e.to_string())?;
-            if file.name() != "ComicInfo.xml" {
-                new_archive.raw_copy_file(file).map_err(|e| e.to_string())?;
-            }
-        }
-
-        new_archive.finish().map_err(|e| e.to_string())?;
-    }
-
-    fs::rename(&temp_path, path).map_err(|e| e.to_string())?;
-
+pub fn delete_comicinfo_xml(path: &str) -> Result<(), WriteArchiveError> {
+    open_backend(path)?.write_comic_info(path, None)?;
+    invalidate_zip_archive(path);
     Ok(())
 }
 
@@ -89,6 +33,32 @@ pub struct PageSettings {
     pub image: i32,
 }
 
+/// The settings of every page `pages` describes, keyed by file name, so that
+/// saving them back changes nothing.
+pub fn described_page_settings(
+    sorted_files: &[String],
+    pages: &[ComicPageInfo],
+) -> HashMap<String, PageSettings> {
+    pages
+        .iter()
+        .filter_map(|page| {
+            let file_name = usize::try_from(page.image)
+                .ok()
+                .and_then(|index| sorted_files.get(index))?;
+
+            Some((
+                file_name.clone(),
+                PageSettings {
+                    page_type: page.type_.clone().unwrap_or(ComicPageType::Other),
+                    double_page: page.double_page,
+                    bookmark: page.bookmark.clone(),
+                    image: page.image,
+                },
+            ))
+        })
+        .collect()
+}
+
 /// Builds the page list for ComicInfo based on provided settings.
 ///
 /// This function implements a deletion-by-omission strategy where only pages
@@ -97,9 +67,9 @@ pub struct PageSettings {
 /// deleting it from the ComicInfo metadata.
 ///
 /// For pages that are included, the function preserves metadata (image dimensions,
-/// size, and key) from the original ComicInfo if available, while updating the
-/// user-editable fields (page type, double page flag, and bookmark) from the
-/// provided settings.
+/// size, key, and unknown attributes) from the original ComicInfo if available,
+/// while updating the user-editable fields (page type, double page flag, and
+/// bookmark) from the provided settings.
 ///
 /// # Arguments
 ///
@@ -129,6 +99,7 @@ fn build_page_list(
                 page_info.image_size = original_page.image_size;
                 page_info.image_width = original_page.image_width;
                 page_info.key = original_page.key.clone();
+                page_info.unknown_attributes = original_page.unknown_attributes.clone();
             }
 
             // Set the filename for XML comment generation
@@ -143,18 +114,11 @@ fn build_page_list(
 pub fn save_page_settings_impl(
     path: String,
     page_settings: HashMap<String, PageSettings>,
-) -> Result<(), String> {
-    let archive = read_archive(&path).map_err(|e| e.to_string())?;
-
-    let image_files = archive
-        .files
-        .iter()
-        .filter(|f| is_image_file(&f.name))
-        .map(|f| f.name.clone())
-        .collect::<Vec<_>>();
+) -> Result<(), ErrorResponse> {
+    ensure_writable(&path).map_err(|e| e.to_error_response())?;
+    let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
 
-    let mut sorted = image_files;
-    sorted.sort();
+    let sorted = sorted_image_files(&archive);
 
     let original_comic_info = archive.comic_info.clone();
     let mut updated_comic_info = archive.comic_info.unwrap_or_default();
@@ -176,8 +140,10 @@ pub fn save_page_settings_impl(
     build_page_list(pages, &sorted, &page_settings, &original_pages_map);
 
     suppress_next_archive_event(&path);
-    let xml_content = updated_comic_info.to_xml().map_err(|e| e.to_string())?;
-    update_zip_with_comicinfo(&path, &xml_content)?;
+    let xml_content = updated_comic_info
+        .to_xml()
+        .map_err(|e| e.to_error_response())?;
+    update_zip_with_comicinfo(&path, &xml_content).map_err(|e| e.to_error_response())?;
 
     Ok(())
 }
@@ -216,17 +182,12 @@ fn restore_filenames_from_existing_pages(
 /// For pages without filenames (typically new pages or pages from external sources),
 /// this function maps them to actual image files in the archive by using the image
 /// index to look up the corresponding file in the sorted list of archive images.
-fn populate_filenames_from_archive(comic_info: &mut ComicInfo, archive: &super::types::Archive) {
+pub(crate) fn populate_filenames_from_archive(
+    comic_info: &mut ComicInfo,
+    archive: &super::types::Archive,
+) {
     if let Some(ref mut pages) = comic_info.pages {
-        let image_files = archive
-            .files
-            .iter()
-            .filter(|f| is_image_file(&f.name))
-            .map(|f| f.name.clone())
-            .collect::<Vec<_>>();
-
-        let mut sorted = image_files;
-        sorted.sort();
+        let sorted = sorted_image_files(archive);
 
         for page in &mut pages.page {
             if page.filename.is_none() {
@@ -240,21 +201,25 @@ fn populate_filenames_from_archive(comic_info: &mut ComicInfo, archive: &super::
 }
 
 /// Business logic for saving ComicInfo XML
-pub fn save_comicinfo_xml_impl(path: String, xml: String) -> Result<String, String> {
+pub fn save_comicinfo_xml_impl(path: String, xml: String) -> Result<String, ErrorResponse> {
     debug!("Saving ComicInfo XML to {} with xml {}", path, xml);
 
-    let mut comic_info: ComicInfo = serde_xml_rs::from_str(&xml).map_err(|e| e.to_string())?;
-    comic_info.validate().map_err(|e| e.to_string())?;
+    let mut comic_info = ComicInfo::parse(&xml).map_err(|e| e.to_error_response())?;
 
-    let archive = read_archive(&path).map_err(|e| e.to_string())?;
+    ensure_writable(&path).map_err(|e| e.to_error_response())?;
+    let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
+
+    comic_info
+        .validate(Some(archive.image_files.len()))
+        .map_err(|e| e.to_error_response())?;
 
     restore_filenames_from_existing_pages(&mut comic_info, &archive);
     populate_filenames_from_archive(&mut comic_info, &archive);
 
-    let formatted_xml = comic_info.to_xml().map_err(|e| e.to_string())?;
+    let formatted_xml = comic_info.to_xml().map_err(|e| e.to_error_response())?;
 
     suppress_next_archive_event(&path);
-    update_zip_with_comicinfo(&path, formatted_xml.as_str())?;
+    update_zip_with_comicinfo(&path, formatted_xml.as_str()).map_err(|e| e.to_error_response())?;
 
     Ok(formatted_xml)
 }
@@ -262,6 +227,7 @@ pub fn save_comicinfo_xml_impl(path: String, xml: String) -> Result<String, Stri
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::archive::types::{ArchiveFormat, ErrorDetails, ErrorResponseType};
     use crate::comicinfo::{ComicInfo, ComicPageType};
     use std::collections::HashMap;
     use std::io::{Read, Write};
@@ -289,10 +255,12 @@ mod tests {
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
 
             zip.start_file("image1.jpg", options).expect("start file");
-            zip.write_all(b"fake image data").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data")
+                .expect("write data");
 
             zip.start_file("image2.jpg", options).expect("start file");
-            zip.write_all(b"fake image data 2").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data 2")
+                .expect("write data");
 
             zip.finish().expect("finish zip");
         }
@@ -358,7 +326,8 @@ mod tests {
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
 
             zip.start_file("image1.jpg", options).expect("start file");
-            zip.write_all(b"fake image data").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data")
+                .expect("write data");
 
             zip.finish().expect("finish zip");
         }
@@ -407,6 +376,28 @@ mod tests {
         let _ = std::fs::remove_file(&path);
     }
 
+    #[test]
+    fn test_save_comicinfo_xml_impl_keeps_unknown_elements() {
+        let path = test_path("test_save_xml_unknown.cbz");
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let file = std::fs::File::create(&path).expect("create cbz");
+            let zip = zip::ZipWriter::new(file);
+            zip.finish().expect("finish zip");
+        }
+
+        let xml = r#"<ComicInfo>
+  <Title>Extended</Title>
+  <Extension source="other-tool">value</Extension>
+</ComicInfo>"#;
+
+        let saved = save_comicinfo_xml_impl(path.clone(), xml.to_string()).unwrap();
+        assert!(saved.contains(r#"<Extension source="other-tool">value</Extension>"#));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
     #[test]
     fn test_save_comicinfo_xml_impl_invalid() {
         let path = test_path("test_save_xml_invalid.cbz");
@@ -425,6 +416,46 @@ mod tests {
         let _ = std::fs::remove_file(&path);
     }
 
+    #[test]
+    fn test_update_comicinfo_refuses_rar_archive() {
+        let path = test_path("test_refuse_rar.cbr");
+        std::fs::write(&path, b"Rar!\x1a\x07\x01\x00not really a rar").expect("write cbr");
+
+        let result = update_zip_with_comicinfo(&path, "<ComicInfo></ComicInfo>");
+
+        match result {
+            Err(err @ WriteArchiveError::ReadOnlyFormat { .. }) => {
+                assert!(err.to_string().contains("Convert the archive to CBZ"));
+            }
+            other => panic!("expected ReadOnlyFormat error, got {:?}", other),
+        }
+
+        let content = std::fs::read(&path).expect("read cbr");
+        assert!(content.starts_with(b"Rar!"), "CBR must be left untouched");
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_save_comicinfo_xml_impl_offers_conversion_for_rar_archive() {
+        let path = test_path("test_save_xml_rar.cbr");
+        std::fs::write(&path, b"Rar!\x1a\x07\x01\x00not really a rar").expect("write cbr");
+
+        let err = save_comicinfo_xml_impl(path.clone(), "<ComicInfo></ComicInfo>".to_string())
+            .unwrap_err();
+
+        assert_eq!(err.error_type, ErrorResponseType::ReadOnlyArchiveFormat);
+        assert_eq!(
+            err.details,
+            Some(ErrorDetails::Format {
+                format: ArchiveFormat::Rar,
+                convert_to: Some(ArchiveFormat::Zip),
+            })
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
     #[test]
     fn test_save_page_settings_deletes_page_when_not_in_settings() {
         let path = test_path("test_page_deletion.cbz");
@@ -438,13 +469,16 @@ mod tests {
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
 
             zip.start_file("image1.jpg", options).expect("start file");
-            zip.write_all(b"fake image data").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data")
+                .expect("write data");
 
             zip.start_file("image2.jpg", options).expect("start file");
-            zip.write_all(b"fake image data 2").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data 2")
+                .expect("write data");
 
             zip.start_file("image3.jpg", options).expect("start file");
-            zip.write_all(b"fake image data 3").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data 3")
+                .expect("write data");
 
             zip.finish().expect("finish zip");
         }
@@ -557,10 +591,12 @@ mod tests {
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
 
             zip.start_file("image1.jpg", options).expect("start file");
-            zip.write_all(b"fake image data").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data")
+                .expect("write data");
 
             zip.start_file("image2.jpg", options).expect("start file");
-            zip.write_all(b"fake image data 2").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data 2")
+                .expect("write data");
 
             zip.finish().expect("finish zip");
         }
@@ -630,10 +666,12 @@ mod tests {
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
 
             zip.start_file("image1.jpg", options).expect("start file");
-            zip.write_all(b"fake image data").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data")
+                .expect("write data");
 
             zip.start_file("image2.jpg", options).expect("start file");
-            zip.write_all(b"fake image data 2").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data 2")
+                .expect("write data");
 
             zip.finish().expect("finish zip");
         }
@@ -722,13 +760,16 @@ mod tests {
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
 
             zip.start_file("image1.jpg", options).expect("start file");
-            zip.write_all(b"fake image data").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data")
+                .expect("write data");
 
             zip.start_file("image2.jpg", options).expect("start file");
-            zip.write_all(b"fake image data 2").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data 2")
+                .expect("write data");
 
             zip.start_file("image3.jpg", options).expect("start file");
-            zip.write_all(b"fake image data 3").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image data 3")
+                .expect("write data");
 
             zip.finish().expect("finish zip");
         }
@@ -831,13 +872,16 @@ mod tests {
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
 
             zip.start_file("cover.jpg", options).expect("start file");
-            zip.write_all(b"fake cover").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake cover")
+                .expect("write data");
 
             zip.start_file("page001.jpg", options).expect("start file");
-            zip.write_all(b"fake page 1").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake page 1")
+                .expect("write data");
 
             zip.start_file("page002.jpg", options).expect("start file");
-            zip.write_all(b"fake page 2").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake page 2")
+                .expect("write data");
 
             zip.finish().expect("finish zip");
         }
@@ -915,10 +959,12 @@ mod tests {
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
 
             zip.start_file("image1.jpg", options).expect("start file");
-            zip.write_all(b"fake image 1").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image 1")
+                .expect("write data");
 
             zip.start_file("image2.jpg", options).expect("start file");
-            zip.write_all(b"fake image 2").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake image 2")
+                .expect("write data");
 
             zip.finish().expect("finish zip");
         }
@@ -997,10 +1043,12 @@ mod tests {
                 ZipFileOptions::<()>::default().compression_method(ZipCompressionMethod::Stored);
 
             zip.start_file("page1.jpg", options).expect("start file");
-            zip.write_all(b"fake page 1").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake page 1")
+                .expect("write data");
 
             zip.start_file("page2.jpg", options).expect("start file");
-            zip.write_all(b"fake page 2").expect("write data");
+            zip.write_all(b"\xFF\xD8\xFFfake page 2")
+                .expect("write data");
 
             zip.finish().expect("finish zip");
         }
@@ -1,21 +1,17 @@
 use super::get_bookmarked_pages as super_get_bookmarked_pages;
 use super::info::ComicInfo;
+use super::validation::ValidationIssue;
+use crate::archive::order::sorted_image_files;
 use crate::archive::read_archive;
+use crate::archive::reader::list_image_files;
+use crate::archive::types::{ErrorResponse, ToErrorResponse};
 
 #[tauri::command]
-pub async fn get_bookmarked_pages(path: String) -> Result<Vec<String>, String> {
-    let archive = read_archive(&path).map_err(|e| e.to_string())?;
+pub async fn get_bookmarked_pages(path: String) -> Result<Vec<String>, ErrorResponse> {
+    let archive = read_archive(&path).map_err(|e| e.to_error_response())?;
 
     if let Some(comic_info) = &archive.comic_info {
-        let image_files = archive
-            .files
-            .iter()
-            .filter(|f| crate::archive::is_image_file(&f.name))
-            .map(|f| f.name.clone())
-            .collect::<Vec<_>>();
-
-        let mut sorted = image_files;
-        sorted.sort();
+        let sorted = sorted_image_files(&archive);
 
         Ok(super_get_bookmarked_pages(comic_info, &sorted))
     } else {
@@ -23,15 +19,30 @@ pub async fn get_bookmarked_pages(path: String) -> Result<Vec<String>, String> {
     }
 }
 
+/// Validates ComicInfo XML and returns its warnings. When `path` is given the
+/// page rules are checked against that archive's images.
 #[tauri::command]
-pub fn validate_comicinfo_xml(xml: String) -> Result<(), String> {
-    let comic_info = ComicInfo::parse(&xml).map_err(|e| format!("Parse error: {}", e))?;
+pub fn validate_comicinfo_xml(
+    xml: String,
+    path: Option<String>,
+) -> Result<Vec<ValidationIssue>, ErrorResponse> {
+    let comic_info = ComicInfo::parse(&xml).map_err(|e| e.to_error_response())?;
+
+    let image_count = match path {
+        Some(path) => Some(
+            list_image_files(&path)
+                .map_err(|e| e.to_error_response())?
+                .len(),
+        ),
+        None => None,
+    };
+
     comic_info
-        .validate()
-        .map_err(|e| format!("Validation error: {}", e))
+        .validate(image_count)
+        .map_err(|e| e.to_error_response())
 }
 
 #[tauri::command]
-pub fn format_comicinfo_xml(xml: String) -> Result<String, String> {
+pub fn format_comicinfo_xml(xml: String) -> Result<String, ErrorResponse> {
     super::info::format_comicinfo_xml_str(&xml)
 }
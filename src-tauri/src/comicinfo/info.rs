@@ -1,9 +1,12 @@
+use super::lenient::{ParseDiagnostic, sanitize_values};
 use super::page::Pages;
 use super::types::{
     AgeRating, Manga, YesNo, default_age_rating, default_manga, default_minus_one, default_yes_no,
     is_minus_one, is_unknown_age_rating, is_unknown_manga, is_unknown_yes_no, is_zero_i32,
 };
-use crate::archive::types::{ErrorResponse, ErrorResponseType, ToErrorResponse};
+use super::unknown::{RawAttributes, split_unknown, with_raw_attributes};
+use super::validation::{ValidationIssue, ValidationSeverity, validation_issues};
+use crate::archive::types::{ErrorDetails, ErrorResponse, ErrorResponseType, ToErrorResponse};
 use quick_xml::events::Event;
 use quick_xml::{Reader, Writer};
 use serde::{Deserialize, Serialize};
@@ -180,6 +183,14 @@ pub struct ComicInfo {
     pub review: Option<String>,
     #[serde(rename = "GTIN", default, skip_serializing_if = "Option::is_none")]
     pub gtin: Option<String>,
+    /// Attributes of the root element, such as namespace declarations,
+    /// written back verbatim.
+    #[serde(skip)]
+    pub unknown_attributes: RawAttributes,
+    /// Child elements outside the schema, kept as raw XML and written back
+    /// verbatim.
+    #[serde(skip)]
+    pub unknown_elements: Vec<String>,
 }
 
 impl Default for ComicInfo {
@@ -229,6 +240,8 @@ impl Default for ComicInfo {
             main_character_or_team: None,
             review: None,
             gtin: None,
+            unknown_attributes: Vec::new(),
+            unknown_elements: Vec::new(),
         }
     }
 }
@@ -278,70 +291,151 @@ impl From<std::str::Utf8Error> for ComicInfoParseError {
     }
 }
 
-impl ComicInfo {
-    pub fn parse(xml: &str) -> Result<ComicInfo, ComicInfoParseError> {
-        if xml.trim().is_empty() {
-            return Err(ComicInfoParseError::EmptyXml);
-        }
+/// Checks that `xml` holds exactly one root element and nothing after it.
+fn check_document_structure(xml: &str) -> Result<(), ComicInfoParseError> {
+    if xml.trim().is_empty() {
+        return Err(ComicInfoParseError::EmptyXml);
+    }
 
-        let mut reader = Reader::from_str(xml);
-        reader.trim_text(true);
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
 
-        let mut buf = Vec::new();
-        let mut depth: usize = 0;
-        let mut root_seen_end = false;
+    let mut buf = Vec::new();
+    let mut depth: usize = 0;
+    let mut root_seen_end = false;
 
-        loop {
-            match reader.read_event_into(&mut buf) {
-                Ok(Event::Start(_)) => {
-                    depth = depth.saturating_add(1);
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(_)) => {
+                depth = depth.saturating_add(1);
+            }
+            Ok(Event::Empty(_)) => {
+                if depth == 0 {
+                    root_seen_end = true;
                 }
-                Ok(Event::Empty(_)) => {
+            }
+            Ok(Event::End(_)) => {
+                if depth > 0 {
+                    depth -= 1;
                     if depth == 0 {
                         root_seen_end = true;
                     }
                 }
-                Ok(Event::End(_)) => {
-                    if depth > 0 {
-                        depth -= 1;
-                        if depth == 0 {
-                            root_seen_end = true;
-                        }
-                    }
-                }
-                Ok(Event::Text(e)) => {
-                    if root_seen_end {
-                        let s = std::str::from_utf8(e.as_ref())?;
-                        if !s.trim().is_empty() {
-                            return Err(ComicInfoParseError::TrailingContent);
-                        }
+            }
+            Ok(Event::Text(e)) => {
+                if root_seen_end {
+                    let s = std::str::from_utf8(e.as_ref())?;
+                    if !s.trim().is_empty() {
+                        return Err(ComicInfoParseError::TrailingContent);
                     }
                 }
-                Ok(Event::Decl(_)) | Ok(Event::Comment(_)) | Ok(Event::PI(_)) => {}
-                Ok(Event::CData(e)) => {
-                    if root_seen_end {
-                        let s = std::str::from_utf8(e.as_ref())?;
-                        if !s.trim().is_empty() {
-                            return Err(ComicInfoParseError::TrailingContent);
-                        }
+            }
+            Ok(Event::Decl(_)) | Ok(Event::Comment(_)) | Ok(Event::PI(_)) => {}
+            Ok(Event::CData(e)) => {
+                if root_seen_end {
+                    let s = std::str::from_utf8(e.as_ref())?;
+                    if !s.trim().is_empty() {
+                        return Err(ComicInfoParseError::TrailingContent);
                     }
                 }
-                Ok(Event::Eof) => break,
-                Err(e) => return Err(ComicInfoParseError::QuickXml(e)),
-                _ => {}
             }
-            buf.clear();
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ComicInfoParseError::QuickXml(e)),
+            _ => {}
         }
+        buf.clear();
+    }
+
+    if !root_seen_end {
+        return Err(ComicInfoParseError::NoRootElement);
+    }
+
+    Ok(())
+}
+
+impl ComicInfo {
+    pub fn parse(xml: &str) -> Result<ComicInfo, ComicInfoParseError> {
+        check_document_structure(xml)?;
+        Self::deserialize_preserving_unknown(xml)
+    }
+
+    /// Parses `xml`, replacing values that do not match their schema type
+    /// with the field's fallback instead of failing.
+    ///
+    /// Malformed XML is still an error.
+    pub fn parse_lenient(
+        xml: &str,
+    ) -> Result<(ComicInfo, Vec<ParseDiagnostic>), ComicInfoParseError> {
+        check_document_structure(xml)?;
+        let (sanitized, diagnostics) = sanitize_values(xml)?;
+        let comic_info = Self::deserialize_preserving_unknown(&sanitized)?;
 
-        if !root_seen_end {
-            return Err(ComicInfoParseError::NoRootElement);
+        Ok((comic_info, diagnostics))
+    }
+
+    fn deserialize_preserving_unknown(xml: &str) -> Result<ComicInfo, ComicInfoParseError> {
+        let (known_xml, unknown) = split_unknown(xml)?;
+        let mut comic_info: ComicInfo = serde_xml_rs::from_str(&known_xml)?;
+        comic_info.unknown_attributes = unknown.root_attributes;
+        comic_info.unknown_elements = unknown.elements;
+
+        if let Some(pages) = comic_info.pages.as_mut() {
+            for (page, attributes) in pages.page.iter_mut().zip(unknown.page_attributes) {
+                page.unknown_attributes = attributes;
+            }
         }
 
-        let comic_info: ComicInfo = serde_xml_rs::from_str(xml)?;
         Ok(comic_info)
     }
 }
 
+/// Returns the Image attribute of a Page element.
+fn page_image_index(event: &quick_xml::events::BytesStart) -> Option<i32> {
+    event
+        .attributes()
+        .filter_map(|a| a.ok())
+        .find(|attr| attr.key.as_ref() == b"Image")
+        .and_then(|attr| {
+            std::str::from_utf8(&attr.value)
+                .ok()
+                .and_then(|s| s.parse::<i32>().ok())
+        })
+}
+
+/// Reads the filename comments written before each Page element, keyed by
+/// image index.
+///
+/// These record which file each page described when the XML was written.
+/// Unreadable XML yields the comments found up to the error.
+pub fn recorded_page_filenames(xml: &str) -> std::collections::HashMap<i32, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut filenames = std::collections::HashMap::new();
+    let mut pending: Option<String> = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Comment(text)) => {
+                let text = String::from_utf8_lossy(&text).into_owned();
+                pending = text.trim().strip_prefix("filename:").map(|filename| {
+                    quick_xml::escape::unescape(filename.trim())
+                        .map(|f| f.into_owned())
+                        .unwrap_or_else(|_| filename.trim().to_string())
+                });
+            }
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"Page" => {
+                if let (Some(filename), Some(image)) = (pending.take(), page_image_index(&e)) {
+                    filenames.insert(image, filename);
+                }
+            }
+            Ok(Event::Text(_)) => {}
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => pending = None,
+        }
+    }
+
+    filenames
+}
+
 /// Writes a Page event to the XML writer, optionally preceded by a filename comment.
 ///
 /// Extracts the Image attribute from the event, looks up the corresponding filename
@@ -362,17 +456,7 @@ fn write_page_event_with_filename_comment<'a, W: std::io::Write>(
         quick_xml::events::BytesStart<'a>,
     ) -> Result<(), quick_xml::Error>,
 ) -> Result<(), ComicInfoError> {
-    let image_idx = event
-        .attributes()
-        .filter_map(|a| a.ok())
-        .find(|attr| attr.key.as_ref() == b"Image")
-        .and_then(|attr| {
-            std::str::from_utf8(&attr.value)
-                .ok()
-                .and_then(|s| s.parse::<i32>().ok())
-        });
-
-    if let Some(idx) = image_idx {
+    if let Some(idx) = page_image_index(event) {
         if let Some(filename) = page_filenames.get(&idx) {
             let comment = format!(" filename: {} ", filename);
             writer
@@ -385,6 +469,24 @@ fn write_page_event_with_filename_comment<'a, W: std::io::Write>(
     Ok(())
 }
 
+/// Writes each raw element on its own line at the current indentation.
+fn write_unknown_elements<W: std::io::Write>(
+    elements: &[String],
+    writer: &mut Writer<W>,
+) -> Result<(), ComicInfoError> {
+    for element in elements {
+        writer
+            .write_indent()
+            .map_err(|e| ComicInfoError::ToXml(e.to_string()))?;
+        writer
+            .get_mut()
+            .write_all(element.as_bytes())
+            .map_err(|e| ComicInfoError::ToXml(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
 impl ComicInfo {
     pub fn to_xml(&self) -> Result<String, ComicInfoError> {
         let xml =
@@ -408,6 +510,12 @@ impl ComicInfo {
             })
             .unwrap_or_default();
 
+        let mut page_attributes = self
+            .pages
+            .iter()
+            .flat_map(|pages| pages.page.iter())
+            .map(|page| page.unknown_attributes.as_slice());
+
         loop {
             match reader.read_event() {
                 Ok(Event::Eof) => break,
@@ -416,17 +524,50 @@ impl ComicInfo {
                         .write_event(Event::Decl(decl))
                         .map_err(|e| ComicInfoError::ToXml(e.to_string()))?;
                 }
+                Ok(Event::Start(e)) if e.name().as_ref() == b"ComicInfo" => {
+                    let root = with_raw_attributes(e, &self.unknown_attributes);
+                    writer
+                        .write_event(Event::Start(root))
+                        .map_err(|e| ComicInfoError::ToXml(e.to_string()))?;
+                }
+                Ok(Event::Empty(e)) if e.name().as_ref() == b"ComicInfo" => {
+                    let root = with_raw_attributes(e, &self.unknown_attributes);
+
+                    if self.unknown_elements.is_empty() {
+                        writer
+                            .write_event(Event::Empty(root))
+                            .map_err(|e| ComicInfoError::ToXml(e.to_string()))?;
+                        continue;
+                    }
+
+                    let end = root.to_end().into_owned();
+                    writer
+                        .write_event(Event::Start(root))
+                        .map_err(|e| ComicInfoError::ToXml(e.to_string()))?;
+                    write_unknown_elements(&self.unknown_elements, &mut writer)?;
+                    writer
+                        .write_event(Event::End(end))
+                        .map_err(|e| ComicInfoError::ToXml(e.to_string()))?;
+                }
+                Ok(Event::End(e)) if e.name().as_ref() == b"ComicInfo" => {
+                    write_unknown_elements(&self.unknown_elements, &mut writer)?;
+                    writer
+                        .write_event(Event::End(e))
+                        .map_err(|e| ComicInfoError::ToXml(e.to_string()))?;
+                }
                 Ok(Event::Empty(e)) if e.name().as_ref() == b"Page" => {
+                    let page = with_raw_attributes(e, page_attributes.next().unwrap_or(&[]));
                     write_page_event_with_filename_comment(
-                        &e,
+                        &page,
                         &page_filenames,
                         &mut writer,
                         |w, evt| w.write_event(Event::Empty(evt)),
                     )?;
                 }
                 Ok(Event::Start(e)) if e.name().as_ref() == b"Page" => {
+                    let page = with_raw_attributes(e, page_attributes.next().unwrap_or(&[]));
                     write_page_event_with_filename_comment(
-                        &e,
+                        &page,
                         &page_filenames,
                         &mut writer,
                         |w, evt| w.write_event(Event::Start(evt)),
@@ -444,24 +585,32 @@ impl ComicInfo {
         String::from_utf8(output).map_err(|e| ComicInfoError::ToXml(e.to_string()))
     }
 
-    pub fn validate(&self) -> Result<(), ComicInfoError> {
-        if let Some(pages) = &self.pages {
-            for page in &pages.page {
-                if page.image < 0 {
-                    return Err(ComicInfoError::Validate(
-                        "Page image index must be non-negative".into(),
-                    ));
-                }
-            }
+    /// Checks field values against the schema's semantic rules.
+    ///
+    /// Returns the warnings when no rule is broken badly enough to refuse a
+    /// save; otherwise fails with every issue found, warnings included.
+    /// `image_count` enables the rules that compare against the archive.
+    pub fn validate(
+        &self,
+        image_count: Option<usize>,
+    ) -> Result<Vec<ValidationIssue>, ComicInfoError> {
+        let issues = validation_issues(self, image_count);
+
+        if issues
+            .iter()
+            .any(|issue| issue.severity == ValidationSeverity::Error)
+        {
+            return Err(ComicInfoError::Validate(issues));
         }
-        Ok(())
+
+        Ok(issues)
     }
 }
 
 #[derive(Debug)]
 pub enum ComicInfoError {
     ToXml(String),
-    Validate(String),
+    Validate(Vec<ValidationIssue>),
 }
 
 impl fmt::Display for ComicInfoError {
@@ -470,7 +619,14 @@ impl fmt::Display for ComicInfoError {
             ComicInfoError::ToXml(msg) => {
                 write!(f, "Failed to serialize ComicInfo to XML: {}", msg)
             }
-            ComicInfoError::Validate(msg) => write!(f, "ComicInfo validation failed: {}", msg),
+            ComicInfoError::Validate(issues) => {
+                let issues = issues
+                    .iter()
+                    .map(|issue| issue.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                write!(f, "ComicInfo validation failed: {}", issues)
+            }
         }
     }
 }
@@ -483,13 +639,25 @@ impl ToErrorResponse for ComicInfoError {
             ComicInfoError::ToXml(msg) => {
                 ErrorResponse::new(ErrorResponseType::FailedToParseComicInfoXml, msg.clone())
             }
-            ComicInfoError::Validate(msg) => {
-                ErrorResponse::new(ErrorResponseType::ComicInfoXmlInvalid, msg.clone())
+            ComicInfoError::Validate(issues) => {
+                ErrorResponse::new(ErrorResponseType::ComicInfoXmlInvalid, self.to_string())
+                    .with_details(ErrorDetails::Validation {
+                        issues: issues.clone(),
+                    })
             }
         }
     }
 }
 
+impl ToErrorResponse for ComicInfoParseError {
+    fn to_error_response(&self) -> ErrorResponse {
+        ErrorResponse::new(
+            ErrorResponseType::FailedToParseComicInfoXml,
+            format!("Failed to parse ComicInfo XML: {}", self),
+        )
+    }
+}
+
 impl<'de> serde::Deserialize<'de> for ComicInfo {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -634,6 +802,8 @@ impl<'de> serde::Deserialize<'de> for ComicInfo {
             main_character_or_team: strict.main_character_or_team,
             review: strict.review,
             gtin: strict.gtin,
+            unknown_attributes: Vec::new(),
+            unknown_elements: Vec::new(),
         })
     }
 }
@@ -656,11 +826,9 @@ pub fn get_bookmarked_pages(comic_info: &ComicInfo, image_files: &[String]) -> V
 }
 
 /// Formats a ComicInfo XML string and returns the formatted XML or an error string.
-pub fn format_comicinfo_xml_str(xml: &str) -> Result<String, String> {
-    let comic_info = ComicInfo::parse(xml).map_err(|e| format!("Parse error: {}", e))?;
-    comic_info
-        .to_xml()
-        .map_err(|e| format!("Serialization error: {}", e))
+pub fn format_comicinfo_xml_str(xml: &str) -> Result<String, ErrorResponse> {
+    let comic_info = ComicInfo::parse(xml).map_err(|e| e.to_error_response())?;
+    comic_info.to_xml().map_err(|e| e.to_error_response())
 }
 
 #[cfg(test)]
@@ -687,7 +855,7 @@ mod tests {
             "Expected error for empty XML, got: {:?}",
             result
         );
-        let err_msg = result.unwrap_err();
+        let err_msg = result.unwrap_err().message;
         assert!(
             err_msg.contains(&expected),
             "Expected error message to contain '{}', got: '{}'",
@@ -706,7 +874,7 @@ mod tests {
             "Expected error for malformed XML, got: {:?}",
             result
         );
-        let err_msg = result.unwrap_err();
+        let err_msg = result.unwrap_err().message;
         assert!(
             err_msg.contains(&expected),
             "Expected error message to contain '{}', got: '{}'",
@@ -725,7 +893,7 @@ mod tests {
             "Expected error for trailing content, got: {:?}",
             result
         );
-        let err_msg = result.unwrap_err();
+        let err_msg = result.unwrap_err().message;
         assert!(
             err_msg.contains(&expected),
             "Expected error message to contain '{}', got: '{}'",
@@ -734,6 +902,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_recorded_page_filenames() {
+        let xml = r#"<ComicInfo>
+  <Pages>
+    <!-- filename: cover &amp; logo.jpg -->
+    <Page Image="0" Type="FrontCover" />
+    <!-- a note -->
+    <Page Image="1" />
+    <!-- filename: page10.jpg -->
+    <Page Image="2"></Page>
+  </Pages>
+</ComicInfo>"#;
+
+        let filenames = recorded_page_filenames(xml);
+
+        assert_eq!(filenames.len(), 2);
+        assert_eq!(filenames[&0], "cover & logo.jpg");
+        assert_eq!(filenames[&2], "page10.jpg");
+    }
+
     #[test]
     fn test_to_xml_with_filename_comments() {
         let comic = ComicInfo {
@@ -749,6 +937,7 @@ mod tests {
                         image_width: -1,
                         image_height: -1,
                         filename: Some("cover.jpg".to_string()),
+                        unknown_attributes: Vec::new(),
                     },
                     ComicPageInfo {
                         image: 1,
@@ -760,6 +949,7 @@ mod tests {
                         image_width: -1,
                         image_height: -1,
                         filename: Some("page001.jpg".to_string()),
+                        unknown_attributes: Vec::new(),
                     },
                 ],
             }),
@@ -800,6 +990,7 @@ mod tests {
                     image_width: -1,
                     image_height: -1,
                     filename: None,
+                    unknown_attributes: Vec::new(),
                 }],
             }),
             ..ComicInfo::default()
@@ -829,6 +1020,7 @@ mod tests {
                         image_width: -1,
                         image_height: -1,
                         filename: Some("cover.jpg".to_string()),
+                        unknown_attributes: Vec::new(),
                     },
                     ComicPageInfo {
                         image: 1,
@@ -840,6 +1032,7 @@ mod tests {
                         image_width: -1,
                         image_height: -1,
                         filename: None, // This one has no filename
+                        unknown_attributes: Vec::new(),
                     },
                     ComicPageInfo {
                         image: 2,
@@ -851,6 +1044,7 @@ mod tests {
                         image_width: -1,
                         image_height: -1,
                         filename: Some("back.jpg".to_string()),
+                        unknown_attributes: Vec::new(),
                     },
                 ],
             }),
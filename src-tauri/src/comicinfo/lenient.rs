@@ -0,0 +1,260 @@
+use super::info::ComicInfoParseError;
+use super::types::{AgeRating, ComicPageType, Manga, YesNo};
+use super::unknown::name_of;
+use quick_xml::escape::unescape;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::name::QName;
+use quick_xml::{Reader, Writer};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// A value in ComicInfo.xml that does not match its schema type, and the
+/// fallback used in its place.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParseDiagnostic {
+    pub element: String,
+    pub attribute: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub value: String,
+    pub fallback: String,
+}
+
+/// How an invalid value is recovered from.
+enum Recovery {
+    /// The value is dropped so the field takes its default.
+    Drop(&'static str),
+    /// The value is kept and the deserializer maps it to the fallback.
+    Keep(&'static str),
+}
+
+impl Recovery {
+    fn fallback(&self) -> &'static str {
+        match self {
+            Recovery::Drop(fallback) | Recovery::Keep(fallback) => fallback,
+        }
+    }
+}
+
+fn drop_unless(valid: bool, fallback: &'static str) -> Option<Recovery> {
+    (!valid).then_some(Recovery::Drop(fallback))
+}
+
+fn keep_unless(valid: bool, fallback: &'static str) -> Option<Recovery> {
+    (!valid).then_some(Recovery::Keep(fallback))
+}
+
+fn element_recovery(element: &str, value: &str) -> Option<Recovery> {
+    let value = value.trim();
+
+    match element {
+        "Count" | "Volume" | "AlternateCount" | "Year" | "Month" | "Day" => {
+            drop_unless(value.parse::<i32>().is_ok(), "-1")
+        }
+        "PageCount" => drop_unless(value.parse::<i32>().is_ok(), "0"),
+        "CommunityRating" => drop_unless(value.parse::<f32>().is_ok(), "none"),
+        "BlackAndWhite" => keep_unless(
+            value == "Unknown" || YesNo::from_str(value) != Ok(YesNo::Unknown),
+            "Unknown",
+        ),
+        "Manga" => keep_unless(
+            value == "Unknown" || Manga::from_str(value) != Ok(Manga::Unknown),
+            "Unknown",
+        ),
+        "AgeRating" => keep_unless(
+            value == "Unknown" || AgeRating::from_str(value) != Ok(AgeRating::Unknown),
+            "Unknown",
+        ),
+        _ => None,
+    }
+}
+
+fn page_attribute_recovery(attribute: &str, value: &str) -> Option<Recovery> {
+    let value = value.trim();
+
+    match attribute {
+        "Image" => drop_unless(value.parse::<i32>().is_ok(), "0"),
+        "ImageWidth" | "ImageHeight" => drop_unless(value.parse::<i32>().is_ok(), "-1"),
+        "ImageSize" => drop_unless(value.parse::<i64>().is_ok(), "0"),
+        "DoublePage" => drop_unless(value.parse::<bool>().is_ok(), "false"),
+        "Type" => {
+            let first = value.split_whitespace().next().unwrap_or("");
+            keep_unless(
+                first.is_empty()
+                    || first == "Other"
+                    || ComicPageType::from_str(first) != Ok(ComicPageType::Other),
+                "Other",
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Elements whose text must match a schema type.
+const CHECKED_ELEMENTS: &[&str] = &[
+    "Count",
+    "Volume",
+    "AlternateCount",
+    "Year",
+    "Month",
+    "Day",
+    "PageCount",
+    "CommunityRating",
+    "BlackAndWhite",
+    "Manga",
+    "AgeRating",
+];
+
+fn is_checked_element(element: &str) -> bool {
+    CHECKED_ELEMENTS.contains(&element)
+}
+
+/// Converts a byte offset into a 1-based line and column.
+fn line_and_column(xml: &str, position: usize) -> (usize, usize) {
+    let before = &xml[..position.min(xml.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+    (line, before[line_start..].chars().count() + 1)
+}
+
+fn unescaped(raw: &str) -> String {
+    unescape(raw)
+        .map(|s| s.into_owned())
+        .unwrap_or_else(|_| raw.to_string())
+}
+
+struct Diagnostics<'a> {
+    xml: &'a str,
+    found: Vec<ParseDiagnostic>,
+}
+
+impl Diagnostics<'_> {
+    fn push(
+        &mut self,
+        position: usize,
+        element: &str,
+        attribute: Option<&str>,
+        value: &str,
+        fallback: &str,
+    ) {
+        let (line, column) = line_and_column(self.xml, position);
+
+        self.found.push(ParseDiagnostic {
+            element: element.to_string(),
+            attribute: attribute.map(str::to_string),
+            line,
+            column,
+            value: unescaped(value),
+            fallback: fallback.to_string(),
+        });
+    }
+}
+
+/// Copies a `<Page>` element, dropping attributes whose values cannot be read.
+fn sanitize_page(
+    start: &BytesStart,
+    position: usize,
+    diagnostics: &mut Diagnostics,
+) -> Result<BytesStart<'static>, ComicInfoParseError> {
+    let mut kept = BytesStart::new("Page");
+
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(quick_xml::Error::from)?;
+        let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+        let value = String::from_utf8_lossy(&attribute.value).into_owned();
+
+        let Some(recovery) = page_attribute_recovery(&key, &unescaped(&value)) else {
+            kept.push_attribute(attribute);
+            continue;
+        };
+
+        diagnostics.push(position, "Page", Some(&key), &value, recovery.fallback());
+
+        if let Recovery::Keep(_) = recovery {
+            kept.push_attribute(attribute);
+        }
+    }
+
+    Ok(kept)
+}
+
+/// Finds values that do not match their schema type.
+///
+/// Returns a copy of `xml` without the values that would make deserialization
+/// fail, and a diagnostic for every invalid value found.
+pub fn sanitize_values(xml: &str) -> Result<(String, Vec<ParseDiagnostic>), ComicInfoParseError> {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Vec::new());
+    let mut diagnostics = Diagnostics {
+        xml,
+        found: Vec::new(),
+    };
+    let mut depth: usize = 0;
+    let mut in_pages = false;
+
+    loop {
+        let position = reader.buffer_position();
+
+        let event = match reader.read_event()? {
+            Event::Eof => break,
+            Event::Start(e) if depth == 1 && is_checked_element(&name_of(&e)) => {
+                let name = name_of(&e);
+                let text = reader.read_text(QName(name.as_bytes()))?;
+
+                if let Some(recovery) = element_recovery(&name, &unescaped(&text)) {
+                    diagnostics.push(position, &name, None, &text, recovery.fallback());
+
+                    if let Recovery::Drop(_) = recovery {
+                        continue;
+                    }
+                }
+
+                writer.write_event(Event::Start(e))?;
+                writer.write_event(Event::Text(BytesText::from_escaped(text)))?;
+                writer.write_event(Event::End(BytesEnd::new(name)))?;
+                continue;
+            }
+            Event::Empty(e) if depth == 1 && is_checked_element(&name_of(&e)) => {
+                let name = name_of(&e);
+
+                if let Some(recovery) = element_recovery(&name, "") {
+                    diagnostics.push(position, &name, None, "", recovery.fallback());
+                }
+
+                continue;
+            }
+            Event::Start(e) if in_pages && depth == 2 && e.name().as_ref() == b"Page" => {
+                Event::Start(sanitize_page(&e, position, &mut diagnostics)?)
+            }
+            Event::Empty(e) if in_pages && depth == 2 && e.name().as_ref() == b"Page" => {
+                Event::Empty(sanitize_page(&e, position, &mut diagnostics)?)
+            }
+            Event::Start(e) => {
+                if depth == 1 {
+                    in_pages = e.name().as_ref() == b"Pages";
+                }
+
+                Event::Start(e)
+            }
+            event => event,
+        };
+
+        match &event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => {
+                depth = depth.saturating_sub(1);
+
+                if depth == 1 {
+                    in_pages = false;
+                }
+            }
+            _ => {}
+        }
+
+        writer.write_event(event)?;
+    }
+
+    let sanitized = String::from_utf8(writer.into_inner()).map_err(|e| e.utf8_error())?;
+    Ok((sanitized, diagnostics.found))
+}
@@ -1,9 +1,12 @@
 pub mod commands;
 pub mod info;
+pub mod lenient;
 pub mod page;
 pub mod types;
+pub mod unknown;
+pub mod validation;
 
-pub use info::{ComicInfo, get_bookmarked_pages};
+pub use info::{ComicInfo, get_bookmarked_pages, recorded_page_filenames};
 pub use page::{ComicPageInfo, Pages};
 pub use types::ComicPageType;
 
@@ -34,6 +37,73 @@ mod tests {
         assert!(comic.is_err());
     }
 
+    #[test]
+    fn test_parse_lenient_reports_invalid_values() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo>
+  <Title>Lenient</Title>
+  <Count>INVALID_NUMBER</Count>
+  <Year>2025</Year>
+  <BlackAndWhite>MAYBE</BlackAndWhite>
+  <Pages>
+    <Page Image="0" Type="INVALID_TYPE" ImageWidth="wide" />
+  </Pages>
+</ComicInfo>"#;
+
+        assert!(ComicInfo::parse(xml).is_err());
+
+        let (comic, diagnostics) = ComicInfo::parse_lenient(xml).unwrap();
+        assert_eq!(comic.title, Some("Lenient".to_string()));
+        assert_eq!(comic.count, -1);
+        assert_eq!(comic.year, 2025);
+        assert_eq!(comic.black_and_white, types::YesNo::Unknown);
+
+        let page = &comic.pages.as_ref().unwrap().page[0];
+        assert_eq!(page.type_, Some(ComicPageType::Other));
+        assert_eq!(page.image_width, -1);
+
+        assert_eq!(
+            diagnostics[0],
+            lenient::ParseDiagnostic {
+                element: "Count".to_string(),
+                attribute: None,
+                line: 4,
+                column: 3,
+                value: "INVALID_NUMBER".to_string(),
+                fallback: "-1".to_string(),
+            }
+        );
+        let reported: Vec<(&str, Option<&str>, &str)> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.element.as_str(),
+                    d.attribute.as_deref(),
+                    d.fallback.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                ("Count", None, "-1"),
+                ("BlackAndWhite", None, "Unknown"),
+                ("Page", Some("Type"), "Other"),
+                ("Page", Some("ImageWidth"), "-1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_lenient_without_problems() {
+        let xml = r#"<ComicInfo><Count>3</Count><Manga>YesAndRightToLeft</Manga></ComicInfo>"#;
+
+        let (comic, diagnostics) = ComicInfo::parse_lenient(xml).unwrap();
+
+        assert_eq!(comic, ComicInfo::parse(xml).unwrap());
+        assert!(diagnostics.is_empty());
+    }
+
     #[test]
     fn test_parse_yes_no() {
         let xml = r#"<?xml version="1.0" encoding="utf-8"?>
@@ -221,6 +291,7 @@ A secret double life begins—one he can't tell anyone about!
                     image_width: -1,
                     image_height: -1,
                     filename: None,
+                    unknown_attributes: Vec::new(),
                 }],
             }),
             ..ComicInfo::default()
@@ -246,6 +317,7 @@ A secret double life begins—one he can't tell anyone about!
                         image_width: -1,
                         image_height: -1,
                         filename: None,
+                        unknown_attributes: Vec::new(),
                     },
                     ComicPageInfo {
                         image: 1,
@@ -257,6 +329,7 @@ A secret double life begins—one he can't tell anyone about!
                         image_width: -1,
                         image_height: -1,
                         filename: None,
+                        unknown_attributes: Vec::new(),
                     },
                 ],
             }),
@@ -267,4 +340,61 @@ A secret double life begins—one he can't tell anyone about!
         assert_eq!(bookmarked.len(), 1);
         assert_eq!(bookmarked[0], "page001.jpg");
     }
+
+    #[test]
+    fn test_parse_preserves_unknown_content() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xmlns:app="urn:example:app">
+  <Title>Unknown Content</Title>
+  <app:Rating source="shelf">4 &amp; a half</app:Rating>
+  <Translator>Jane Doe</Translator>
+  <Languages><Language>en</Language><Language>ja</Language></Languages>
+  <Pages>
+    <Page Image="0" Type="FrontCover" app:Crop="10 &amp; 20" />
+  </Pages>
+  <Archived />
+</ComicInfo>"#;
+
+        let comic = ComicInfo::parse(xml).unwrap();
+        assert_eq!(comic.title, Some("Unknown Content".to_string()));
+        assert_eq!(comic.translator, Some("Jane Doe".to_string()));
+        assert_eq!(
+            comic.unknown_elements,
+            vec![
+                r#"<app:Rating source="shelf">4 &amp; a half</app:Rating>"#.to_string(),
+                "<Languages><Language>en</Language><Language>ja</Language></Languages>".to_string(),
+                "<Archived />".to_string(),
+            ]
+        );
+        assert_eq!(comic.unknown_attributes.len(), 2);
+
+        let pages = &comic.pages.as_ref().unwrap().page;
+        assert_eq!(pages[0].type_, Some(ComicPageType::FrontCover));
+        assert_eq!(
+            pages[0].unknown_attributes,
+            vec![("app:Crop".to_string(), "10 &amp; 20".to_string())]
+        );
+
+        let output = comic.to_xml().unwrap();
+        assert!(output.contains(
+            r#"<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xmlns:app="urn:example:app">"#
+        ));
+        assert!(output.contains(r#"<app:Rating source="shelf">4 &amp; a half</app:Rating>"#));
+        assert!(output.contains("<Archived />"));
+        assert!(output.contains(r#"app:Crop="10 &amp; 20""#));
+        assert_eq!(ComicInfo::parse(&output).unwrap(), comic);
+    }
+
+    #[test]
+    fn test_to_xml_expands_empty_root_for_unknown_elements() {
+        let comic = ComicInfo {
+            unknown_elements: vec!["<Extension>value</Extension>".to_string()],
+            ..ComicInfo::default()
+        };
+
+        let xml = comic.to_xml().unwrap();
+
+        assert!(xml.contains("<Extension>value</Extension>"));
+        assert_eq!(ComicInfo::parse(&xml).unwrap(), comic);
+    }
 }
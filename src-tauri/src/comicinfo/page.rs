@@ -1,4 +1,5 @@
 use super::types::{ComicPageType, default_minus_one, is_false, is_minus_one, is_zero_i64};
+use super::unknown::RawAttributes;
 use serde::Deserialize;
 
 mod comic_page_type_option_serde {
@@ -38,6 +39,8 @@ pub struct ComicPageInfo {
     pub key: String,
     pub bookmark: String,
     pub filename: Option<String>,
+    /// Attributes outside the schema, written back verbatim.
+    pub unknown_attributes: RawAttributes,
 }
 
 impl ComicPageInfo {
@@ -65,6 +68,7 @@ impl ComicPageInfo {
             key: String::new(),
             bookmark,
             filename: None,
+            unknown_attributes: Vec::new(),
         }
     }
 }
@@ -151,6 +155,7 @@ impl<'de> serde::Deserialize<'de> for ComicPageInfo {
             key: strict.key,
             bookmark: strict.bookmark,
             filename: None,
+            unknown_attributes: Vec::new(),
         })
     }
 }
@@ -208,6 +213,7 @@ mod tests {
             key: "k".to_string(),
             bookmark: "b".to_string(),
             filename: None,
+            unknown_attributes: Vec::new(),
         };
 
         let xml = serde_xml_rs::to_string(&page).unwrap();
@@ -260,6 +266,7 @@ mod tests {
             key: "".to_string(),
             bookmark: "book".to_string(),
             filename: None,
+            unknown_attributes: Vec::new(),
         };
 
         let xml = serde_xml_rs::to_string(&page).unwrap();
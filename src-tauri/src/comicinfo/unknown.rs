@@ -0,0 +1,216 @@
+use super::info::ComicInfoParseError;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::name::QName;
+use quick_xml::{Reader, Writer};
+
+/// Child elements of `<ComicInfo>` defined by the ComicInfo v2.1 draft schema.
+pub const COMIC_INFO_ELEMENTS: &[&str] = &[
+    "Title",
+    "Series",
+    "Number",
+    "Count",
+    "Volume",
+    "AlternateSeries",
+    "AlternateNumber",
+    "AlternateCount",
+    "Summary",
+    "Notes",
+    "Year",
+    "Month",
+    "Day",
+    "Writer",
+    "Penciller",
+    "Inker",
+    "Colorist",
+    "Letterer",
+    "CoverArtist",
+    "Editor",
+    "Translator",
+    "Publisher",
+    "Imprint",
+    "Genre",
+    "Tags",
+    "Web",
+    "PageCount",
+    "LanguageISO",
+    "Format",
+    "BlackAndWhite",
+    "Manga",
+    "Characters",
+    "Teams",
+    "Locations",
+    "ScanInformation",
+    "StoryArc",
+    "StoryArcNumber",
+    "SeriesGroup",
+    "AgeRating",
+    "Pages",
+    "CommunityRating",
+    "MainCharacterOrTeam",
+    "Review",
+    "GTIN",
+];
+
+/// Attributes of `<Page>` defined by the ComicInfo v2.1 draft schema.
+pub const PAGE_ATTRIBUTES: &[&str] = &[
+    "Image",
+    "Type",
+    "DoublePage",
+    "ImageSize",
+    "Key",
+    "Bookmark",
+    "ImageWidth",
+    "ImageHeight",
+];
+
+/// Attribute names and values exactly as written in the source document,
+/// values still escaped.
+pub type RawAttributes = Vec<(String, String)>;
+
+/// Everything in a ComicInfo document that falls outside the schema.
+#[derive(Debug, Default)]
+pub struct UnknownContent {
+    pub root_attributes: RawAttributes,
+    pub elements: Vec<String>,
+    /// Unknown attributes of every `<Page>`, in document order.
+    pub page_attributes: Vec<RawAttributes>,
+}
+
+pub(super) fn name_of(start: &BytesStart) -> String {
+    String::from_utf8_lossy(start.name().as_ref()).into_owned()
+}
+
+/// Splits the attributes of `start` into a copy of the element carrying only
+/// the `known` ones, and the remaining attributes.
+fn split_attributes(
+    start: &BytesStart,
+    known: &[&str],
+) -> Result<(BytesStart<'static>, RawAttributes), ComicInfoParseError> {
+    let mut kept = BytesStart::new(name_of(start));
+    let mut unknown = Vec::new();
+
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(quick_xml::Error::from)?;
+        let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+
+        if known.contains(&key.as_str()) {
+            kept.push_attribute(attribute);
+            continue;
+        }
+
+        let value = String::from_utf8_lossy(&attribute.value).into_owned();
+        unknown.push((key, value));
+    }
+
+    Ok((kept, unknown))
+}
+
+/// Copies `start` with the given raw attributes appended, without escaping
+/// their values again.
+pub fn with_raw_attributes<'a>(
+    start: BytesStart<'a>,
+    attributes: &[(String, String)],
+) -> BytesStart<'a> {
+    if attributes.is_empty() {
+        return start;
+    }
+
+    let name_len = start.name().as_ref().len();
+    let original = String::from_utf8_lossy(&start).into_owned();
+    let mut content = original.trim_end().to_string();
+
+    for (key, value) in attributes {
+        let quote = if value.contains('"') { '\'' } else { '"' };
+        content.push_str(&format!(" {}={}{}{}", key, quote, value, quote));
+    }
+
+    if original.ends_with(char::is_whitespace) {
+        content.push(' ');
+    }
+
+    BytesStart::from_content(content, name_len)
+}
+
+/// Removes everything outside the ComicInfo schema from `xml`.
+///
+/// Returns the document with only known elements and attributes, which the
+/// strict deserializer accepts, together with the removed content so it can be
+/// written back out unchanged.
+pub fn split_unknown(xml: &str) -> Result<(String, UnknownContent), ComicInfoParseError> {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Vec::new());
+    let mut unknown = UnknownContent::default();
+    let mut depth: usize = 0;
+    let mut in_pages = false;
+
+    loop {
+        let position = reader.buffer_position();
+        let event = reader.read_event()?;
+
+        let event = match event {
+            Event::Eof => break,
+            Event::Start(e) if depth == 1 => {
+                let name = name_of(&e);
+
+                if !COMIC_INFO_ELEMENTS.contains(&name.as_str()) {
+                    reader.read_to_end(QName(name.as_bytes()))?;
+                    unknown
+                        .elements
+                        .push(xml[position..reader.buffer_position()].to_string());
+                    continue;
+                }
+
+                in_pages = name == "Pages";
+                Event::Start(e)
+            }
+            Event::Empty(e) if depth == 1 => {
+                if !COMIC_INFO_ELEMENTS.contains(&name_of(&e).as_str()) {
+                    unknown
+                        .elements
+                        .push(xml[position..reader.buffer_position()].to_string());
+                    continue;
+                }
+
+                Event::Empty(e)
+            }
+            Event::Start(e) if depth == 0 => {
+                let (root, attributes) = split_attributes(&e, &[])?;
+                unknown.root_attributes = attributes;
+                Event::Start(root)
+            }
+            Event::Empty(e) if depth == 0 => {
+                let (root, attributes) = split_attributes(&e, &[])?;
+                unknown.root_attributes = attributes;
+                Event::Empty(root)
+            }
+            Event::Start(e) if in_pages && depth == 2 && e.name().as_ref() == b"Page" => {
+                let (page, attributes) = split_attributes(&e, PAGE_ATTRIBUTES)?;
+                unknown.page_attributes.push(attributes);
+                Event::Start(page)
+            }
+            Event::Empty(e) if in_pages && depth == 2 && e.name().as_ref() == b"Page" => {
+                let (page, attributes) = split_attributes(&e, PAGE_ATTRIBUTES)?;
+                unknown.page_attributes.push(attributes);
+                Event::Empty(page)
+            }
+            event => event,
+        };
+
+        match &event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => {
+                depth = depth.saturating_sub(1);
+
+                if depth == 1 {
+                    in_pages = false;
+                }
+            }
+            _ => {}
+        }
+
+        writer.write_event(event)?;
+    }
+
+    let known = String::from_utf8(writer.into_inner()).map_err(|e| e.utf8_error())?;
+    Ok((known, unknown))
+}
@@ -0,0 +1,480 @@
+use super::info::ComicInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationSeverity {
+    /// The value contradicts the schema; saving is refused.
+    Error,
+    /// The value is suspicious but can be saved.
+    Warning,
+}
+
+/// A single problem found by [`ComicInfo::validate`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    pub severity: ValidationSeverity,
+    /// Path to the offending value, e.g. `Month` or `Pages.Page[2].Image`.
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+struct Issues(Vec<ValidationIssue>);
+
+impl Issues {
+    fn error(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.push(ValidationSeverity::Error, field.into(), message.into());
+    }
+
+    fn warning(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.push(ValidationSeverity::Warning, field.into(), message.into());
+    }
+
+    fn push(&mut self, severity: ValidationSeverity, field: String, message: String) {
+        self.0.push(ValidationIssue {
+            severity,
+            field,
+            message,
+        });
+    }
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Days in `month`, assuming a leap year when the year is unknown.
+fn days_in_month(year: Option<i32>, month: i32) -> i32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year.is_some_and(|y| !is_leap_year(y)) => 28,
+        2 => 29,
+        _ => 31,
+    }
+}
+
+fn check_date(comic_info: &ComicInfo, issues: &mut Issues) {
+    let month_known = comic_info.month != -1;
+
+    if month_known && !(1..=12).contains(&comic_info.month) {
+        issues.error(
+            "Month",
+            format!("{} is not a month between 1 and 12", comic_info.month),
+        );
+    }
+
+    if comic_info.day == -1 {
+        return;
+    }
+
+    let year = (comic_info.year != -1).then_some(comic_info.year);
+    let max_day = if (1..=12).contains(&comic_info.month) {
+        days_in_month(year, comic_info.month)
+    } else {
+        31
+    };
+
+    if !(1..=max_day).contains(&comic_info.day) {
+        issues.error(
+            "Day",
+            format!("{} is not a valid day for this month", comic_info.day),
+        );
+    }
+}
+
+fn all_alpha(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+fn all_alphanumeric(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Checks the syntax of a BCP 47 language tag. Plain ISO 639 codes are valid
+/// tags consisting of only the language subtag.
+fn is_language_tag(tag: &str) -> bool {
+    let subtags: Vec<&str> = tag.split('-').collect();
+
+    if subtags
+        .iter()
+        .any(|s| s.is_empty() || s.len() > 8 || !all_alphanumeric(s))
+    {
+        return false;
+    }
+
+    if subtags[0].eq_ignore_ascii_case("x") {
+        return subtags.len() > 1;
+    }
+
+    let language = subtags[0];
+
+    if !all_alpha(language) || !(2..=8).contains(&language.len()) || language.len() == 4 {
+        return false;
+    }
+
+    let mut rest = subtags[1..].iter().peekable();
+    let mut extlangs = 0;
+
+    while extlangs < 3 && language.len() <= 3 {
+        match rest.peek() {
+            Some(s) if s.len() == 3 && all_alpha(s) => {
+                rest.next();
+                extlangs += 1;
+            }
+            _ => break,
+        }
+    }
+
+    if rest.peek().is_some_and(|s| s.len() == 4 && all_alpha(s)) {
+        rest.next();
+    }
+
+    if rest.peek().is_some_and(|s| {
+        (s.len() == 2 && all_alpha(s)) || (s.len() == 3 && s.chars().all(|c| c.is_ascii_digit()))
+    }) {
+        rest.next();
+    }
+
+    while let Some(s) = rest.peek() {
+        let is_variant =
+            s.len() >= 5 || (s.len() == 4 && s.starts_with(|c: char| c.is_ascii_digit()));
+
+        if !is_variant {
+            break;
+        }
+
+        rest.next();
+    }
+
+    while let Some(singleton) = rest.next() {
+        if singleton.len() != 1 {
+            return false;
+        }
+
+        if singleton.eq_ignore_ascii_case("x") {
+            return rest.next().is_some();
+        }
+
+        let mut extension_subtags = 0;
+
+        while rest.peek().is_some_and(|s| s.len() >= 2) {
+            rest.next();
+            extension_subtags += 1;
+        }
+
+        if extension_subtags == 0 {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Checks the check digit of a GTIN-8, -12, -13 or -14, or an ISBN-10.
+/// Spaces and hyphens are ignored.
+fn is_valid_gtin(gtin: &str) -> bool {
+    let code: Vec<char> = gtin.chars().filter(|c| *c != ' ' && *c != '-').collect();
+
+    if code.len() == 10 {
+        return is_valid_isbn10(&code);
+    }
+
+    if ![8, 12, 13, 14].contains(&code.len()) || !code.iter().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+
+    let digits: Vec<u32> = code.iter().filter_map(|c| c.to_digit(10)).collect();
+    let (check, body) = digits.split_last().unwrap();
+    let sum: u32 = body
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { d * 3 } else { *d })
+        .sum();
+
+    (10 - sum % 10) % 10 == *check
+}
+
+fn is_valid_isbn10(code: &[char]) -> bool {
+    let mut sum = 0;
+
+    for (i, c) in code.iter().enumerate() {
+        let value = match c {
+            'X' | 'x' if i == 9 => 10,
+            _ => match c.to_digit(10) {
+                Some(d) => d,
+                None => return false,
+            },
+        };
+        sum += value * (10 - i as u32);
+    }
+
+    sum % 11 == 0
+}
+
+fn is_web_url(value: &str) -> bool {
+    url::Url::parse(value)
+        .map(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+        .unwrap_or(false)
+}
+
+fn check_identifiers(comic_info: &ComicInfo, issues: &mut Issues) {
+    if let Some(language) = comic_info.language_iso.as_deref() {
+        if !is_language_tag(language.trim()) {
+            issues.warning(
+                "LanguageISO",
+                format!(
+                    "'{}' is not a valid ISO 639 or BCP 47 language code",
+                    language
+                ),
+            );
+        }
+    }
+
+    if let Some(gtin) = comic_info.gtin.as_deref() {
+        if !is_valid_gtin(gtin.trim()) {
+            issues.warning(
+                "GTIN",
+                format!("'{}' is not a GTIN or ISBN with a valid check digit", gtin),
+            );
+        }
+    }
+
+    if let Some(web) = comic_info.web.as_deref() {
+        for url in web.split_whitespace().filter(|url| !is_web_url(url)) {
+            issues.warning("Web", format!("'{}' is not a valid http(s) URL", url));
+        }
+    }
+}
+
+fn check_rating(comic_info: &ComicInfo, issues: &mut Issues) {
+    let Some(rating) = comic_info.community_rating else {
+        return;
+    };
+
+    if !(0.0..=5.0).contains(&rating) {
+        issues.error(
+            "CommunityRating",
+            format!("{} is not a rating between 0 and 5", rating),
+        );
+    }
+}
+
+fn check_pages(comic_info: &ComicInfo, image_count: Option<usize>, issues: &mut Issues) {
+    if let Some(count) = image_count {
+        if comic_info.page_count != 0 && comic_info.page_count as usize != count {
+            issues.warning(
+                "PageCount",
+                format!(
+                    "{} does not match the {} images in the archive",
+                    comic_info.page_count, count
+                ),
+            );
+        }
+    }
+
+    let Some(pages) = &comic_info.pages else {
+        return;
+    };
+
+    let mut seen = HashSet::new();
+
+    for (index, page) in pages.page.iter().enumerate() {
+        let field = format!("Pages.Page[{}].Image", index);
+
+        if page.image < 0 {
+            issues.error(field, "Page image index must be non-negative");
+            continue;
+        }
+
+        if image_count.is_some_and(|count| page.image as usize >= count) {
+            issues.error(
+                field.clone(),
+                format!(
+                    "Image {} is beyond the {} images in the archive",
+                    page.image,
+                    image_count.unwrap_or_default()
+                ),
+            );
+        }
+
+        if !seen.insert(page.image) {
+            issues.warning(
+                field,
+                format!("Image {} is described by more than one page", page.image),
+            );
+        }
+    }
+}
+
+/// Runs every semantic rule and returns all problems found.
+///
+/// `image_count` is the number of images in the archive; rules that compare
+/// against it are skipped when it is `None`.
+pub fn validation_issues(
+    comic_info: &ComicInfo,
+    image_count: Option<usize>,
+) -> Vec<ValidationIssue> {
+    let mut issues = Issues(Vec::new());
+
+    check_date(comic_info, &mut issues);
+    check_identifiers(comic_info, &mut issues);
+    check_rating(comic_info, &mut issues);
+    check_pages(comic_info, image_count, &mut issues);
+
+    issues.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comicinfo::{ComicPageInfo, ComicPageType, Pages};
+
+    fn page(image: i32) -> ComicPageInfo {
+        ComicPageInfo::from_page_settings(ComicPageType::Story, false, String::new(), image)
+    }
+
+    fn fields(issues: &[ValidationIssue]) -> Vec<(&str, ValidationSeverity)> {
+        issues
+            .iter()
+            .map(|issue| (issue.field.as_str(), issue.severity))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_comic_info_has_no_issues() {
+        let comic = ComicInfo {
+            year: 2024,
+            month: 2,
+            day: 29,
+            language_iso: Some("pt-BR".to_string()),
+            gtin: Some("978-0-306-40615-7".to_string()),
+            web: Some("https://example.com/a http://example.org".to_string()),
+            community_rating: Some(4.5),
+            page_count: 2,
+            pages: Some(Pages {
+                page: vec![page(0), page(1)],
+            }),
+            ..ComicInfo::default()
+        };
+
+        assert_eq!(validation_issues(&comic, Some(2)), vec![]);
+        assert_eq!(comic.validate(Some(2)).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_reports_every_issue_at_once() {
+        let comic = ComicInfo {
+            month: 13,
+            day: 32,
+            language_iso: Some("en_GB".to_string()),
+            gtin: Some("9780306406158".to_string()),
+            web: Some("https://example.com not-a-url".to_string()),
+            community_rating: Some(6.5),
+            page_count: 5,
+            pages: Some(Pages {
+                page: vec![page(0), page(0), page(3), page(-1)],
+            }),
+            ..ComicInfo::default()
+        };
+
+        let issues = validation_issues(&comic, Some(2));
+
+        assert_eq!(
+            fields(&issues),
+            vec![
+                ("Month", ValidationSeverity::Error),
+                ("Day", ValidationSeverity::Error),
+                ("LanguageISO", ValidationSeverity::Warning),
+                ("GTIN", ValidationSeverity::Warning),
+                ("Web", ValidationSeverity::Warning),
+                ("CommunityRating", ValidationSeverity::Error),
+                ("PageCount", ValidationSeverity::Warning),
+                ("Pages.Page[1].Image", ValidationSeverity::Warning),
+                ("Pages.Page[2].Image", ValidationSeverity::Error),
+                ("Pages.Page[3].Image", ValidationSeverity::Error),
+            ]
+        );
+
+        let err = comic.validate(Some(2)).unwrap_err();
+        assert!(
+            matches!(err, crate::comicinfo::info::ComicInfoError::Validate(ref all) if all.len() == 10)
+        );
+    }
+
+    #[test]
+    fn test_warnings_do_not_fail_validation() {
+        let comic = ComicInfo {
+            language_iso: Some("en_US".to_string()),
+            ..ComicInfo::default()
+        };
+
+        let warnings = comic.validate(None).unwrap();
+
+        assert_eq!(
+            fields(&warnings),
+            vec![("LanguageISO", ValidationSeverity::Warning)]
+        );
+    }
+
+    #[test]
+    fn test_day_depends_on_month_and_year() {
+        let day_issues = |year, month, day| {
+            let comic = ComicInfo {
+                year,
+                month,
+                day,
+                ..ComicInfo::default()
+            };
+            validation_issues(&comic, None).len()
+        };
+
+        assert_eq!(day_issues(2023, 2, 29), 1);
+        assert_eq!(day_issues(2024, 2, 29), 0);
+        assert_eq!(day_issues(-1, 2, 29), 0);
+        assert_eq!(day_issues(2024, 4, 31), 1);
+        assert_eq!(day_issues(2024, -1, 31), 0);
+        assert_eq!(day_issues(2024, 1, 0), 1);
+    }
+
+    #[test]
+    fn test_language_tags() {
+        for valid in [
+            "en",
+            "ja",
+            "EN",
+            "fra",
+            "zh-Hant-TW",
+            "es-419",
+            "de-CH-1996",
+            "x-custom",
+        ] {
+            assert!(is_language_tag(valid), "{valid} should be valid");
+        }
+
+        for invalid in [
+            "", "e", "english-", "en_US", "en--US", "abcd", "en-x", "123",
+        ] {
+            assert!(!is_language_tag(invalid), "{invalid} should be invalid");
+        }
+    }
+
+    #[test]
+    fn test_gtin_check_digits() {
+        assert!(is_valid_gtin("9780306406157"));
+        assert!(is_valid_gtin("96385074"));
+        assert!(is_valid_gtin("036000291452"));
+        assert!(is_valid_gtin("0-306-40615-2"));
+        assert!(is_valid_gtin("080442957X"));
+        assert!(!is_valid_gtin("9780306406158"));
+        assert!(!is_valid_gtin("978030640615"));
+        assert!(!is_valid_gtin("97803064061A7"));
+    }
+}
@@ -0,0 +1,20 @@
+use super::epub::{read_epub_metadata, save_epub_metadata_impl};
+use super::metadata::EpubMetadata;
+use crate::archive::fingerprint::{ArchiveFingerprint, Fingerprinted, write_unchanged};
+use crate::archive::types::{ErrorResponse, ToErrorResponse};
+
+#[tauri::command]
+pub fn get_epub_metadata(path: String) -> Result<EpubMetadata, ErrorResponse> {
+    read_epub_metadata(&path).map_err(|e| e.to_error_response())
+}
+
+#[tauri::command]
+pub fn save_epub_metadata(
+    path: String,
+    metadata: EpubMetadata,
+    expected_fingerprint: ArchiveFingerprint,
+) -> Result<Fingerprinted<EpubMetadata>, ErrorResponse> {
+    write_unchanged(&path, &expected_fingerprint, || {
+        save_epub_metadata_impl(path.clone(), metadata)
+    })
+}
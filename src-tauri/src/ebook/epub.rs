@@ -0,0 +1,915 @@
+use super::metadata::{DC_NAMESPACE, EpubMetadata};
+use crate::archive::atomic_write::AtomicFile;
+use crate::archive::types::{ErrorResponse, ErrorResponseType, ToErrorResponse};
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
+use zip::write::FileOptions;
+
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+const PACKAGE_MEDIA_TYPE: &str = "application/oebps-package+xml";
+
+#[derive(Debug)]
+pub enum EpubError {
+    Io(std::io::Error),
+    Zip(zip::result::ZipError),
+    MissingContainer,
+    MissingPackageDocument,
+    Xml(String),
+}
+
+impl fmt::Display for EpubError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpubError::Io(err) => write!(f, "IO error: {}", err),
+            EpubError::Zip(err) => write!(f, "Zip error: {}", err),
+            EpubError::MissingContainer => {
+                write!(f, "Not an EPUB: {} is missing", CONTAINER_PATH)
+            }
+            EpubError::MissingPackageDocument => {
+                write!(f, "The EPUB container does not point to a package document")
+            }
+            EpubError::Xml(err) => write!(f, "Invalid EPUB XML: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for EpubError {}
+
+impl ToErrorResponse for EpubError {
+    fn to_error_response(&self) -> ErrorResponse {
+        match self {
+            EpubError::Io(err) => err.to_error_response(),
+            EpubError::MissingContainer | EpubError::MissingPackageDocument => {
+                ErrorResponse::new(ErrorResponseType::UnsupportedFormat, self.to_string())
+            }
+            EpubError::Zip(_) | EpubError::Xml(_) => {
+                ErrorResponse::new(ErrorResponseType::Other, self.to_string())
+            }
+        }
+    }
+}
+
+fn xml_error(err: impl fmt::Display) -> EpubError {
+    EpubError::Xml(err.to_string())
+}
+
+fn open_epub(path: &str) -> Result<zip::ZipArchive<fs::File>, EpubError> {
+    let file = fs::File::open(path).map_err(EpubError::Io)?;
+    zip::ZipArchive::new(file).map_err(EpubError::Zip)
+}
+
+fn read_entry<R: Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    name: &str,
+) -> Result<String, EpubError> {
+    let mut entry = archive.by_name(name).map_err(EpubError::Zip)?;
+    let mut content = String::new();
+    entry.read_to_string(&mut content).map_err(EpubError::Io)?;
+    Ok(content)
+}
+
+fn attribute(e: &BytesStart, name: &str) -> Result<Option<String>, EpubError> {
+    let Some(attr) = e.try_get_attribute(name).map_err(xml_error)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(attr.unescape_value().map_err(xml_error)?.into_owned()))
+}
+
+/// Returns the package document path from `META-INF/container.xml`,
+/// preferring the first rootfile with the OPF media type.
+pub fn find_package_path(container_xml: &str) -> Result<String, EpubError> {
+    let mut reader = Reader::from_str(container_xml);
+    let mut fallback = None;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Eof => break,
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"rootfile" => {
+                let Some(full_path) = attribute(&e, "full-path")? else {
+                    continue;
+                };
+
+                if attribute(&e, "media-type")?.as_deref() == Some(PACKAGE_MEDIA_TYPE) {
+                    return Ok(full_path);
+                }
+
+                fallback.get_or_insert(full_path);
+            }
+            _ => {}
+        }
+    }
+
+    fallback.ok_or(EpubError::MissingPackageDocument)
+}
+
+fn locate_package<R: Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+) -> Result<String, EpubError> {
+    let container_xml = read_entry(archive, CONTAINER_PATH).map_err(|e| match e {
+        EpubError::Zip(zip::result::ZipError::FileNotFound) => EpubError::MissingContainer,
+        e => e,
+    })?;
+
+    find_package_path(&container_xml)
+}
+
+/// Prefixes bound to the Dublin Core namespace on this element.
+fn declared_dc_prefixes(e: &BytesStart) -> Result<Vec<String>, EpubError> {
+    let mut prefixes = Vec::new();
+
+    for attr in e.attributes() {
+        let attr = attr.map_err(xml_error)?;
+        let Some(prefix) = attr.key.as_ref().strip_prefix(b"xmlns:") else {
+            continue;
+        };
+
+        if attr.value.as_ref() == DC_NAMESPACE.as_bytes() {
+            prefixes.push(String::from_utf8_lossy(prefix).into_owned());
+        }
+    }
+
+    Ok(prefixes)
+}
+
+/// The local name of a Dublin Core element, or `None` for anything else.
+fn dc_element_name(e: &BytesStart, dc_prefixes: &[String]) -> Option<String> {
+    let name = e.name();
+    let prefix = name.prefix()?;
+
+    if !dc_prefixes.iter().any(|p| p.as_bytes() == prefix.as_ref()) {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(name.local_name().as_ref()).into_owned())
+}
+
+fn is_metadata(e: &BytesStart) -> bool {
+    e.local_name().as_ref() == b"metadata"
+}
+
+/// A Dublin Core element of the package metadata.
+struct DcElement {
+    name: String,
+    id: Option<String>,
+    text: String,
+}
+
+/// Returns the package's unique identifier and its Dublin Core elements in
+/// document order, empty ones included.
+fn read_dc_elements(opf: &str) -> Result<(Option<String>, Vec<DcElement>), EpubError> {
+    let mut reader = Reader::from_str(opf);
+    let mut dc_prefixes = Vec::new();
+    let mut in_metadata = false;
+    let mut unique_identifier = None;
+    let mut elements = Vec::new();
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Eof => break,
+            Event::Start(e) => {
+                dc_prefixes.extend(declared_dc_prefixes(&e)?);
+
+                if e.local_name().as_ref() == b"package" {
+                    unique_identifier = attribute(&e, "unique-identifier")?;
+                    continue;
+                }
+
+                if is_metadata(&e) {
+                    in_metadata = true;
+                    continue;
+                }
+
+                if !in_metadata {
+                    continue;
+                }
+
+                let Some(name) = dc_element_name(&e, &dc_prefixes) else {
+                    continue;
+                };
+
+                let raw = reader.read_text(e.name()).map_err(xml_error)?;
+                let text = quick_xml::escape::unescape(&raw).map_err(xml_error)?;
+
+                elements.push(DcElement {
+                    name,
+                    id: attribute(&e, "id")?,
+                    text: text.trim().to_string(),
+                });
+            }
+            Event::Empty(e) if in_metadata => {
+                dc_prefixes.extend(declared_dc_prefixes(&e)?);
+
+                if let Some(name) = dc_element_name(&e, &dc_prefixes) {
+                    elements.push(DcElement {
+                        name,
+                        id: attribute(&e, "id")?,
+                        text: String::new(),
+                    });
+                }
+            }
+            Event::End(e) if e.local_name().as_ref() == b"metadata" => in_metadata = false,
+            _ => {}
+        }
+    }
+
+    Ok((unique_identifier, elements))
+}
+
+fn metadata_of(elements: &[DcElement]) -> EpubMetadata {
+    let mut values: HashMap<String, Vec<String>> = HashMap::new();
+
+    for element in elements.iter().filter(|e| !e.text.is_empty()) {
+        values
+            .entry(element.name.clone())
+            .or_default()
+            .push(element.text.clone());
+    }
+
+    EpubMetadata::from_elements(values)
+}
+
+/// Reads the Dublin Core elements from an OPF package document.
+pub fn parse_package_metadata(opf: &str) -> Result<EpubMetadata, EpubError> {
+    let (_, elements) = read_dc_elements(opf)?;
+    Ok(metadata_of(&elements))
+}
+
+#[derive(Debug)]
+enum Alignment {
+    Same(usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/// Aligns the current values of a field with the new ones, keeping as many
+/// current values as possible in place. Indices are offset by `from`.
+fn align(current: &[String], new: &[String], from: (usize, usize)) -> Vec<Alignment> {
+    // lengths[i][j] is the longest common run of current[i..] and new[j..].
+    let mut lengths = vec![vec![0; new.len() + 1]; current.len() + 1];
+    for i in (0..current.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if current[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut alignment = Vec::new();
+
+    while i < current.len() && j < new.len() {
+        if current[i] == new[j] {
+            alignment.push(Alignment::Same(from.0 + i));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            alignment.push(Alignment::Removed(from.0 + i));
+            i += 1;
+        } else {
+            alignment.push(Alignment::Added(from.1 + j));
+            j += 1;
+        }
+    }
+
+    alignment.extend((i..current.len()).map(|i| Alignment::Removed(from.0 + i)));
+    alignment.extend((j..new.len()).map(|j| Alignment::Added(from.1 + j)));
+    alignment
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum Rewrite {
+    #[default]
+    Keep,
+    Replace(String),
+    Remove,
+}
+
+/// What happens to one existing Dublin Core element on save.
+#[derive(Debug, Default)]
+struct ElementPlan {
+    /// New values written as elements just before this one.
+    insert_before: Vec<String>,
+    rewrite: Rewrite,
+}
+
+/// Plans the elements a field's current values were read from, returning one
+/// plan per element and the values to append after them.
+///
+/// `pinned` is the element that must keep its value while that value is still
+/// wanted, and must never be removed.
+fn plan_field(
+    current: &[String],
+    new: &[String],
+    pinned: Option<usize>,
+) -> (Vec<ElementPlan>, Vec<String>) {
+    let anchor = pinned.and_then(|i| Some((i, new.iter().position(|value| *value == current[i])?)));
+    let alignment = match anchor {
+        Some((i, j)) => {
+            let mut alignment = align(&current[..i], &new[..j], (0, 0));
+            alignment.push(Alignment::Same(i));
+            alignment.extend(align(&current[i + 1..], &new[j + 1..], (i + 1, j + 1)));
+            alignment
+        }
+        None => align(current, new, (0, 0)),
+    };
+
+    let mut plans: Vec<ElementPlan> = current.iter().map(|_| ElementPlan::default()).collect();
+    let mut appended = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+
+    for step in alignment.into_iter().map(Some).chain([None]) {
+        match step {
+            Some(Alignment::Removed(i)) => removed.push(i),
+            Some(Alignment::Added(j)) => added.push(new[j].clone()),
+            // A kept element, or the end, closes the run of changes before it.
+            Some(Alignment::Same(_)) | None => {
+                let mut values = added.drain(..);
+
+                for i in removed.drain(..) {
+                    plans[i].rewrite = match values.next() {
+                        Some(value) => Rewrite::Replace(value),
+                        None if Some(i) == pinned => Rewrite::Keep,
+                        None => Rewrite::Remove,
+                    };
+                }
+
+                let rest: Vec<String> = values.collect();
+                match step {
+                    Some(Alignment::Same(i)) => plans[i].insert_before = rest,
+                    _ => appended.extend(rest),
+                }
+            }
+        }
+    }
+
+    (plans, appended)
+}
+
+struct MetadataRewriter {
+    /// The plan of every Dublin Core element, by local name in document order.
+    plans: HashMap<String, VecDeque<ElementPlan>>,
+    pending: Vec<(&'static str, VecDeque<String>)>,
+    /// Ids of removed elements, whose `<meta refines>` go with them.
+    removed_ids: HashSet<String>,
+    dc_prefixes: Vec<String>,
+    indent: String,
+}
+
+impl MetadataRewriter {
+    /// Plans a save that only touches the fields `metadata` changes. Elements
+    /// of other fields, later values of single-valued fields such as an EPUB3
+    /// subtitle, and the package's unique identifier are left as they are.
+    fn new(opf: &str, metadata: &EpubMetadata) -> Result<Self, EpubError> {
+        let (unique_identifier, elements) = read_dc_elements(opf)?;
+        let current = metadata_of(&elements);
+        let mut rewriter = MetadataRewriter {
+            plans: HashMap::new(),
+            pending: Vec::new(),
+            removed_ids: HashSet::new(),
+            dc_prefixes: Vec::new(),
+            indent: "\n  ".to_string(),
+        };
+
+        for ((name, current_values), (_, new_values)) in current
+            .to_elements()
+            .into_iter()
+            .zip(metadata.to_elements())
+        {
+            if current_values == new_values {
+                continue;
+            }
+
+            let occurrences: Vec<&DcElement> = elements.iter().filter(|e| e.name == name).collect();
+            let sources: Vec<usize> = (0..occurrences.len())
+                .filter(|&i| !occurrences[i].text.is_empty())
+                .take(current_values.len())
+                .collect();
+            let pinned = sources.iter().position(|&i| {
+                name == "identifier"
+                    && unique_identifier.is_some()
+                    && occurrences[i].id == unique_identifier
+            });
+
+            let (source_plans, appended) = plan_field(&current_values, &new_values, pinned);
+            let mut plans: Vec<ElementPlan> =
+                occurrences.iter().map(|_| ElementPlan::default()).collect();
+
+            for (&i, plan) in sources.iter().zip(source_plans) {
+                if plan.rewrite == Rewrite::Remove {
+                    rewriter.removed_ids.extend(occurrences[i].id.clone());
+                }
+                plans[i] = plan;
+            }
+
+            rewriter.plans.insert(name.to_string(), plans.into());
+            rewriter.pending.push((name, appended.into()));
+        }
+
+        Ok(rewriter)
+    }
+
+    fn next_plan(&mut self, element: &str) -> ElementPlan {
+        self.plans
+            .get_mut(element)
+            .and_then(VecDeque::pop_front)
+            .unwrap_or_default()
+    }
+
+    fn refines_removed(&self, e: &BytesStart) -> Result<bool, EpubError> {
+        if e.local_name().as_ref() != b"meta" {
+            return Ok(false);
+        }
+
+        Ok(attribute(e, "refines")?
+            .as_deref()
+            .and_then(|refines| refines.strip_prefix('#'))
+            .is_some_and(|id| self.removed_ids.contains(id)))
+    }
+
+    /// Declares the Dublin Core namespace on `<metadata>` when the document
+    /// has not bound it yet, so appended elements stay valid.
+    fn metadata_start(&mut self, e: BytesStart) -> Result<BytesStart<'static>, EpubError> {
+        self.dc_prefixes.extend(declared_dc_prefixes(&e)?);
+
+        let mut start = e.into_owned();
+        if self.dc_prefixes.is_empty() {
+            start.push_attribute(("xmlns:dc", DC_NAMESPACE));
+            self.dc_prefixes.push("dc".to_string());
+        }
+
+        Ok(start)
+    }
+
+    /// Writes the element `e` stands for according to its plan.
+    fn write_element<W: Write>(
+        &mut self,
+        reader: &mut Reader<&[u8]>,
+        writer: &mut Writer<W>,
+        e: BytesStart,
+        element: &str,
+        empty: bool,
+    ) -> Result<(), EpubError> {
+        let plan = self.next_plan(element);
+        let tag = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+
+        for value in &plan.insert_before {
+            write_text_element(writer, BytesStart::new(tag.as_str()), value)?;
+            writer
+                .write_event(Event::Text(BytesText::new(&self.indent)))
+                .map_err(xml_error)?;
+        }
+
+        if !empty && plan.rewrite != Rewrite::Keep {
+            reader.read_to_end(e.name()).map_err(xml_error)?;
+        }
+
+        match plan.rewrite {
+            Rewrite::Keep if empty => writer.write_event(Event::Empty(e)).map_err(xml_error),
+            Rewrite::Keep => writer.write_event(Event::Start(e)).map_err(xml_error),
+            Rewrite::Replace(value) => write_text_element(writer, e, &value),
+            Rewrite::Remove => Ok(()),
+        }
+    }
+
+    fn write_remaining<W: Write>(&mut self, writer: &mut Writer<W>) -> Result<(), EpubError> {
+        let prefix = self
+            .dc_prefixes
+            .first()
+            .cloned()
+            .unwrap_or_else(|| "dc".to_string());
+
+        for (element, values) in &mut self.pending {
+            for value in values.drain(..) {
+                let tag = format!("{}:{}", prefix, element);
+                writer
+                    .write_event(Event::Text(BytesText::new("  ")))
+                    .map_err(xml_error)?;
+                write_text_element(writer, BytesStart::new(tag), &value)?;
+                writer
+                    .write_event(Event::Text(BytesText::new("\n  ")))
+                    .map_err(xml_error)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_text_element<W: Write>(
+    writer: &mut Writer<W>,
+    start: BytesStart,
+    value: &str,
+) -> Result<(), EpubError> {
+    let end = start.to_end().into_owned();
+
+    writer.write_event(Event::Start(start)).map_err(xml_error)?;
+    writer
+        .write_event(Event::Text(BytesText::new(value)))
+        .map_err(xml_error)?;
+    writer.write_event(Event::End(end)).map_err(xml_error)
+}
+
+/// Rewrites the Dublin Core elements of an OPF package document.
+///
+/// Only fields whose values changed are touched. Their elements keep their
+/// attributes and position where a value stays, get their text replaced where
+/// a value changed, and are dropped together with the `<meta refines>` that
+/// point at them where a value was removed. New values are written next to
+/// the field's other elements, or at the end of `<metadata>`. Everything else
+/// in the document is copied verbatim.
+pub fn update_package_metadata(opf: &str, metadata: &EpubMetadata) -> Result<String, EpubError> {
+    let mut reader = Reader::from_str(opf);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut rewriter = MetadataRewriter::new(opf, metadata)?;
+    let mut in_metadata = false;
+
+    loop {
+        let event = reader.read_event().map_err(xml_error)?;
+
+        match event {
+            Event::Eof => break,
+            Event::Start(e) if e.local_name().as_ref() == b"package" => {
+                rewriter.dc_prefixes.extend(declared_dc_prefixes(&e)?);
+                writer.write_event(Event::Start(e)).map_err(xml_error)?;
+            }
+            Event::Start(e) if is_metadata(&e) => {
+                let start = rewriter.metadata_start(e)?;
+                writer.write_event(Event::Start(start)).map_err(xml_error)?;
+                in_metadata = true;
+            }
+            Event::Empty(e) if is_metadata(&e) => {
+                let start = rewriter.metadata_start(e)?;
+                let end = start.to_end().into_owned();
+                writer.write_event(Event::Start(start)).map_err(xml_error)?;
+                rewriter.write_remaining(&mut writer)?;
+                writer.write_event(Event::End(end)).map_err(xml_error)?;
+            }
+            Event::End(e) if in_metadata && e.local_name().as_ref() == b"metadata" => {
+                rewriter.write_remaining(&mut writer)?;
+                writer.write_event(Event::End(e)).map_err(xml_error)?;
+                in_metadata = false;
+            }
+            Event::Text(e) if in_metadata => {
+                if e.iter().all(u8::is_ascii_whitespace) {
+                    rewriter.indent = String::from_utf8_lossy(&e).into_owned();
+                }
+                writer.write_event(Event::Text(e)).map_err(xml_error)?;
+            }
+            Event::Start(e) if in_metadata => {
+                rewriter.dc_prefixes.extend(declared_dc_prefixes(&e)?);
+
+                if rewriter.refines_removed(&e)? {
+                    reader.read_to_end(e.name()).map_err(xml_error)?;
+                    continue;
+                }
+
+                match dc_element_name(&e, &rewriter.dc_prefixes) {
+                    Some(element) => {
+                        rewriter.write_element(&mut reader, &mut writer, e, &element, false)?
+                    }
+                    None => writer.write_event(Event::Start(e)).map_err(xml_error)?,
+                }
+            }
+            Event::Empty(e) if in_metadata => {
+                rewriter.dc_prefixes.extend(declared_dc_prefixes(&e)?);
+
+                if rewriter.refines_removed(&e)? {
+                    continue;
+                }
+
+                match dc_element_name(&e, &rewriter.dc_prefixes) {
+                    Some(element) => {
+                        rewriter.write_element(&mut reader, &mut writer, e, &element, true)?
+                    }
+                    None => writer.write_event(Event::Empty(e)).map_err(xml_error)?,
+                }
+            }
+            event => writer.write_event(event).map_err(xml_error)?,
+        }
+    }
+
+    String::from_utf8(writer.into_inner().into_inner()).map_err(xml_error)
+}
+
+pub fn read_epub_metadata(path: &str) -> Result<EpubMetadata, EpubError> {
+    let mut archive = open_epub(path)?;
+    let package_path = locate_package(&mut archive)?;
+    let opf = read_entry(&mut archive, &package_path)?;
+
+    parse_package_metadata(&opf)
+}
+
+/// Replaces the package document inside the EPUB, keeping every other entry
+/// byte for byte and in its original order so `mimetype` stays first.
+fn write_package_document(path: &str, package_path: &str, opf: &str) -> Result<(), EpubError> {
+    let mut output = AtomicFile::create(path).map_err(EpubError::Io)?;
+
+    {
+        let original_file = fs::File::open(path).map_err(EpubError::Io)?;
+        let mut original_archive =
+            zip::ZipArchive::new(BufReader::new(original_file)).map_err(EpubError::Zip)?;
+
+        let mut new_archive = zip::ZipWriter::new(BufWriter::new(output.as_file_mut()));
+
+        for i in 0..original_archive.len() {
+            let file = original_archive.by_index_raw(i).map_err(EpubError::Zip)?;
+
+            if file.name() != package_path {
+                new_archive.raw_copy_file(file).map_err(EpubError::Zip)?;
+                continue;
+            }
+
+            new_archive
+                .start_file(package_path, FileOptions::<()>::default())
+                .map_err(EpubError::Zip)?;
+            new_archive
+                .write_all(opf.as_bytes())
+                .map_err(EpubError::Io)?;
+        }
+
+        new_archive
+            .finish()
+            .map_err(EpubError::Zip)?
+            .flush()
+            .map_err(EpubError::Io)?;
+    }
+
+    output.commit().map_err(EpubError::Io)
+}
+
+/// Business logic for saving EPUB metadata
+pub fn save_epub_metadata_impl(
+    path: String,
+    metadata: EpubMetadata,
+) -> Result<EpubMetadata, ErrorResponse> {
+    let mut archive = open_epub(&path).map_err(|e| e.to_error_response())?;
+    let package_path = locate_package(&mut archive).map_err(|e| e.to_error_response())?;
+    let opf = read_entry(&mut archive, &package_path).map_err(|e| e.to_error_response())?;
+    drop(archive);
+
+    let updated_opf =
+        update_package_metadata(&opf, &metadata).map_err(|e| e.to_error_response())?;
+    write_package_document(&path, &package_path, &updated_opf)
+        .map_err(|e| e.to_error_response())?;
+
+    parse_package_metadata(&updated_opf).map_err(|e| e.to_error_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zip::CompressionMethod;
+
+    const CONTAINER_XML: &str = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+
+    const PACKAGE_OPF: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="BookId" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="BookId">urn:uuid:1234</dc:identifier>
+    <dc:title>Old Title</dc:title>
+    <dc:creator id="author">First Author</dc:creator>
+    <dc:creator>Second Author</dc:creator>
+    <dc:language>en</dc:language>
+    <dc:description>Fish &amp; Chips</dc:description>
+    <meta property="dcterms:modified">2024-01-01T00:00:00Z</meta>
+  </metadata>
+  <manifest>
+    <item id="chapter1" href="chapter1.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+</package>"#;
+
+    fn test_path(name: &str) -> String {
+        let mut dir = std::env::temp_dir();
+        dir.push("ebook_manager_tests");
+        std::fs::create_dir_all(&dir).expect("create test dir");
+        dir.push(name);
+        dir.to_str().unwrap().to_string()
+    }
+
+    fn create_epub(path: &str, entries: &[(&str, &str)]) {
+        let file = fs::File::create(path).expect("create epub");
+        let mut zip = zip::ZipWriter::new(file);
+        let options = FileOptions::<()>::default().compression_method(CompressionMethod::Stored);
+
+        for (name, content) in entries {
+            zip.start_file(*name, options).expect("start file");
+            zip.write_all(content.as_bytes()).expect("write data");
+        }
+
+        zip.finish().expect("finish zip");
+    }
+
+    fn entry_names(path: &str) -> Vec<String> {
+        let archive = open_epub(path).expect("open epub");
+        archive.file_names().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_find_package_path() {
+        assert_eq!(
+            find_package_path(CONTAINER_XML).unwrap(),
+            "OEBPS/content.opf"
+        );
+        assert!(matches!(
+            find_package_path("<container><rootfiles/></container>"),
+            Err(EpubError::MissingPackageDocument)
+        ));
+    }
+
+    #[test]
+    fn test_parse_package_metadata() {
+        let metadata = parse_package_metadata(PACKAGE_OPF).unwrap();
+
+        assert_eq!(metadata.title, Some("Old Title".to_string()));
+        assert_eq!(metadata.creators, vec!["First Author", "Second Author"]);
+        assert_eq!(metadata.languages, vec!["en"]);
+        assert_eq!(metadata.identifiers, vec!["urn:uuid:1234"]);
+        assert_eq!(metadata.description, Some("Fish & Chips".to_string()));
+        assert_eq!(metadata.publisher, None);
+    }
+
+    #[test]
+    fn test_update_package_metadata_keeps_structure() {
+        let metadata = EpubMetadata {
+            title: Some("New Title".to_string()),
+            creators: vec!["Only Author".to_string()],
+            subjects: vec!["Fantasy".to_string(), "Adventure".to_string()],
+            ..EpubMetadata::default()
+        };
+
+        let opf = update_package_metadata(PACKAGE_OPF, &metadata).unwrap();
+
+        assert!(opf.contains(r#"<dc:title>New Title</dc:title>"#));
+        assert!(opf.contains(r#"<dc:creator id="author">Only Author</dc:creator>"#));
+        assert!(!opf.contains("Second Author"));
+        assert!(!opf.contains("<dc:language>"));
+        assert!(opf.contains(r#"<dc:identifier id="BookId">urn:uuid:1234</dc:identifier>"#));
+        assert!(opf.contains(r#"<meta property="dcterms:modified">"#));
+        assert!(opf.contains(r#"<item id="chapter1""#));
+
+        let reparsed = parse_package_metadata(&opf).unwrap();
+        assert_eq!(reparsed.title, metadata.title);
+        assert_eq!(reparsed.creators, metadata.creators);
+        assert_eq!(reparsed.subjects, metadata.subjects);
+        assert_eq!(reparsed.identifiers, vec!["urn:uuid:1234"]);
+    }
+
+    #[test]
+    fn test_update_package_metadata_keeps_untouched_elements() {
+        let opf = r##"<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="BookId">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:identifier id="BookId">urn:uuid:1234</dc:identifier>
+    <dc:title id="main">Main Title</dc:title>
+    <dc:title id="sub">The Subtitle</dc:title>
+    <meta refines="#sub" property="title-type">subtitle</meta>
+    <dc:date opf:event="publication">2001</dc:date>
+    <dc:date opf:event="modification">2020</dc:date>
+    <dc:creator id="first">First Author</dc:creator>
+    <meta refines="#first" property="role">aut</meta>
+    <dc:creator id="second">Second Author</dc:creator>
+  </metadata>
+</package>"##;
+        let mut metadata = parse_package_metadata(opf).unwrap();
+        assert_eq!(metadata.title, Some("Main Title".to_string()));
+
+        metadata.publisher = Some("Kikou Press".to_string());
+        let updated = update_package_metadata(opf, &metadata).unwrap();
+        assert_eq!(
+            updated,
+            opf.replace(
+                "  </metadata>",
+                "    <dc:publisher>Kikou Press</dc:publisher>\n  </metadata>"
+            )
+        );
+
+        metadata.title = Some("New Title".to_string());
+        metadata.creators = vec!["Second Author".to_string(), "Third Author".to_string()];
+        let updated = update_package_metadata(opf, &metadata).unwrap();
+
+        assert!(updated.contains(r#"<dc:title id="main">New Title</dc:title>"#));
+        assert!(updated.contains(r#"<dc:title id="sub">The Subtitle</dc:title>"#));
+        assert!(updated.contains(r##"<meta refines="#sub" property="title-type">"##));
+        assert!(updated.contains(r#"<dc:date opf:event="publication">2001</dc:date>"#));
+        assert!(updated.contains(r#"<dc:date opf:event="modification">2020</dc:date>"#));
+        assert!(updated.contains(r#"<dc:creator id="second">Second Author</dc:creator>"#));
+        assert!(!updated.contains("First Author"));
+        assert!(!updated.contains(r##"refines="#first""##));
+        assert_eq!(parse_package_metadata(&updated).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_update_package_metadata_matches_the_unique_identifier_by_id() {
+        let opf = r#"<package unique-identifier="BookId">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="isbn">isbn:9780000000001</dc:identifier>
+    <dc:identifier id="BookId">urn:uuid:1234</dc:identifier>
+  </metadata>
+</package>"#;
+        let unique = r#"<dc:identifier id="BookId">urn:uuid:1234</dc:identifier>"#;
+
+        let removed = EpubMetadata {
+            identifiers: vec!["urn:uuid:1234".to_string()],
+            ..EpubMetadata::default()
+        };
+        let updated = update_package_metadata(opf, &removed).unwrap();
+        assert!(updated.contains(unique));
+        assert!(!updated.contains("isbn"));
+        assert_eq!(parse_package_metadata(&updated).unwrap(), removed);
+
+        let reordered = EpubMetadata {
+            identifiers: vec![
+                "urn:uuid:1234".to_string(),
+                "isbn:9780000000001".to_string(),
+            ],
+            ..EpubMetadata::default()
+        };
+        let updated = update_package_metadata(opf, &reordered).unwrap();
+        assert!(updated.contains(unique));
+        assert_eq!(parse_package_metadata(&updated).unwrap(), reordered);
+
+        let updated = update_package_metadata(opf, &EpubMetadata::default()).unwrap();
+        assert!(updated.contains(unique));
+    }
+
+    #[test]
+    fn test_update_package_metadata_declares_namespace() {
+        let opf = r#"<package unique-identifier="id"><metadata/></package>"#;
+        let metadata = EpubMetadata {
+            title: Some("Title".to_string()),
+            ..EpubMetadata::default()
+        };
+
+        let updated = update_package_metadata(opf, &metadata).unwrap();
+
+        assert!(updated.contains(r#"xmlns:dc="http://purl.org/dc/elements/1.1/""#));
+        assert_eq!(
+            parse_package_metadata(&updated).unwrap().title,
+            Some("Title".to_string())
+        );
+    }
+
+    #[test]
+    fn test_save_epub_metadata_impl() {
+        let path = test_path("test_save_metadata.epub");
+        let _ = fs::remove_file(&path);
+        create_epub(
+            &path,
+            &[
+                ("mimetype", "application/epub+zip"),
+                ("META-INF/container.xml", CONTAINER_XML),
+                ("OEBPS/content.opf", PACKAGE_OPF),
+                ("OEBPS/chapter1.xhtml", "<html/>"),
+            ],
+        );
+
+        let mut metadata = read_epub_metadata(&path).expect("read metadata");
+        metadata.title = Some("Saved Title".to_string());
+        metadata.publisher = Some("Kikou Press".to_string());
+
+        let saved = save_epub_metadata_impl(path.clone(), metadata.clone()).expect("save");
+
+        assert_eq!(saved, metadata);
+        assert_eq!(read_epub_metadata(&path).expect("reread"), metadata);
+        assert_eq!(
+            entry_names(&path),
+            vec![
+                "mimetype",
+                "META-INF/container.xml",
+                "OEBPS/content.opf",
+                "OEBPS/chapter1.xhtml"
+            ]
+        );
+        let dir = std::path::Path::new(&path).parent().unwrap();
+        assert!(fs::read_dir(dir).unwrap().flatten().all(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            !(name.starts_with(".test_save_metadata.epub.") && name.ends_with(".kikou-tmp"))
+        }));
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_epub_metadata_without_container() {
+        let path = test_path("test_missing_container.epub");
+        let _ = fs::remove_file(&path);
+        create_epub(&path, &[("mimetype", "application/epub+zip")]);
+
+        let result = read_epub_metadata(&path);
+
+        assert!(matches!(result, Err(EpubError::MissingContainer)));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+
+/// The Dublin Core elements of an EPUB package document.
+///
+/// Elements that commonly repeat are lists; the rest keep only their first
+/// value.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EpubMetadata {
+    pub title: Option<String>,
+    pub creators: Vec<String>,
+    pub contributors: Vec<String>,
+    pub subjects: Vec<String>,
+    pub description: Option<String>,
+    pub publisher: Option<String>,
+    pub date: Option<String>,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    pub format: Option<String>,
+    pub identifiers: Vec<String>,
+    pub source: Option<String>,
+    pub languages: Vec<String>,
+    pub relation: Option<String>,
+    pub coverage: Option<String>,
+    pub rights: Option<String>,
+}
+
+fn first(values: &mut HashMap<String, Vec<String>>, element: &str) -> Option<String> {
+    values.remove(element).and_then(|v| v.into_iter().next())
+}
+
+fn all(values: &mut HashMap<String, Vec<String>>, element: &str) -> Vec<String> {
+    values.remove(element).unwrap_or_default()
+}
+
+impl EpubMetadata {
+    /// Builds the metadata from the text of each Dublin Core element, keyed
+    /// by local name in document order.
+    pub fn from_elements(mut values: HashMap<String, Vec<String>>) -> Self {
+        EpubMetadata {
+            title: first(&mut values, "title"),
+            creators: all(&mut values, "creator"),
+            contributors: all(&mut values, "contributor"),
+            subjects: all(&mut values, "subject"),
+            description: first(&mut values, "description"),
+            publisher: first(&mut values, "publisher"),
+            date: first(&mut values, "date"),
+            type_: first(&mut values, "type"),
+            format: first(&mut values, "format"),
+            identifiers: all(&mut values, "identifier"),
+            source: first(&mut values, "source"),
+            languages: all(&mut values, "language"),
+            relation: first(&mut values, "relation"),
+            coverage: first(&mut values, "coverage"),
+            rights: first(&mut values, "rights"),
+        }
+    }
+
+    /// The values to write for every Dublin Core element, keyed by local name.
+    pub fn to_elements(&self) -> Vec<(&'static str, Vec<String>)> {
+        let single = |value: &Option<String>| value.iter().cloned().collect::<Vec<_>>();
+
+        vec![
+            ("title", single(&self.title)),
+            ("creator", self.creators.clone()),
+            ("contributor", self.contributors.clone()),
+            ("subject", self.subjects.clone()),
+            ("description", single(&self.description)),
+            ("publisher", single(&self.publisher)),
+            ("date", single(&self.date)),
+            ("type", single(&self.type_)),
+            ("format", single(&self.format)),
+            ("identifier", self.identifiers.clone()),
+            ("source", single(&self.source)),
+            ("language", self.languages.clone()),
+            ("relation", single(&self.relation)),
+            ("coverage", single(&self.coverage)),
+            ("rights", single(&self.rights)),
+        ]
+    }
+}
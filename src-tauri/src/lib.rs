@@ -1,10 +1,25 @@
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 mod archive;
 mod comicinfo;
+mod ebook;
+
+/// Internals measured by the benchmarks in `benches/`.
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::archive::backend::cached_zip_archive;
+}
 
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
+        .register_asynchronous_uri_scheme_protocol(
+            archive::protocol::PROTOCOL,
+            |_ctx, request, responder| {
+                tauri::async_runtime::spawn_blocking(move || {
+                    responder.respond(archive::protocol::page_response(&request));
+                });
+            },
+        )
         .invoke_handler(tauri::generate_handler![
             archive::load_cbz,
             archive::unload_cbz,
@@ -14,16 +29,38 @@ pub fn run() {
             archive::get_raw_comicinfo_xml,
             archive::save_comicinfo_xml,
             archive::delete_cbz_comicinfo_xml,
+            archive::commands::list_comicinfo_history,
+            archive::commands::diff_comicinfo_versions,
+            archive::commands::restore_comicinfo_version,
             archive::commands::watch_for_creation,
             archive::commands::stream_file_data,
+            archive::commands::cancel_stream,
+            archive::commands::convert_to_cbz,
+            archive::commands::populate_page_dimensions,
+            archive::commands::detect_double_page_spreads,
+            archive::commands::classify_pages,
+            archive::commands::reorder_pages,
+            archive::commands::remove_pages,
+            archive::commands::insert_pages,
+            archive::commands::replace_page,
+            archive::commands::clean_archive,
+            archive::commands::get_supported_image_formats,
+            archive::commands::clear_thumbnail_cache,
             comicinfo::commands::get_bookmarked_pages,
             comicinfo::commands::validate_comicinfo_xml,
             comicinfo::commands::format_comicinfo_xml,
+            ebook::commands::get_epub_metadata,
+            ebook::commands::save_epub_metadata,
         ])
         .setup(|app| {
-            if cfg!(debug_assertions) {
-                use tauri::Manager;
+            use tauri::Manager;
 
+            let app_data_dir = app.path().app_data_dir()?;
+            archive::atomic_write::init_pending_writes(app_data_dir.join("pending-writes"));
+            archive::thumbnail_cache::init_thumbnail_cache(app_data_dir.join("thumbnails"));
+            archive::history::init_comicinfo_history(app_data_dir.join("history"));
+
+            if cfg!(debug_assertions) {
                 if let Some(window) = app.handle().get_webview_window("main") {
                     window.open_devtools();
                 }
@@ -0,0 +1,30 @@
+import { convertFileSrc } from "@tauri-apps/api/core";
+
+export interface ArchivePageUrlOptions {
+  /** Serves a thumbnail no wider or taller than this many pixels. */
+  maxDimension?: number;
+  /** JPEG quality of the thumbnail, from 1 to 100. */
+  quality?: number;
+}
+
+/**
+ * Get a URL that serves a page straight from an opened archive, for use in
+ * plain `<img>` tags. Only archives loaded through `load_cbz` are served.
+ */
+export function archivePageUrl(
+  path: string,
+  fileName: string,
+  { maxDimension, quality }: ArchivePageUrlOptions = {},
+): string {
+  const base = convertFileSrc("", "kikou");
+  const url = `${base}archive/${encodeURIComponent(path)}/${encodeURIComponent(fileName)}`;
+
+  const params = new URLSearchParams();
+
+  if (maxDimension !== undefined) params.set("w", String(maxDimension));
+  if (quality !== undefined) params.set("q", String(quality));
+
+  const query = params.toString();
+
+  return query ? `${url}?${query}` : url;
+}
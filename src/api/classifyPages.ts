@@ -0,0 +1,49 @@
+import { invoke } from "@tauri-apps/api/core";
+import { UnreadableImage } from "./populatePageDimensions";
+import { BackendPageSettings } from "./savePageSettings";
+
+export interface PageClassificationOptions {
+  blank_max_deviation: number;
+  outlier_window: number;
+  aspect_tolerance: number;
+  colour_tolerance: number;
+}
+
+export type ClassificationReason =
+  | "FirstPage"
+  | "LastPage"
+  | "Blank"
+  | "Outlier";
+
+export interface PageTypeSuggestion {
+  file_name: string;
+  reason: ClassificationReason;
+  settings: BackendPageSettings;
+}
+
+export interface PageClassificationReport {
+  suggestions: PageTypeSuggestion[];
+  /** Every described page with all suggestions applied */
+  page_settings: Record<string, BackendPageSettings>;
+  unreadable: UnreadableImage[];
+}
+
+/**
+ * Suggest page types for the covers, near-blank pages and likely
+ * advertisements of an archive. Nothing is saved. savePageSettings replaces
+ * the settings of every page, so accepted suggestions must be merged into the
+ * full page settings first; `page_settings` is that merge with every
+ * suggestion accepted.
+ *
+ * @param path - The archive path
+ * @param options - Detection thresholds, the backend defaults when omitted
+ */
+export async function classifyPages(
+  path: string,
+  options?: Partial<PageClassificationOptions>,
+): Promise<PageClassificationReport> {
+  return invoke<PageClassificationReport>("classify_pages", {
+    path,
+    options: options ?? null,
+  });
+}
@@ -0,0 +1,28 @@
+import { invoke } from "@tauri-apps/api/core";
+import { ArchiveFingerprint, Fingerprinted } from "@/types/comic";
+
+export interface CleanArchiveReport {
+  removed: string[];
+  dry_run: boolean;
+}
+
+/**
+ * Remove operating system junk such as __MACOSX/, ._ resource forks,
+ * .DS_Store and Thumbs.db from a CBZ archive.
+ *
+ * @param path - The archive path
+ * @param dryRun - Only list the junk entries without removing them
+ * @param expectedFingerprint - The fingerprint from load time; checked when the entries are removed
+ * @returns The report, and the fingerprint of the archive afterwards
+ */
+export async function cleanArchive(
+  path: string,
+  dryRun: boolean,
+  expectedFingerprint: ArchiveFingerprint,
+): Promise<Fingerprinted<CleanArchiveReport>> {
+  return invoke<Fingerprinted<CleanArchiveReport>>("clean_archive", {
+    path,
+    dryRun,
+    expectedFingerprint,
+  });
+}
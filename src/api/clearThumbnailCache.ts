@@ -0,0 +1,14 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export interface ThumbnailCacheStats {
+  entries: number;
+  bytes: number;
+}
+
+/**
+ * Remove every thumbnail cached on disk. Returns how many thumbnails were
+ * removed and their total size in bytes.
+ */
+export async function clearThumbnailCache(): Promise<ThumbnailCacheStats> {
+  return invoke<ThumbnailCacheStats>("clear_thumbnail_cache");
+}
@@ -0,0 +1,78 @@
+import { invoke } from "@tauri-apps/api/core";
+import { ArchiveFingerprint, Fingerprinted } from "@/types/comic";
+
+export type HistoryChange =
+  | { kind: "initial" }
+  | { kind: "external" }
+  | { kind: "save_comic_info_xml" }
+  | { kind: "save_page_settings" }
+  | { kind: "delete_comic_info_xml" }
+  | { kind: "reorder_pages" }
+  | { kind: "remove_pages" }
+  | { kind: "insert_pages" }
+  | { kind: "replace_page" }
+  | { kind: "clean_archive" }
+  | { kind: "populate_page_dimensions" }
+  | { kind: "detect_double_page_spreads" }
+  | { kind: "restore"; version: number };
+
+export interface HistoryEntry {
+  version: number;
+  timestamp_ms: number;
+  change: HistoryChange;
+  author: string;
+  has_comic_info: boolean;
+}
+
+export interface DiffLine {
+  kind: "same" | "added" | "removed";
+  text: string;
+}
+
+/**
+ * List the recorded versions of an archive's ComicInfo.xml.
+ *
+ * @param path - The archive path
+ * @returns The versions, newest first
+ */
+export async function listComicInfoHistory(
+  path: string,
+): Promise<HistoryEntry[]> {
+  return invoke<HistoryEntry[]>("list_comicinfo_history", { path });
+}
+
+/**
+ * Compare the ComicInfo.xml of two recorded versions line by line.
+ *
+ * @param path - The archive path
+ * @param from - The older version
+ * @param to - The newer version
+ */
+export async function diffComicInfoVersions(
+  path: string,
+  from: number,
+  to: number,
+): Promise<DiffLine[]> {
+  return invoke<DiffLine[]>("diff_comicinfo_versions", { path, from, to });
+}
+
+/**
+ * Write a recorded version of ComicInfo.xml back into the archive. The
+ * restore is recorded as a new version, so it can be undone in turn.
+ *
+ * @param path - The archive path
+ * @param version - The version to restore
+ * @param expectedFingerprint - The fingerprint from load time; the write fails if the archive changed since
+ * @returns The restored XML, or null when the version had no ComicInfo.xml, and the new fingerprint
+ */
+export async function restoreComicInfoVersion(
+  path: string,
+  version: number,
+  expectedFingerprint: ArchiveFingerprint,
+): Promise<Fingerprinted<string | null>> {
+  return invoke<Fingerprinted<string | null>>("restore_comicinfo_version", {
+    path,
+    version,
+    expectedFingerprint,
+  });
+}
@@ -0,0 +1,70 @@
+import { invoke, Channel } from "@tauri-apps/api/core";
+import { ArchiveFingerprint } from "@/types/comic";
+
+export type ConvertProgressEvent =
+  | {
+      event: "started";
+      data: {
+        total_files: number;
+      };
+    }
+  | {
+      event: "progress";
+      data: {
+        file_name: string;
+        completed: number;
+      };
+    }
+  | {
+      event: "finished";
+      data: {
+        output_path: string;
+      };
+    };
+
+export interface ConversionReport {
+  source_format: "cbz" | "cbr" | "cb7" | "cbt" | "directory";
+  output_path: string;
+  image_files: string[];
+  skipped_files: string[];
+  includes_comic_info: boolean;
+  source_deleted: boolean;
+  dry_run: boolean;
+}
+
+export interface ConvertToCbzOptions {
+  path: string;
+  deleteSource: boolean;
+  dryRun: boolean;
+  /** The fingerprint from load time; a source changed since is not converted. */
+  expectedFingerprint: ArchiveFingerprint;
+  onEvent?: (event: ConvertProgressEvent) => void;
+}
+
+/**
+ * Convert a CBR, CB7, CBT or folder of images into a CBZ next to the source.
+ *
+ * With `dryRun` set nothing is written and the report describes what would be
+ * produced.
+ */
+export async function convertToCbz({
+  path,
+  deleteSource,
+  dryRun,
+  expectedFingerprint,
+  onEvent,
+}: ConvertToCbzOptions): Promise<ConversionReport> {
+  const channel = new Channel<ConvertProgressEvent>();
+
+  channel.onmessage = (message) => {
+    onEvent?.(message);
+  };
+
+  return invoke<ConversionReport>("convert_to_cbz", {
+    path,
+    deleteSource,
+    dryRun,
+    expectedFingerprint,
+    onEvent: channel,
+  });
+}
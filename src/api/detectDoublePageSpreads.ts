@@ -0,0 +1,50 @@
+import { invoke } from "@tauri-apps/api/core";
+import { ArchiveFingerprint, Fingerprinted } from "@/types/comic";
+import { UnreadableImage } from "./populatePageDimensions";
+
+export interface SpreadDetectionOptions {
+  min_aspect_ratio: number;
+  min_relative_width: number;
+}
+
+export interface SpreadSuggestion {
+  file_name: string;
+  image: number;
+  width: number;
+  height: number;
+  aspect_ratio: number;
+  already_marked: boolean;
+}
+
+export interface SpreadDetectionReport {
+  spreads: SpreadSuggestion[];
+  unreadable: UnreadableImage[];
+  saved: boolean;
+}
+
+/**
+ * Find double-page spreads from the aspect ratio of each image and mark them
+ * as DoublePage.
+ *
+ * @param path - The archive path
+ * @param preview - Only return the suggestions, without saving them
+ * @param expectedFingerprint - The fingerprint from load time; checked when the suggestions are saved
+ * @param options - Detection thresholds, the backend defaults when omitted
+ * @returns The report, and the fingerprint of the archive afterwards
+ */
+export async function detectDoublePageSpreads(
+  path: string,
+  preview: boolean,
+  expectedFingerprint: ArchiveFingerprint,
+  options?: Partial<SpreadDetectionOptions>,
+): Promise<Fingerprinted<SpreadDetectionReport>> {
+  return invoke<Fingerprinted<SpreadDetectionReport>>(
+    "detect_double_page_spreads",
+    {
+      path,
+      options: options ?? null,
+      preview,
+      expectedFingerprint,
+    },
+  );
+}
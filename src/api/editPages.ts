@@ -0,0 +1,70 @@
+import { invoke } from "@tauri-apps/api/core";
+import { ArchiveFingerprint, Fingerprinted } from "@/types/comic";
+
+/**
+ * Remove images from a CBZ archive together with their page settings.
+ *
+ * @param path - The archive path
+ * @param fileNames - The image file names to remove
+ * @param expectedFingerprint - The fingerprint from load time; the write fails if the archive changed since
+ * @returns The remaining image file names, in order, and the new fingerprint
+ */
+export async function removePages(
+  path: string,
+  fileNames: string[],
+  expectedFingerprint: ArchiveFingerprint,
+): Promise<Fingerprinted<string[]>> {
+  return invoke<Fingerprinted<string[]>>("remove_pages", {
+    path,
+    fileNames,
+    expectedFingerprint,
+  });
+}
+
+/**
+ * Insert image files from disk into a CBZ archive. Every image is renamed to
+ * its zero-padded page number.
+ *
+ * @param path - The archive path
+ * @param position - The page index the first inserted image takes
+ * @param filePaths - The image files to insert, in order
+ * @param expectedFingerprint - The fingerprint from load time; the write fails if the archive changed since
+ * @returns The new image file names, in order, and the new fingerprint
+ */
+export async function insertPages(
+  path: string,
+  position: number,
+  filePaths: string[],
+  expectedFingerprint: ArchiveFingerprint,
+): Promise<Fingerprinted<string[]>> {
+  return invoke<Fingerprinted<string[]>>("insert_pages", {
+    path,
+    position,
+    filePaths,
+    expectedFingerprint,
+  });
+}
+
+/**
+ * Replace an image of a CBZ archive with an image file from disk, keeping its
+ * page settings.
+ *
+ * @param path - The archive path
+ * @param fileName - The image file name to replace
+ * @param filePath - The replacement image file
+ * @param expectedFingerprint - The fingerprint from load time; the write fails if the archive changed since
+ * @returns The image file names, in order, and the new fingerprint
+ */
+export async function replacePage(
+  path: string,
+  fileName: string,
+  filePath: string,
+  expectedFingerprint: ArchiveFingerprint,
+): Promise<Fingerprinted<string[]>> {
+  return invoke<Fingerprinted<string[]>>("replace_page", {
+    path,
+    fileName,
+    filePath,
+    expectedFingerprint,
+  });
+}
@@ -0,0 +1,47 @@
+import { invoke } from "@tauri-apps/api/core";
+import { ArchiveFingerprint, Fingerprinted } from "@/types/comic";
+
+/**
+ * The Dublin Core metadata of an EPUB package document.
+ */
+export interface EpubMetadata {
+  title: string | null;
+  creators: string[];
+  contributors: string[];
+  subjects: string[];
+  description: string | null;
+  publisher: string | null;
+  date: string | null;
+  type: string | null;
+  format: string | null;
+  identifiers: string[];
+  source: string | null;
+  languages: string[];
+  relation: string | null;
+  coverage: string | null;
+  rights: string | null;
+}
+
+export async function getEpubMetadata(path: string): Promise<EpubMetadata> {
+  return invoke<EpubMetadata>("get_epub_metadata", { path });
+}
+
+/**
+ * Save metadata to the EPUB and get the metadata as stored in the file.
+ *
+ * @param path - The EPUB path
+ * @param metadata - The metadata to write
+ * @param expectedFingerprint - The fingerprint from load time; the write fails if the archive changed since
+ * @returns The metadata read back from the updated package document, and the new fingerprint
+ */
+export async function saveEpubMetadata(
+  path: string,
+  metadata: EpubMetadata,
+  expectedFingerprint: ArchiveFingerprint,
+): Promise<Fingerprinted<EpubMetadata>> {
+  return invoke<Fingerprinted<EpubMetadata>>("save_epub_metadata", {
+    path,
+    metadata,
+    expectedFingerprint,
+  });
+}
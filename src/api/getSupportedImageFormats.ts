@@ -0,0 +1,33 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export type PageImageFormat =
+  | "jpeg"
+  | "png"
+  | "gif"
+  | "bmp"
+  | "webp"
+  | "avif"
+  | "jxl"
+  | "tiff"
+  | "heif";
+
+export interface ImageFormatInfo {
+  format: PageImageFormat;
+  extensions: string[];
+  mime_type: string;
+  /** Shown by the webview as is. */
+  displayable: boolean;
+  /**
+   * Decoded by the backend, so previews and thumbnails can be made at any
+   * size whether or not the webview shows the format.
+   */
+  previewable: boolean;
+}
+
+/**
+ * Get the image formats pages can be stored in, and whether each can be
+ * previewed on this platform.
+ */
+export async function getSupportedImageFormats(): Promise<ImageFormatInfo[]> {
+  return invoke<ImageFormatInfo[]>("get_supported_image_formats");
+}
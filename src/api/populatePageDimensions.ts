@@ -0,0 +1,37 @@
+import { invoke } from "@tauri-apps/api/core";
+import { ArchiveFingerprint, Fingerprinted } from "@/types/comic";
+
+export interface UnreadableImage {
+  file_name: string;
+  message: string;
+}
+
+export interface PageDimensionsReport {
+  updated_pages: number;
+  added_pages: number;
+  unreadable: UnreadableImage[];
+}
+
+/**
+ * Fill in ImageWidth, ImageHeight and ImageSize on every page from the image
+ * headers in the archive.
+ *
+ * @param path - The archive path
+ * @param includeAllImages - Also add a page entry for images that have none
+ * @param expectedFingerprint - The fingerprint from load time; the write fails if the archive changed since
+ * @returns The report, and the new fingerprint
+ */
+export async function populatePageDimensions(
+  path: string,
+  includeAllImages: boolean,
+  expectedFingerprint: ArchiveFingerprint,
+): Promise<Fingerprinted<PageDimensionsReport>> {
+  return invoke<Fingerprinted<PageDimensionsReport>>(
+    "populate_page_dimensions",
+    {
+      path,
+      includeAllImages,
+      expectedFingerprint,
+    },
+  );
+}
@@ -0,0 +1,23 @@
+import { invoke } from "@tauri-apps/api/core";
+import { ArchiveFingerprint, Fingerprinted } from "@/types/comic";
+
+/**
+ * Rewrite a CBZ archive with its images in a new order. Images are renamed to
+ * zero-padded page numbers and their page settings move with them.
+ *
+ * @param path - The archive path
+ * @param order - Every image file name of the archive, in the new order
+ * @param expectedFingerprint - The fingerprint from load time; the write fails if the archive changed since
+ * @returns The new image file names, in order, and the new fingerprint
+ */
+export async function reorderPages(
+  path: string,
+  order: string[],
+  expectedFingerprint: ArchiveFingerprint,
+): Promise<Fingerprinted<string[]>> {
+  return invoke<Fingerprinted<string[]>>("reorder_pages", {
+    path,
+    order,
+    expectedFingerprint,
+  });
+}
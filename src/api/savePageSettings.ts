@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
+import { ArchiveFingerprint, Fingerprinted } from "@/types/comic";
 
 /**
  * The shape of the page settings sent to the backend.
@@ -21,14 +22,20 @@ export type SavePageSettingsResponse = BackendPageSettings[];
  *
  * @param path - The archive path
  * @param pageSettings - The page settings to save, keyed by file name
- * @returns The new authoritative page settings as returned by the backend
+ * @param expectedFingerprint - The fingerprint from load time; the write fails if the archive changed since
+ * @returns The new authoritative page settings as returned by the backend, and the new fingerprint
  */
 export async function savePageSettings(
   path: string,
   pageSettings: Record<string, BackendPageSettings>,
-): Promise<SavePageSettingsResponse> {
-  return invoke<SavePageSettingsResponse>("save_page_settings", {
-    path,
-    pageSettings,
-  });
+  expectedFingerprint: ArchiveFingerprint,
+): Promise<Fingerprinted<SavePageSettingsResponse>> {
+  return invoke<Fingerprinted<SavePageSettingsResponse>>(
+    "save_page_settings",
+    {
+      path,
+      pageSettings,
+      expectedFingerprint,
+    },
+  );
 }
@@ -11,8 +11,12 @@ export type StreamProgressEvent =
       event: "preview";
       data: {
         file_name: string;
-        data_raw: number[];
-        data_base64: string;
+        /** Only sent when the "raw" encoding was requested. */
+        data_raw?: number[];
+        /** Only sent when the "base64" encoding was requested. */
+        data_base64?: string;
+        /** The type of the data, which may be a transcoded copy or thumbnail. */
+        mime_type?: string;
       };
     }
   | {
@@ -24,19 +28,43 @@ export type StreamProgressEvent =
     }
   | {
       event: "finished";
-      data: null;
+      data: {
+        /** Set when the stream was stopped through cancelStream. */
+        cancelled?: boolean;
+      } | null;
     };
 
+export type PreviewEncoding = "raw" | "base64";
+
+export interface ThumbnailOptions {
+  /** Largest width or height of the thumbnail, in pixels. Defaults to 400. */
+  max_dimension: number;
+  /** JPEG quality from 1 to 100. Defaults to 80. */
+  quality: number;
+}
+
 export interface StreamFileDataOptions {
   path: string;
   fileNames: string[];
   onEvent: (event: StreamProgressEvent) => void;
+  /** The one encoding previews are sent in. Defaults to "base64". */
+  encoding?: PreviewEncoding;
+  /** Downscales each page in the backend before sending it. */
+  thumbnail?: Partial<ThumbnailOptions>;
+  /** Lets the stream be stopped with cancelStream. */
+  streamId?: string;
+  /** Pages to send first, such as the ones in view. */
+  priority?: string[];
 }
 
 export async function streamFileData({
   path,
   fileNames,
   onEvent,
+  encoding,
+  thumbnail,
+  streamId,
+  priority,
 }: StreamFileDataOptions): Promise<void> {
   const channel = new Channel<StreamProgressEvent>();
 
@@ -47,6 +75,21 @@ export async function streamFileData({
   await invoke("stream_file_data", {
     path,
     fileNames,
+    options: {
+      encoding: encoding ?? "base64",
+      thumbnail: thumbnail ?? null,
+      stream_id: streamId ?? null,
+      priority: priority ?? [],
+    },
     onEvent: channel,
   });
 }
+
+/**
+ * Stop a running streamFileData call started with the given streamId. Pages
+ * already sent stay cached; the stream ends with a finished event that has
+ * `cancelled` set. Resolves to false when no such stream is running.
+ */
+export async function cancelStream(streamId: string): Promise<boolean> {
+  return invoke<boolean>("cancel_stream", { streamId });
+}
@@ -9,6 +9,7 @@ import { LocalStorageManager } from "../../utils/localStorage";
 import { usePageSettingsContext } from "@/contexts/PageSettingsContext";
 import { useImageFiles } from "@/hooks/useImageFiles";
 import { useArchiveContext } from "@/contexts/ArchiveContext";
+import { errorMessage } from "@/types/errorResponse";
 
 interface EditTabProps {
   storageManager: LocalStorageManager;
@@ -37,7 +38,7 @@ export default function EditTab({ storageManager }: EditTabProps) {
         window.dispatchEvent(new CustomEvent("bookmarksUpdated"));
         setLocalError(null);
       } catch (error) {
-        setLocalError("Failed to save settings: " + error);
+        setLocalError("Failed to save settings: " + errorMessage(error));
       }
     },
     disabled: isSaving || !hasEditedPages,
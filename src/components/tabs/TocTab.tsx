@@ -4,6 +4,7 @@ import { Box, Button } from "@mui/joy";
 import PageSelector from "../pages/Selector";
 import { createBlankPageInfo, ComicPageInfo } from "../../types/comic";
 import { useArchiveContext } from "@/contexts/ArchiveContext";
+import { errorMessage } from "@/types/errorResponse";
 import { useImageFiles } from "@/hooks/useImageFiles";
 import { usePageSettingsContext } from "@/contexts/PageSettingsContext";
 import {
@@ -34,7 +35,7 @@ export default function TocTab() {
       window.dispatchEvent(new CustomEvent("bookmarksUpdated"));
       setLocalError(null);
     } catch (error) {
-      setLocalError("Failed to save settings: " + error);
+      setLocalError("Failed to save settings: " + errorMessage(error));
     }
   };
 
@@ -8,9 +8,13 @@ import React, {
   useRef,
 } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import { LoadCbzResponse, ComicInfo } from "@/types/comic";
+import { LoadCbzResponse, ComicInfo, ArchiveFingerprint } from "@/types/comic";
 import { devLog } from "@/utils/devLog";
-import { ErrorResponse, ErrorResponseType } from "@/types/errorResponse";
+import {
+  ErrorResponse,
+  ErrorResponseType,
+  errorMessage,
+} from "@/types/errorResponse";
 import { useArchiveEvents } from "@/hooks/useArchiveEvents";
 import { getStorageManager } from "@/utils/localStorage";
 
@@ -21,6 +25,7 @@ type ArchiveContextValue = {
   error: ErrorResponse | null;
   reload: () => void;
   reloadComicInfo: () => Promise<void>;
+  setFingerprint: (fingerprint: ArchiveFingerprint) => void;
   previewCache: React.RefObject<Record<string, string>>;
   tocFile: string | null;
   setTocFile: React.Dispatch<React.SetStateAction<string | null>>;
@@ -97,6 +102,12 @@ export function ArchiveProvider({
     load();
   }, [load]);
 
+  // Our own writes change the archive too; take the fingerprint they return
+  // so the next write is not rejected as a conflict.
+  const setFingerprint = useCallback((fingerprint: ArchiveFingerprint) => {
+    setResult((prev) => (prev ? { ...prev, fingerprint } : prev));
+  }, []);
+
   const reloadComicInfo = useCallback(async () => {
     if (!path) return;
 
@@ -130,7 +141,7 @@ export function ArchiveProvider({
       // Unload backend watcher when this provider is no longer active
       if (path) {
         invoke("unload_cbz", { path }).catch((err) => {
-          devLog("Failed to unload CBZ watcher: " + String(err));
+          devLog("Failed to unload CBZ watcher: " + errorMessage(err));
         });
       }
     };
@@ -171,6 +182,7 @@ export function ArchiveProvider({
         error,
         reload,
         reloadComicInfo,
+        setFingerprint,
         previewCache,
         tocFile,
         setTocFile,
@@ -9,10 +9,15 @@ import React, {
   ReactNode,
 } from "react";
 import { savePageSettings } from "@/api/savePageSettings";
-import { ComicPageInfo, createBlankPageInfo } from "../types/comic";
+import {
+  ComicPageInfo,
+  createBlankPageInfo,
+  requireFingerprint,
+} from "../types/comic";
 import { LocalStorageManager } from "../utils/localStorage";
 import { devLog } from "../utils/devLog";
 import { useComicInfo } from "../hooks/useComicInfo";
+import { useArchiveContext } from "./ArchiveContext";
 
 interface PageSettingsContextType {
   currentSettings: Record<string, ComicPageInfo>;
@@ -227,6 +232,9 @@ export function PageSettingsProvider({
   );
 
   const { reloadComicInfo } = useComicInfo();
+  const archive = useArchiveContext();
+  const fingerprint = archive?.result?.fingerprint;
+  const setFingerprint = archive?.setFingerprint;
 
   const saveAllSettings = useCallback(async (): Promise<string[]> => {
     if (!path) {
@@ -270,7 +278,13 @@ export function PageSettingsProvider({
 
         devLog("Saving edited pages to backend:", backendSettings);
 
-        await savePageSettings(path, backendSettings);
+        const saved = await savePageSettings(
+          path,
+          backendSettings,
+          requireFingerprint(fingerprint),
+        );
+
+        setFingerprint?.(saved.fingerprint);
         // No need to setOriginalSettings here; reloadComicInfo will update originalSettings via useComicInfo
         // Reload comic info from backend so useComicInfo gets the latest state
 
@@ -299,6 +313,8 @@ export function PageSettingsProvider({
     currentSettings,
     getBookmarkedFiles,
     storageManager,
+    fingerprint,
+    setFingerprint,
   ]);
 
   const contextValue: PageSettingsContextType = {
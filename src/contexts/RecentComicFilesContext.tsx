@@ -6,6 +6,7 @@ import React, {
   ReactNode,
 } from "react";
 import { open } from "@tauri-apps/plugin-dialog";
+import { COMIC_ARCHIVE_EXTENSIONS } from "@/types/comic";
 import router from "next/router";
 import { useArchiveContext } from "@/contexts/ArchiveContext";
 
@@ -98,8 +99,8 @@ export function useRecentComicFiles() {
       multiple: false,
       filters: [
         {
-          name: "CBZ Files",
-          extensions: ["cbz"],
+          name: "Comic Archives",
+          extensions: COMIC_ARCHIVE_EXTENSIONS,
         },
       ],
     });
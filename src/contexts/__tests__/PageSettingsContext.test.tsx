@@ -53,12 +53,17 @@ const createMockStorageManager = (): Partial<LocalStorageManager> => {
   };
 };
 
+const mockFingerprint = { size: 1024, modified_ms: 1700000000000 };
+
+const savedPageSettings = { value: [], fingerprint: mockFingerprint };
+
 const createMockArchiveContext = (overrides?: any) => ({
-  result: { image_files: [], comic_info: null },
+  result: { image_files: [], comic_info: null, fingerprint: mockFingerprint },
   loading: false,
   error: null,
   reload: jest.fn(),
   reloadComicInfo: jest.fn().mockResolvedValue(undefined),
+  setFingerprint: jest.fn(),
   previewCache: { current: {} },
   tocFile: null,
   setTocFile: jest.fn(),
@@ -390,7 +395,10 @@ describe("PageSettingsContext", () => {
         currentSettings,
       );
 
-      mockedInvoke.mockResolvedValue(undefined);
+      const archiveContext = createMockArchiveContext();
+
+      mockUseArchiveContext.mockReturnValue(archiveContext);
+      mockedInvoke.mockResolvedValue(savedPageSettings);
 
       render(
         <PageSettingsProvider
@@ -431,7 +439,11 @@ describe("PageSettingsContext", () => {
       expect(mockedInvoke).toHaveBeenCalledWith("save_page_settings", {
         path: "/test/path.cbz",
         pageSettings: expectedBackend,
+        expectedFingerprint: mockFingerprint,
       });
+      expect(archiveContext.setFingerprint).toHaveBeenCalledWith(
+        mockFingerprint,
+      );
 
       // storage manager should be updated with bookmarked files and original settings
       expect(
@@ -545,7 +557,7 @@ describe("PageSettingsContext", () => {
       (mockStorage.getCurrentPageSettings as jest.Mock).mockReturnValue(
         currentSettings,
       );
-      mockedInvoke.mockResolvedValue(undefined);
+      mockedInvoke.mockResolvedValue(savedPageSettings);
 
       const TestComponent = () => {
         const { saveAllSettings, updatePageSettings } =
@@ -615,6 +627,7 @@ describe("PageSettingsContext", () => {
         error: null,
         reloadComicInfo: jest.fn(),
       });
+      mockUseArchiveContext.mockReturnValue(createMockArchiveContext());
     });
 
     it("should not send empty pages to backend when user clears page settings", async () => {
@@ -643,7 +656,7 @@ describe("PageSettingsContext", () => {
         currentSettings,
       );
 
-      mockedInvoke.mockResolvedValue(undefined);
+      mockedInvoke.mockResolvedValue(savedPageSettings);
 
       const TestComponent = () => {
         const { currentSettings, updatePageSettings, saveAllSettings } =
@@ -784,7 +797,7 @@ describe("PageSettingsContext", () => {
         settings,
       );
 
-      mockedInvoke.mockResolvedValue(undefined);
+      mockedInvoke.mockResolvedValue(savedPageSettings);
 
       const TestComponent = () => {
         const { saveAllSettings } = usePageSettingsContext();
@@ -878,7 +891,7 @@ describe("PageSettingsContext", () => {
         settings,
       );
 
-      mockedInvoke.mockResolvedValue(undefined);
+      mockedInvoke.mockResolvedValue(savedPageSettings);
 
       const TestComponent = () => {
         const { saveAllSettings } = usePageSettingsContext();
@@ -12,6 +12,7 @@ jest.mock("@/contexts/ArchiveContext", () => ({
 const mockStreamFileData = jest.fn();
 jest.mock("@/api/streamFileData", () => ({
   streamFileData: (...args: any[]) => mockStreamFileData(...args),
+  cancelStream: jest.fn(() => Promise.resolve(true)),
 }));
 
 describe("useStreamPreviews", () => {
@@ -1,6 +1,10 @@
 import { useState, useEffect } from "react";
 import { devLog } from "@/utils/devLog";
-import { ErrorResponse, ErrorResponseType } from "@/types/errorResponse";
+import {
+  ErrorResponse,
+  ErrorResponseType,
+  errorMessage,
+} from "@/types/errorResponse";
 import { useArchiveContext } from "@/contexts/ArchiveContext";
 import { watchForArchiveCreation } from "@/api/watchForArchiveCreation";
 
@@ -47,7 +51,9 @@ export function useArchiveValidation(): ArchiveValidationResult {
       setError(archive.error as ErrorResponse);
       // Send request to watch for creation
       watchForArchiveCreation(archive.path).catch((err) => {
-        devLog("Failed to start watch for archive creation: " + String(err));
+        devLog(
+          "Failed to start watch for archive creation: " + errorMessage(err),
+        );
       });
       setIsValidArchive(false);
       setIsValidXml(false);
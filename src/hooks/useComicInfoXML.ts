@@ -2,12 +2,20 @@ import { useState, useEffect, useCallback } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { devLog } from "@/utils/devLog";
 import { useArchiveContext } from "@/contexts/ArchiveContext";
+import {
+  Fingerprinted,
+  ValidationIssue,
+  requireFingerprint,
+} from "@/types/comic";
+import { errorMessage } from "@/types/errorResponse";
 
 export function useComicInfoXML(path: string) {
   const archiveContext = useArchiveContext();
   const reload = archiveContext?.reload;
   const hasUnsavedChanges = archiveContext?.hasUnsavedXmlChanges ?? false;
   const setHasUnsavedChanges = archiveContext?.setHasUnsavedXmlChanges;
+  const fingerprint = archiveContext?.result?.fingerprint ?? null;
+  const setFingerprint = archiveContext?.setFingerprint;
 
   const [xml, setXml] = useState("");
   const [originalXml, setOriginalXml] = useState("");
@@ -95,17 +103,26 @@ export function useComicInfoXML(path: string) {
       setIsValidating(true);
 
       try {
-        await invoke("validate_comicinfo_xml", { xml: contentToValidate });
+        const warnings = await invoke<ValidationIssue[] | undefined>(
+          "validate_comicinfo_xml",
+          { xml: contentToValidate, path: path || null },
+        );
         setIsValid(true);
         setIsValidating(false);
-        setValidationMessage("Valid ComicInfo.xml");
+        setValidationMessage(
+          warnings && warnings.length > 0
+            ? `Valid ComicInfo.xml with warnings: ${warnings
+                .map((w) => `${w.field}: ${w.message}`)
+                .join("; ")}`
+            : "Valid ComicInfo.xml",
+        );
       } catch (e: unknown) {
         setIsValid(false);
         setIsValidating(false);
-        setValidationMessage(e instanceof Error ? e.message : String(e));
+        setValidationMessage(errorMessage(e));
       }
     },
-    [xml, isValidating],
+    [xml, isValidating, path],
   );
 
   const clearValidation = useCallback(() => {
@@ -125,11 +142,17 @@ export function useComicInfoXML(path: string) {
       setError(null);
 
       try {
-        const savedXml = await invoke<string>("save_comicinfo_xml", {
-          path,
-          xml,
-        });
+        const saved = await invoke<Fingerprinted<string>>(
+          "save_comicinfo_xml",
+          {
+            path,
+            xml,
+            expectedFingerprint: requireFingerprint(fingerprint),
+          },
+        );
+        const savedXml = saved.value;
 
+        setFingerprint?.(saved.fingerprint);
         setOriginalXml(savedXml);
         setXml(savedXml);
         setIsValid(true);
@@ -147,13 +170,11 @@ export function useComicInfoXML(path: string) {
         setIsValid(false);
         setIsSaving(false);
         setValidationMessage(null);
-        setError(
-          "Failed to save XML: " + (e instanceof Error ? e.message : String(e)),
-        );
+        setError("Failed to save XML: " + errorMessage(e));
         return false;
       }
     },
-    [xml, path, setHasSavedData],
+    [xml, path, fingerprint, setFingerprint, setHasSavedData],
   );
 
   const resetXml = useCallback(() => {
@@ -189,17 +210,25 @@ export function useComicInfoXML(path: string) {
     setError(null);
 
     try {
-      await invoke("delete_cbz_comicinfo_xml", { path });
+      const deleted = await invoke<Fingerprinted<null>>(
+        "delete_cbz_comicinfo_xml",
+        {
+          path,
+          expectedFingerprint: requireFingerprint(fingerprint),
+        },
+      );
+
+      setFingerprint?.(deleted.fingerprint);
       setXml("");
       setOriginalXml("");
       return true;
-    } catch {
-      setError("Failed to delete ComicInfo.xml");
+    } catch (e: unknown) {
+      setError("Failed to delete ComicInfo.xml: " + errorMessage(e));
       return false;
     } finally {
       setIsDeleting(false);
     }
-  }, [path]);
+  }, [path, fingerprint, setFingerprint]);
 
   const onTabLoseFocus = useCallback(() => {
     const hasSavedData = getHasSavedData();
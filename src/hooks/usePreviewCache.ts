@@ -17,6 +17,16 @@ function getMimeType(fileName: string): string {
       return "image/webp";
     case "bmp":
       return "image/bmp";
+    case "avif":
+      return "image/avif";
+    case "jxl":
+      return "image/jxl";
+    case "heic":
+    case "heif":
+      return "image/heif";
+    case "tif":
+    case "tiff":
+      return "image/tiff";
     case "svg":
       return "image/svg+xml";
     case "png":
@@ -1,6 +1,11 @@
-import { useEffect, useCallback } from "react";
+import { useEffect, useCallback, useRef } from "react";
 import { useArchiveContext } from "@/contexts/ArchiveContext";
-import { streamFileData, StreamProgressEvent } from "@/api/streamFileData";
+import {
+  cancelStream,
+  streamFileData,
+  StreamProgressEvent,
+  ThumbnailOptions,
+} from "@/api/streamFileData";
 import { devLog } from "@/utils/devLog";
 
 function getMimeType(fileName: string): string {
@@ -16,6 +21,16 @@ function getMimeType(fileName: string): string {
       return "image/webp";
     case "bmp":
       return "image/bmp";
+    case "avif":
+      return "image/avif";
+    case "jxl":
+      return "image/jxl";
+    case "heic":
+    case "heif":
+      return "image/heif";
+    case "tif":
+    case "tiff":
+      return "image/tiff";
     case "svg":
       return "image/svg+xml";
     case "png":
@@ -24,9 +39,13 @@ function getMimeType(fileName: string): string {
   }
 }
 
-function createDataUrl(base64Data: string, fileName: string): string {
-  const mimeType = getMimeType(fileName);
+let nextStreamId = 0;
 
+function createDataUrl(
+  base64Data: string,
+  fileName: string,
+  mimeType: string = getMimeType(fileName),
+): string {
   return `data:${mimeType};base64,${base64Data}`;
 }
 
@@ -36,6 +55,10 @@ export interface UseStreamPreviewsOptions {
   onProgress?: (loaded: number, total: number) => void;
   onFinish?: () => void;
   onStart?: () => void;
+  /** Streams downscaled previews instead of full-resolution pages. */
+  thumbnail?: Partial<ThumbnailOptions>;
+  /** Pages to stream first, such as the ones in view. */
+  priority?: string[];
 }
 
 export function useStreamPreviews({
@@ -43,8 +66,11 @@ export function useStreamPreviews({
   onProgress,
   onFinish,
   onStart,
+  thumbnail,
+  priority,
 }: UseStreamPreviewsOptions) {
   const ctx = useArchiveContext();
+  const streamIdRef = useRef<string | null>(null);
 
   if (!ctx) throw new Error("ArchiveContext not found");
 
@@ -66,11 +92,18 @@ export function useStreamPreviews({
 
     let loaded = fileNames.length - uncachedFiles.length;
     const total = fileNames.length;
+    const streamId = `previews-${++nextStreamId}`;
+
+    streamIdRef.current = streamId;
 
     try {
       await streamFileData({
         path: ctx.path,
         fileNames: uncachedFiles,
+        encoding: "base64",
+        thumbnail,
+        streamId,
+        priority,
         onEvent: (event: StreamProgressEvent) => {
           switch (event.event) {
             case "started":
@@ -80,16 +113,17 @@ export function useStreamPreviews({
 
               break;
             case "preview": {
-              const { file_name, data_base64 } = event.data;
+              const { file_name, data_base64, mime_type } = event.data;
 
-              if (!file_name) {
-                devLog("Received preview with undefined fileName", event);
+              if (!file_name || data_base64 === undefined) {
+                devLog("Received preview without fileName or data", event);
                 return;
               }
 
               ctx.previewCache.current[file_name] = createDataUrl(
                 data_base64,
                 file_name,
+                mime_type,
               );
 
               loaded++;
@@ -107,6 +141,11 @@ export function useStreamPreviews({
               onProgress?.(loaded, total);
               break;
             case "finished":
+              if (event.data?.cancelled) {
+                devLog("Streaming cancelled");
+                break;
+              }
+
               devLog("Streaming finished");
 
               onFinish?.();
@@ -116,11 +155,26 @@ export function useStreamPreviews({
       });
     } catch (error) {
       devLog("Streaming error:", error);
+    } finally {
+      if (streamIdRef.current === streamId) {
+        streamIdRef.current = null;
+      }
     }
-  }, [ctx.path, fileNames, onProgress, onFinish, onStart]);
+  }, [ctx.path, fileNames, onProgress, onFinish, onStart, thumbnail, priority]);
 
   useEffect(() => {
     startStreaming();
+
+    return () => {
+      const streamId = streamIdRef.current;
+
+      if (streamId) {
+        streamIdRef.current = null;
+        cancelStream(streamId).catch((error) =>
+          devLog("Failed to cancel streaming:", error),
+        );
+      }
+    };
   }, [startStreaming]);
 
   return { startStreaming };
@@ -1,6 +1,7 @@
 "use client";
 
 import { open } from "@tauri-apps/plugin-dialog";
+import { COMIC_ARCHIVE_EXTENSIONS } from "@/types/comic";
 import { useRouter } from "next/router";
 import { useState } from "react";
 import {
@@ -35,19 +36,22 @@ function PageContent() {
 
   useResetNavigation();
 
-  const handlePickFile = async () => {
+  const handlePick = async (directory: boolean) => {
     setLoading(true);
     setError(null);
 
     try {
       const selected = await open({
         multiple: false,
-        filters: [
-          {
-            name: "CBZ Files",
-            extensions: ["cbz"],
-          },
-        ],
+        directory,
+        filters: directory
+          ? undefined
+          : [
+              {
+                name: "Comic Archives",
+                extensions: COMIC_ARCHIVE_EXTENSIONS,
+              },
+            ],
       });
 
       if (selected) {
@@ -96,7 +100,8 @@ function PageContent() {
               Comic Tagger
             </Typography>
             <Typography level="body-lg" color="neutral">
-              Select a .cbz file to start tagging your comics.
+              Select a comic archive or a folder of images to start tagging
+              your comics.
             </Typography>
 
             {loading ? (
@@ -106,12 +111,21 @@ function PageContent() {
             ) : (
               <Stack spacing={2}>
                 <Button
-                  onClick={handlePickFile}
+                  onClick={() => handlePick(false)}
                   size="lg"
                   variant="solid"
                   color="primary"
                 >
-                  Pick a .cbz file
+                  Pick a comic archive
+                </Button>
+
+                <Button
+                  onClick={() => handlePick(true)}
+                  size="lg"
+                  variant="outlined"
+                  color="primary"
+                >
+                  Pick a folder of images
                 </Button>
 
                 <RecentComicFiles onFileSelect={handleRecentFileSelect} />
@@ -66,10 +66,79 @@ export interface ComicInfoPage {
   Bookmark?: string;
 }
 
+export const COMIC_ARCHIVE_EXTENSIONS = ["cbz", "cbr", "cb7", "cbt"];
+
+export type ArchiveFormat = "cbz" | "cbr" | "cb7" | "cbt" | "directory";
+
+export interface ComicInfoParseDiagnostic {
+  element: string;
+  attribute: string | null;
+  line: number;
+  column: number;
+  value: string;
+  fallback: string;
+}
+
+export interface ValidationIssue {
+  severity: "Error" | "Warning";
+  field: string;
+  message: string;
+}
+
+export interface PageOrderMismatch {
+  image: number;
+  recorded: string;
+  current: string;
+}
+
+/**
+ * Raised when ComicInfo.xml was written against a different page order, so
+ * some pages may describe the wrong image.
+ */
+export interface PageOrderWarning {
+  message: string;
+  mismatches: PageOrderMismatch[];
+}
+
+/**
+ * The state of an archive on disk when it was loaded. Write commands take it
+ * back to reject writes to archives changed by another program since.
+ */
+export interface ArchiveFingerprint {
+  size: number;
+  modified_ms: number;
+}
+
+/**
+ * The result of a write together with the fingerprint the archive has after
+ * it, to pass to the next write.
+ */
+export interface Fingerprinted<T> {
+  value: T;
+  fingerprint: ArchiveFingerprint;
+}
+
+/**
+ * The fingerprint to pass to a write. Fails when the archive was loaded
+ * without one, as the write could not be checked for conflicts.
+ */
+export function requireFingerprint(
+  fingerprint: ArchiveFingerprint | null | undefined,
+): ArchiveFingerprint {
+  if (!fingerprint) {
+    throw new Error("The archive has no fingerprint; reload it and try again");
+  }
+
+  return fingerprint;
+}
+
 export interface LoadCbzResponse {
   image_files: string[];
   comic_info: ComicInfo | null;
   error: ErrorResponse | null;
+  diagnostics?: ComicInfoParseDiagnostic[];
+  page_order_warning?: PageOrderWarning | null;
+  fingerprint?: ArchiveFingerprint | null;
 }
 
 export const isBookmarked = (
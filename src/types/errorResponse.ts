@@ -1,18 +1,78 @@
+import { ArchiveFingerprint, ArchiveFormat, ValidationIssue } from "./comic";
+
 export enum ErrorResponseType {
   FailedToLoadArchive = "FailedToLoadArchive",
   FailedToParseComicInfoXml = "FailedToParseComicInfoXml",
   ComicInfoXmlInvalid = "ComicInfoXmlInvalid",
+  ReadOnlyArchiveFormat = "ReadOnlyArchiveFormat",
+  Conflict = "Conflict",
+  NotFound = "NotFound",
+  PermissionDenied = "PermissionDenied",
+  UnsupportedFormat = "UnsupportedFormat",
+  ValidationFailed = "ValidationFailed",
+  WatcherFailure = "WatcherFailure",
   Other = "Other",
 }
 
+/**
+ * Machine-readable context of an error, tagged by `kind`.
+ */
+export type ErrorDetails =
+  | { kind: "path"; path: string }
+  | { kind: "entry"; file_name: string }
+  | { kind: "io"; error_kind: string }
+  | {
+      kind: "conflict";
+      expected: ArchiveFingerprint;
+      actual: ArchiveFingerprint;
+    }
+  | { kind: "format"; format: ArchiveFormat; convert_to: ArchiveFormat | null }
+  | { kind: "validation"; issues: ValidationIssue[] }
+  | { kind: "history_version"; version: number };
+
 export interface ErrorResponse {
   error_type: ErrorResponseType;
   message: string;
+  details?: ErrorDetails;
 }
 
 export const ErrorResponseTypeMap: Record<string, ErrorResponseType> = {
   FailedToLoadArchive: ErrorResponseType.FailedToLoadArchive,
   FailedToParseComicInfoXml: ErrorResponseType.FailedToParseComicInfoXml,
   ComicInfoXmlInvalid: ErrorResponseType.ComicInfoXmlInvalid,
+  ReadOnlyArchiveFormat: ErrorResponseType.ReadOnlyArchiveFormat,
+  Conflict: ErrorResponseType.Conflict,
+  NotFound: ErrorResponseType.NotFound,
+  PermissionDenied: ErrorResponseType.PermissionDenied,
+  UnsupportedFormat: ErrorResponseType.UnsupportedFormat,
+  ValidationFailed: ErrorResponseType.ValidationFailed,
+  WatcherFailure: ErrorResponseType.WatcherFailure,
   Other: ErrorResponseType.Other,
 };
+
+export function isErrorResponse(e: unknown): e is ErrorResponse {
+  return (
+    typeof e === "object" &&
+    e !== null &&
+    "error_type" in e &&
+    "message" in e
+  );
+}
+
+/**
+ * The message of an error thrown by a command, whether the command failed
+ * with an ErrorResponse or a plain string.
+ */
+export function errorMessage(e: unknown): string {
+  if (isErrorResponse(e)) return e.message;
+
+  return e instanceof Error ? e.message : String(e);
+}
+
+/**
+ * Whether a write was rejected because the archive changed on disk since it
+ * was loaded.
+ */
+export function isConflictError(e: unknown): boolean {
+  return isErrorResponse(e) && e.error_type === ErrorResponseType.Conflict;
+}